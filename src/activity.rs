@@ -0,0 +1,126 @@
+use crate::coords::OSCoords;
+use crate::profile;
+use crate::projection;
+use crate::reader::DataFile;
+use std::error::Error;
+
+/***********************************************************************
+    Code for correcting the altitudes recorded by GPS & sports watches
+    with the terrain elevations of their positions
+
+    Barometric altimeters drift with the weather, and GPS altitudes are
+    noisy, so the total ascent of an activity is often well out. Each
+    track point with a position in Great Britain gets the terrain
+    elevation, optionally blended with the recorded altitude, and the
+    altitudes can then be smoothed with a moving average. Points without a
+    position, or outside the grid or in a sea block without data, keep
+    their recorded altitude.
+
+    The FIT & TCX readers and writers (fit.rs & tcx.rs) use this to
+    correct the altitudes in their files.
+************************************************************************/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CorrectionOptions {
+    pub blend: f64, // Weight of the terrain elevation: 1 replaces the recorded altitude, 0 keeps it
+    pub smooth: usize, // Points in the moving average window, or 0 for no smoothing
+}
+
+impl Default for CorrectionOptions {
+    fn default() -> Self {
+        CorrectionOptions { blend: 1.0, smooth: 0 }
+    }
+}
+
+// A point of an activity's track
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackPoint {
+    pub position: Option<(f64, f64)>, // WGS84 latitude & longitude
+    pub altitude: Option<f64>, // As recorded, in metres
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CorrectionSummary {
+    pub points: usize, // Track points read
+    pub corrected: usize, // Points given a terrain elevation
+    pub recorded_ascent: f64, // Total ascent of the recorded altitudes
+    pub ascent: f64, // Total ascent of the corrected altitudes
+    pub descent: f64,
+}
+
+pub struct Correction {
+    pub altitudes: Vec<Option<f64>>, // None where there's neither a terrain elevation nor a recorded altitude
+    pub summary: CorrectionSummary,
+}
+
+// Gets the corrected altitude of each track point and a summary of the changes
+pub fn correct_altitudes(data: &mut DataFile, points: &[TrackPoint], options: &CorrectionOptions) -> Result<Correction, Box<dyn Error>> {
+    if !(0.0..=1.0).contains(&options.blend) {
+        return Err("The blend must be between 0 and 1".into());
+    }
+
+    // Look up the points with data together, so each data block is read once
+    let mut inside = Vec::new();
+    let mut locations = Vec::new();
+    for (i, point) in points.iter().enumerate() {
+        if let Some((lat, lon)) = point.position {
+            if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+                return Err(format!("Track point {} has an invalid latitude & longitude {},{}", i + 1, lat, lon).into());
+            }
+            let (easting, northing) = projection::wgs84_to_os(lat, lon);
+            let (easting, northing) = (easting.round() as i64, northing.round() as i64);
+            if data.data_block_address(easting, northing)?.is_some() {
+                inside.push(i);
+                locations.push(OSCoords { easting, northing, elevation: None });
+            }
+        }
+    }
+    let mut terrain = vec![None; points.len()];
+    for (i, coord) in inside.iter().zip(data.read_elevations_batch(&locations)?) {
+        terrain[*i] = coord.elevation.map(|elevation| elevation as f64);
+    }
+
+    let mut altitudes: Vec<Option<f64>> = points
+        .iter()
+        .zip(&terrain)
+        .map(|(point, terrain)| match (terrain, point.altitude) {
+            (Some(terrain), Some(recorded)) => Some(options.blend * terrain + (1.0 - options.blend) * recorded),
+            (Some(terrain), None) => Some(*terrain),
+            (None, recorded) => recorded,
+        })
+        .collect();
+    if options.smooth > 1 {
+        smooth(&mut altitudes, options.smooth);
+    }
+
+    let corrected: Vec<f64> = altitudes.iter().flatten().copied().collect();
+    let summary = CorrectionSummary {
+        points: points.len(),
+        corrected: inside.len(),
+        recorded_ascent: profile::total_ascent(points.iter().filter_map(|point| point.altitude)),
+        ascent: profile::total_ascent(corrected.iter().copied()),
+        // The descent forwards is the ascent backwards
+        descent: profile::total_ascent(corrected.iter().rev().copied()),
+    };
+    Ok(Correction { altitudes, summary })
+}
+
+// Replaces each known altitude with the mean of those in a window centred on it
+fn smooth(altitudes: &mut [Option<f64>], window: usize) {
+    let known: Vec<f64> = altitudes.iter().flatten().copied().collect();
+    let half = window / 2;
+    let mut means = (0..known.len()).map(|i| {
+        let (first, last) = (i.saturating_sub(half), (i + half).min(known.len() - 1));
+        known[first..=last].iter().sum::<f64>() / (last - first + 1) as f64
+    });
+    for altitude in altitudes.iter_mut().flatten() {
+        *altitude = means.next().unwrap_or(*altitude);
+    }
+}
+
+#[test]
+fn moving_average() {
+    let mut altitudes = vec![Some(10.0), None, Some(20.0), Some(30.0), Some(10.0)];
+    smooth(&mut altitudes, 3);
+    assert_eq!(vec![Some(15.0), None, Some(20.0), Some(20.0), Some(20.0)], altitudes);
+}
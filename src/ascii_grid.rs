@@ -0,0 +1,83 @@
+use crate::os;
+use crate::raster::{self, BoundingBox};
+use crate::reader::DataFile;
+use std::io::{BufWriter, Write};
+use std::{error::Error, fs, path};
+
+/***********************************************************************
+    Code for writing regions of the OS binary file as an ESRI ASCII grid
+
+    The output uses the same layout as the OS data files: metadata lines
+    followed by space-separated data rows from N to S, so a 10km² region
+    written out here matches the source data file it was built from.
+************************************************************************/
+
+pub const NODATA_ASCII: i16 = -9999; // Default nodata value written to the grid
+
+pub fn write_ascii_grid(
+    data: &mut DataFile,
+    bbox: &BoundingBox,
+    output_file: &path::Path,
+    nodata: i16,
+) -> Result<(), Box<dyn Error>> {
+    let cells = bbox.snap_to_cells();
+    let (cols, rows) = cells.size_in_cells();
+    let file = fs::File::create(output_file)?;
+    let mut file_buffer = BufWriter::new(file);
+
+    // Write the metadata lines
+    let metadata = [
+        format!("ncols {}", cols),
+        format!("nrows {}", rows),
+        format!("xllcorner {}", cells.min_easting),
+        format!("yllcorner {}", cells.min_northing),
+        format!("cellsize {}", os::ELEVATION_DISTANCE),
+        format!("NODATA_value {}", nodata),
+    ];
+    for line in metadata {
+        file_buffer.write_all(line.as_bytes())?;
+        file_buffer.write_all(os::OS_NEW_LINE.as_bytes())?;
+    }
+
+    // Write the data rows a row of 10km² data blocks at a time
+    raster::extract_bands_x10(data, &cells, os::ELEVATIONS_PER_ROW, |grid| {
+        for row in 0..grid.rows {
+            let elevations: Vec<String> = (0..grid.cols)
+                .map(|col| {
+                    if grid.is_nodata(col, row) {
+                        nodata.to_string()
+                    } else {
+                        format_x10(grid.get(col, row))
+                    }
+                })
+                .collect();
+            file_buffer.write_all(elevations.join(os::OS_DATA_SEPARATOR).as_bytes())?;
+            file_buffer.write_all(os::OS_NEW_LINE.as_bytes())?;
+        }
+        Ok(())
+    })?;
+    file_buffer.flush()?;
+    Ok(())
+}
+
+// Restores the decimal elevation from a x10 value the way it appears
+// in the OS data files, i.e. with either no decimal place or one
+pub fn format_x10(elev_x10: i16) -> String {
+    let sign = if elev_x10 < 0 { "-" } else { "" };
+    let abs_x10 = (elev_x10 as i32).abs();
+    if abs_x10 % 10 == 0 {
+        format!("{}{}", sign, abs_x10 / 10)
+    } else {
+        format!("{}{}.{}", sign, abs_x10 / 10, abs_x10 % 10)
+    }
+}
+
+#[test]
+fn format_x10_as_os_decimals() {
+    assert_eq!("12", format_x10(120));
+    assert_eq!("12.7", format_x10(127));
+    assert_eq!("0.5", format_x10(5));
+    assert_eq!("-0.5", format_x10(-5));
+    assert_eq!("-1.5", format_x10(-15));
+    assert_eq!("0", format_x10(0));
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+/***********************************************************************
+    An in-memory cache for DataFile of the header's data block addresses
+    and the most recently used data blocks
+
+    Lookups along a route fall in the same few 10km² data blocks again and
+    again, so with the cache each block is read from disk in one go the
+    first time it's needed and its elevations then come from memory. The
+    header section (36KB) is read & kept whole. Blocks are 80KB each and
+    the least recently used block is dropped when adding another would go
+    over the memory budget.
+************************************************************************/
+
+pub const BLOCK_BYTES: usize = 80_000; // Memory used by a cached 10km² data block
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64, // Data block lookups found in the cache
+    pub misses: u64, // Data block lookups read from disk
+    pub blocks: usize, // Data blocks held now
+    pub bytes: usize, // Memory used by the blocks held now
+}
+
+pub struct BlockCache {
+    header: Vec<u8>, // The file up to the end of the header section
+    blocks: HashMap<u64, (Vec<i16>, u64)>, // Elevations & last use of each block by its address
+    max_blocks: usize,
+    uses: u64, // Counts the lookups to order the blocks by last use
+    hits: u64,
+    misses: u64,
+}
+
+impl BlockCache {
+    pub fn new(header: Vec<u8>, budget_bytes: usize) -> BlockCache {
+        BlockCache {
+            header,
+            blocks: HashMap::new(),
+            max_blocks: budget_bytes / BLOCK_BYTES,
+            uses: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    // Checks whether the budget allows any data blocks to be cached
+    pub fn holds_blocks(&self) -> bool {
+        self.max_blocks > 0
+    }
+
+    // Gets the data block address stored at a header offset (0 for sea areas)
+    pub fn address_at(&self, offset: u64) -> Option<u32> {
+        let offset = offset as usize;
+        let bytes = self.header.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // Gets the elevations of a data block if it's cached, counting a hit or a miss
+    pub fn get(&mut self, address: u64) -> Option<&[i16]> {
+        self.uses += 1;
+        match self.blocks.get_mut(&address) {
+            Some((block, last_use)) => {
+                *last_use = self.uses;
+                self.hits += 1;
+                Some(block)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    // Adds a data block's elevations, dropping the least recently used block if the cache is full
+    pub fn insert(&mut self, address: u64, block: Vec<i16>) {
+        if self.max_blocks == 0 {
+            return;
+        }
+        if self.blocks.len() >= self.max_blocks && !self.blocks.contains_key(&address) {
+            let oldest = self.blocks.iter().min_by_key(|(_, (_, last_use))| *last_use).map(|(&address, _)| address);
+            if let Some(oldest) = oldest {
+                self.blocks.remove(&oldest);
+            }
+        }
+        self.blocks.insert(address, (block, self.uses));
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            blocks: self.blocks.len(),
+            bytes: self.blocks.len() * BLOCK_BYTES,
+        }
+    }
+}
+
+#[test]
+fn least_recently_used_block_is_dropped() {
+    let mut cache = BlockCache::new(Vec::new(), 2 * BLOCK_BYTES);
+    cache.insert(100, vec![1]);
+    cache.insert(200, vec![2]);
+    assert!(cache.get(100).is_some());
+    cache.insert(300, vec![3]);
+    assert!(cache.get(200).is_none());
+    assert_eq!(Some(&[1][..]), cache.get(100));
+    assert_eq!(Some(&[3][..]), cache.get(300));
+    assert_eq!(CacheStats { hits: 3, misses: 1, blocks: 2, bytes: 2 * BLOCK_BYTES }, cache.stats());
+}
@@ -0,0 +1,156 @@
+use crate::coords::OSCoords;
+use crate::hydrology;
+use crate::os;
+use crate::polygonize;
+use crate::raster::{self, BoundingBox, Raster};
+use crate::reader::DataFile;
+use crate::{geojson, reader};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+
+/***********************************************************************
+    Code for finding the catchment which drains to a pour point
+
+    Flow directions are worked out (as for streams) over a window of 10km²
+    data blocks around the pour point, starting with the pour point's block
+    and the blocks around it. The catchment is then grown upstream from the
+    pour point, adding every cell which drains into a cell already in it.
+
+    If the catchment reaches an edge of the window it may carry on beyond
+    it, so the window is widened on that side and the flow worked out again.
+    Blocks are read from the binary file as the window first reaches them
+    and are kept for the later passes, so a large catchment only reads the
+    blocks it spans plus a border around them.
+************************************************************************/
+
+pub const DEFAULT_SNAP_CELLS: usize = 3; // Search up to 150m for the stream nearest a pour point
+
+pub struct Catchment {
+    pub pour_point: (f64, f64), // Centre of the cell the catchment drains to
+    pub mask: Raster<u8>, // 1 in the catchment, 0 elsewhere
+    pub area: f64, // In km²
+    pub blocks_read: usize,
+}
+
+// Delineates the catchment draining to a pour point. The pour point is first moved
+// to the cell within snap_cells cells of it which has the greatest flow accumulation,
+// as a grid reference given for a river may miss the 50m cells the river follows.
+pub fn delineate(data: &mut DataFile, pour_point: OSCoords, snap_cells: usize) -> Result<Catchment, Box<dyn Error>> {
+    if !reader::in_grid(pour_point.easting, pour_point.northing) {
+        return Err("The pour point is outside the OS grid".into());
+    }
+    let block_size = os::METRES_IN_10_GRID;
+    let gb_blocks_e = os::GRIDS_PER_ROW_100 * os::METRES_IN_100_GRID / block_size;
+    let gb_blocks_n = os::GRIDS_PER_COL_100 * os::METRES_IN_100_GRID / block_size;
+
+    // The window as the first & last block columns and rows
+    let (block_e, block_n) = (pour_point.easting / block_size, pour_point.northing / block_size);
+    let mut window = [
+        (block_e - 1).max(0),
+        (block_n - 1).max(0),
+        (block_e + 1).min(gb_blocks_e - 1),
+        (block_n + 1).min(gb_blocks_n - 1),
+    ];
+    let mut blocks: HashMap<(i64, i64), Option<Vec<i16>>> = HashMap::new();
+
+    loop {
+        let bbox = BoundingBox::new(
+            window[0] * block_size,
+            window[1] * block_size,
+            (window[2] + 1) * block_size,
+            (window[3] + 1) * block_size,
+        )?;
+        let grid = raster::extract_x10_from_blocks(&bbox, raster::NODATA_X10, |easting, northing| {
+            if let Some(block) = blocks.get(&(easting, northing)) {
+                return Ok(block.clone());
+            }
+            let block = match data.data_block_address(easting, northing)? {
+                Some(address) => Some(data.read_data_block(address)?),
+                None => None,
+            };
+            blocks.insert((easting, northing), block.clone());
+            Ok(block)
+        })?;
+
+        let directions = hydrology::flow_directions(&hydrology::fill_depressions(&grid));
+        let accumulation = hydrology::flow_accumulation(&directions);
+        let outlet = snap_to_stream(&accumulation, pour_point, snap_cells)?;
+        let mask = hydrology::upstream_mask(&directions, outlet);
+
+        // Widen the window on any side the catchment reaches, unless it's the edge of the OS grid.
+        // Each side grows by half the window's size so that large catchments need few passes.
+        let touches = catchment_edges(&mask);
+        let (width, height) = (window[2] - window[0] + 1, window[3] - window[1] + 1);
+        let grown = [
+            if touches[0] { (window[0] - (width / 2).max(1)).max(0) } else { window[0] },
+            if touches[1] { (window[1] - (height / 2).max(1)).max(0) } else { window[1] },
+            if touches[2] { (window[2] + (width / 2).max(1)).min(gb_blocks_e - 1) } else { window[2] },
+            if touches[3] { (window[3] + (height / 2).max(1)).min(gb_blocks_n - 1) } else { window[3] },
+        ];
+        if grown == window {
+            let cell_size = mask.cell_size as f64;
+            let cell_area = cell_size * cell_size / 1_000_000.0;
+            return Ok(Catchment {
+                pour_point: (
+                    mask.min_easting as f64 + ((outlet % mask.cols) as f64 + 0.5) * cell_size,
+                    mask.max_northing() as f64 - ((outlet / mask.cols) as f64 + 0.5) * cell_size,
+                ),
+                area: mask.values.iter().filter(|&&value| value == 1).count() as f64 * cell_area,
+                mask,
+                blocks_read: blocks.len(),
+            });
+        }
+        window = grown;
+    }
+}
+
+// Gets the index of the cell near a location which has the most cells draining through it
+fn snap_to_stream(accumulation: &Raster<u32>, location: OSCoords, snap_cells: usize) -> Result<usize, Box<dyn Error>> {
+    let (col, row) = accumulation
+        .cell_at(location.easting as f64, location.northing as f64)
+        .ok_or("The pour point is outside the catchment window")?;
+    let snap = snap_cells as isize;
+    let mut best = row * accumulation.cols + col;
+    for dr in -snap..=snap {
+        for dc in -snap..=snap {
+            let (c, r) = (col as isize + dc, row as isize + dr);
+            if c < 0 || r < 0 || c >= accumulation.cols as isize || r >= accumulation.rows as isize {
+                continue;
+            }
+            let index = r as usize * accumulation.cols + c as usize;
+            if accumulation.values[index] > accumulation.values[best] {
+                best = index;
+            }
+        }
+    }
+    if accumulation.values[best] == 0 {
+        return Err("The pour point is in the sea".into());
+    }
+    Ok(best)
+}
+
+// Checks whether a mask has any cells on its west, south, east & north edges
+fn catchment_edges(mask: &Raster<u8>) -> [bool; 4] {
+    let (cols, rows) = (mask.cols, mask.rows);
+    let marked = |col: usize, row: usize| mask.get(col, row) == 1;
+    [
+        (0..rows).any(|row| marked(0, row)),
+        (0..cols).any(|col| marked(col, rows - 1)),
+        (0..rows).any(|row| marked(cols - 1, row)),
+        (0..cols).any(|col| marked(col, 0)),
+    ]
+}
+
+// Converts a catchment to a GeoJSON feature collection with its outline and area
+pub fn to_geojson(catchment: &Catchment) -> Value {
+    let properties = json!({
+        "area_km2": (catchment.area * 100.0).round() / 100.0,
+        "pour_point": [catchment.pour_point.0, catchment.pour_point.1],
+    });
+    let features = polygonize::polygonize(&catchment.mask, |value| value == 1)
+        .iter()
+        .map(|rings| geojson::feature(geojson::polygon(rings), properties.clone()))
+        .collect();
+    geojson::feature_collection(features)
+}
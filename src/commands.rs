@@ -0,0 +1,820 @@
+use osterrain50::activity::CorrectionOptions;
+use osterrain50::ascii_grid;
+use osterrain50::catchment;
+use osterrain50::contours;
+use osterrain50::coords;
+use osterrain50::derivatives::{self, Derivative, Method};
+use osterrain50::drape::{self, Crs, DrapeOptions};
+use osterrain50::enrich::{self, Columns, EnrichOptions};
+use osterrain50::fit;
+use osterrain50::geojson;
+use osterrain50::geotiff::{self, GeoTiffOptions, SampleType};
+use osterrain50::hydrology;
+use osterrain50::igc::{self, AltitudeSource};
+use osterrain50::inundation;
+use osterrain50::kml;
+use osterrain50::line_of_sight::{self, SightOptions};
+use osterrain50::radio::{self, RadioOptions};
+use osterrain50::raster::{self, BoundingBox};
+use osterrain50::reader::DataFile;
+use osterrain50::render::{self, RenderOptions};
+use osterrain50::server::Server;
+use osterrain50::summits;
+use osterrain50::tcx;
+use osterrain50::terrain_tiles::{self, TerrainEncoding, TileOptions};
+use osterrain50::utils;
+use osterrain50::viewshed::{self, ViewshedOptions};
+use osterrain50::zonal;
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::str::FromStr;
+use std::{error::Error, fs, path, string};
+
+/***********************************************************************
+   Commands which read an existing binary data file
+************************************************************************/
+
+const EXTRACT_USAGE: &str = "extract <binary data file> <min E,min N,max E,max N> <output file> [--type i16|f32] [--nodata <value>]";
+const EXPORT_USAGE: &str = "export <binary data file> <min E,min N,max E,max N | 100km² grid | GB> <output file> --format geotiff|asc [--type f32|i16] [--nodata <value>] [--tiled] [--compress deflate]";
+const RENDER_USAGE: &str = "render <binary data file> <min E,min N,max E,max N | 100km² grid | GB> <output PNG file> [--azimuth <degrees>] [--altitude <degrees>] [--zfactor <value>] [--tint] [--sea <#rrggbb>] [--step <cells>]";
+const CONTOURS_USAGE: &str = "contours <binary data file> <min E,min N,max E,max N | 100km² grid | GB> <output GeoJSON file> --interval <metres> [--base <metres>]";
+const SLOPE_USAGE: &str = "slope <binary data file> <grid ref | min E,min N,max E,max N> [<output file> --derivative slope|slope-percent|aspect|plan-curvature|profile-curvature] [--method horn|zt]";
+const LOS_USAGE: &str = "los <binary data file> <observer grid ref | E,N> <target grid ref | E,N> [--observer-height <metres>] [--target-height <metres>] [--curvature] [--refraction <coefficient>]";
+const VIEWSHED_USAGE: &str = "viewshed <binary data file> <observer grid ref | E,N> <output .png | .tif | .geojson file> [--radius <metres>] [--observer-height <metres>] [--target-height <metres>] [--curvature] [--refraction <coefficient>]";
+const RADIO_USAGE: &str = "radio <binary data file> <transmitter grid ref | E,N> <receiver grid ref | E,N> --frequency <MHz> [--tx-height <metres>] [--rx-height <metres>] [--k <factor>] [--profile <output CSV file>]";
+const SUMMITS_USAGE: &str = "summits <binary data file> <min E,min N,max E,max N | 100km² grid | GB> [--min-prominence <metres>] [--csv <output file>]";
+const STREAMS_USAGE: &str = "streams <binary data file> <min E,min N,max E,max N | 100km² grid> <output GeoJSON file> [--threshold <km²>] [--directions <output GeoTIFF file>]";
+const CATCHMENT_USAGE: &str = "catchment <binary data file> <pour point grid ref | E,N> <output GeoJSON file> [--snap <cells>]";
+const FLOOD_USAGE: &str = "flood <binary data file> <min E,min N,max E,max N | 100km² grid | GB> <output GeoTIFF file> --level <metres>";
+const ZONAL_USAGE: &str = "zonal <binary data file> <input GeoJSON file> [--bin <metres>] [--output <GeoJSON file>]";
+const SERVE_USAGE: &str = "serve <binary data file> [--host <address>] [--port <port>]";
+const ENRICH_USAGE: &str = "enrich <binary data file> <input CSV file | -> [--output <CSV file>] [--ref <column> | --easting <column> --northing <column> | --lat <column> --lon <column>] [--slope]";
+const DRAPE_USAGE: &str = "drape <binary data file> <input GeoJSON file> <output GeoJSON file> [--densify] [--crs bng|wgs84]";
+const KML_USAGE: &str = "kml <binary data file> <input .kml | .kmz file> <output .kml | .kmz file>";
+const CORRECT_USAGE: &str = "correct <binary data file> <input .fit | .tcx file> <output file> [--blend <0 to 1>] [--smooth <points>]";
+const IGC_USAGE: &str = "igc <binary data file> <input .igc file> [--altitude gps|pressure] [--threshold <metres>] [--csv <output file>]";
+const TILES_USAGE: &str = "export <binary data file> <min E,min N,max E,max N | 100km² grid | GB> <output directory | .mbtiles file> --format terrain-rgb|terrarium [--minzoom <zoom>] [--maxzoom <zoom>]";
+
+// Runs the command named by the first arg, or returns None if there isn't one
+pub fn run(args: &[string::String]) -> Option<Result<(), Box<dyn Error>>> {
+    let command = args.get(1)?;
+    let params = Options::parse(&args[2..]);
+    match command.as_str() {
+        "extract" => Some(extract(&params)),
+        "export" => Some(export(&params)),
+        "render" => Some(render(&params)),
+        "contours" => Some(contours(&params)),
+        "slope" => Some(slope(&params)),
+        "los" => Some(los(&params)),
+        "viewshed" => Some(viewshed(&params)),
+        "radio" => Some(radio(&params)),
+        "summits" => Some(summits(&params)),
+        "streams" => Some(streams(&params)),
+        "catchment" => Some(catchment(&params)),
+        "flood" => Some(flood(&params)),
+        "zonal" => Some(zonal(&params)),
+        "serve" => Some(serve(&params)),
+        "enrich" => Some(enrich(&params)),
+        "drape" => Some(drape(&params)),
+        "kml" => Some(kml(&params)),
+        "correct" => Some(correct(&params)),
+        "igc" => Some(igc(&params)),
+        _ => None,
+    }
+}
+
+pub fn show_usage(app_name: &str) {
+    for usage in [EXTRACT_USAGE, EXPORT_USAGE, TILES_USAGE, RENDER_USAGE, CONTOURS_USAGE, SLOPE_USAGE, LOS_USAGE, VIEWSHED_USAGE, RADIO_USAGE, SUMMITS_USAGE, STREAMS_USAGE, CATCHMENT_USAGE, FLOOD_USAGE, ZONAL_USAGE, SERVE_USAGE, ENRICH_USAGE, DRAPE_USAGE, KML_USAGE, CORRECT_USAGE, IGC_USAGE] {
+        eprintln!("{} {}", app_name, usage);
+    }
+}
+
+// The options which are flags, so never take the next arg as their value
+const FLAGS: [&str; 5] = ["curvature", "densify", "slope", "tiled", "tint"];
+
+// Command args are positional values plus any "--name value" options.
+// Flags, and options with no following value, have an empty value.
+struct Options {
+    positional: Vec<String>,
+    named: HashMap<String, String>,
+}
+
+impl Options {
+    fn parse(args: &[string::String]) -> Options {
+        let mut options = Options {
+            positional: Vec::new(),
+            named: HashMap::new(),
+        };
+        let mut args = args.iter().peekable();
+        while let Some(arg) = args.next() {
+            if let Some(name) = arg.strip_prefix("--") {
+                let value = match args.peek() {
+                    Some(next) if !FLAGS.contains(&name) && !next.starts_with("--") => args.next().cloned().unwrap_or_default(),
+                    _ => String::new(),
+                };
+                options.named.insert(name.to_string(), value);
+            } else {
+                options.positional.push(arg.to_string());
+            }
+        }
+        options
+    }
+
+    fn positional(&self, index: usize, usage: &str) -> Result<&str, Box<dyn Error>> {
+        match self.positional.get(index) {
+            Some(value) => Ok(value),
+            None => Err(format!("Missing argument. Usage: {}", usage).into()),
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.named.get(name).map(|value| value.as_str())
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.named.contains_key(name)
+    }
+
+    fn get_or<T: FromStr>(&self, name: &str, default: T) -> Result<T, Box<dyn Error>> {
+        match self.get(name) {
+            Some(value) => value
+                .parse::<T>()
+                .map_err(|_| format!("Invalid value {:?} for --{}", value, name).into()),
+            None => Ok(default),
+        }
+    }
+}
+
+// Writes a bounding box as a raw little endian i16 (x10) or f32 (metres) grid
+fn extract(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, EXTRACT_USAGE)?)?;
+    let bbox = BoundingBox::parse(params.positional(1, EXTRACT_USAGE)?)?;
+    let output_file = params.positional(2, EXTRACT_USAGE)?;
+    let mut file_buffer = BufWriter::new(fs::File::create(output_file)?);
+
+    let (cols, rows, min_easting, min_northing) = match params.get("type").unwrap_or("i16") {
+        "i16" => {
+            let grid = raster::extract_x10(&mut data, &bbox, params.get_or("nodata", raster::NODATA_X10)?)?;
+            for value in &grid.values {
+                file_buffer.write_all(&value.to_le_bytes())?;
+            }
+            (grid.cols, grid.rows, grid.min_easting, grid.min_northing)
+        }
+        "f32" => {
+            let grid = raster::extract_metres(&mut data, &bbox, params.get_or("nodata", raster::NODATA_METRES)?)?;
+            for value in &grid.values {
+                file_buffer.write_all(&value.to_le_bytes())?;
+            }
+            (grid.cols, grid.rows, grid.min_easting, grid.min_northing)
+        }
+        other => return Err(format!("Unknown grid type {}. Use i16 or f32", other).into()),
+    };
+    file_buffer.flush()?;
+    println!(
+        "Grid of {} columns x {} rows with SW corner {}, {} written to {:?} (rows run N to S).",
+        cols, rows, min_easting, min_northing, output_file
+    );
+    Ok(())
+}
+
+// Writes a bounding box, 100km² grid or all of GB in a GIS file format
+fn export(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, EXPORT_USAGE)?)?;
+    let bbox = BoundingBox::for_region(params.positional(1, EXPORT_USAGE)?)?;
+    let output_file = path::Path::new(params.positional(2, EXPORT_USAGE)?);
+
+    match params.get("format") {
+        Some("geotiff") => {
+            let sample_type = match params.get("type").unwrap_or("f32") {
+                "f32" => SampleType::F32,
+                "i16" => SampleType::I16,
+                other => return Err(format!("Unknown sample type {}. Use f32 or i16", other).into()),
+            };
+            let default_nodata = match sample_type {
+                SampleType::F32 => raster::NODATA_METRES,
+                SampleType::I16 => raster::NODATA_X10 as f32,
+            };
+            let deflate = match params.get("compress") {
+                None | Some("none") => false,
+                Some("deflate") => true,
+                Some(other) => return Err(format!("Unknown compression {}. Use deflate or none", other).into()),
+            };
+            let options = GeoTiffOptions {
+                sample_type,
+                nodata: params.get_or("nodata", default_nodata)?,
+                tiled: params.has("tiled"),
+                deflate,
+            };
+            geotiff::write_geotiff(&mut data, &bbox, output_file, &options)?;
+        }
+        Some("asc") => {
+            let nodata = params.get_or("nodata", ascii_grid::NODATA_ASCII)?;
+            ascii_grid::write_ascii_grid(&mut data, &bbox, output_file, nodata)?;
+        }
+        Some(format @ ("terrain-rgb" | "terrarium")) => {
+            let options = TileOptions {
+                encoding: if format == "terrarium" {
+                    TerrainEncoding::Terrarium
+                } else {
+                    TerrainEncoding::Mapbox
+                },
+                min_zoom: params.get_or("minzoom", 0)?,
+                max_zoom: params.get_or("maxzoom", 11)?,
+            };
+            let tile_count = terrain_tiles::write_tiles(&mut data, &bbox, output_file, &options)?;
+            println!("Wrote {} tiles.", utils::format_int(tile_count as isize));
+        }
+        Some(other) => return Err(format!("Unknown export format {}", other).into()),
+        None => return Err(format!("Missing --format. Usage: {}", EXPORT_USAGE).into()),
+    }
+    println!("Exported {:?}.", output_file);
+    Ok(())
+}
+
+// Renders a hillshaded and optionally colour-tinted PNG of a region
+fn render(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, RENDER_USAGE)?)?;
+    let bbox = BoundingBox::for_region(params.positional(1, RENDER_USAGE)?)?;
+    let output_file = path::Path::new(params.positional(2, RENDER_USAGE)?);
+
+    let defaults = RenderOptions::default();
+    let options = RenderOptions {
+        azimuth: params.get_or("azimuth", defaults.azimuth)?,
+        altitude: params.get_or("altitude", defaults.altitude)?,
+        z_factor: params.get_or("zfactor", defaults.z_factor)?,
+        tint: params.has("tint"),
+        sea_colour: match params.get("sea") {
+            Some(colour) => render::parse_colour(colour)?,
+            None => defaults.sea_colour,
+        },
+        step: params.get_or("step", defaults.step)?,
+    };
+    let (width, height) = render::write_png(&mut data, &bbox, output_file, &options)?;
+    println!("Rendered {} x {} image {:?}.", width, height, output_file);
+    Ok(())
+}
+
+// Writes contour lines for a region as GeoJSON
+fn contours(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, CONTOURS_USAGE)?)?;
+    let bbox = BoundingBox::for_region(params.positional(1, CONTOURS_USAGE)?)?;
+    let output_file = path::Path::new(params.positional(2, CONTOURS_USAGE)?);
+    let interval: f64 = match params.get("interval") {
+        Some(_) => params.get_or("interval", 0.0)?,
+        None => return Err(format!("Missing --interval. Usage: {}", CONTOURS_USAGE).into()),
+    };
+
+    let grid = raster::extract_x10(&mut data, &bbox, raster::NODATA_X10)?;
+    let lines = contours::contours(&grid, interval, params.get_or("base", 0.0)?)?;
+    geojson::write_file(output_file, &contours::to_geojson(&lines))?;
+    println!("Wrote {} contour lines to {:?}.", utils::format_int(lines.len() as isize), output_file);
+    Ok(())
+}
+
+// Prints the slope, aspect & curvature at a point, or writes one of them for
+// every cell of a bounding box as a raw little endian f32 grid
+fn slope(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, SLOPE_USAGE)?)?;
+    let location = params.positional(1, SLOPE_USAGE)?;
+    let method = match params.get("method").unwrap_or("horn") {
+        "horn" => Method::Horn,
+        "zt" => Method::ZevenbergenThorne,
+        other => return Err(format!("Unknown method {}. Use horn or zt", other).into()),
+    };
+
+    if location.matches(',').count() != 3 {
+        let point = coords::parse_coord(location)?;
+        match derivatives::derivatives_at(&mut data, point.easting as f64, point.northing as f64, method)? {
+            Some(result) => {
+                println!("Slope: {:.2}° ({:.1}%)", result.slope_degrees, result.slope_percent);
+                match result.aspect {
+                    Some(aspect) => println!("Aspect: {:.1}°", aspect),
+                    None => println!("Aspect: flat"),
+                }
+                println!("Plan curvature: {:.6}/m", result.plan_curvature);
+                println!("Profile curvature: {:.6}/m", result.profile_curvature);
+            }
+            None => println!("{} is in the sea.", location),
+        }
+        return Ok(());
+    }
+
+    let bbox = BoundingBox::parse(location)?;
+    let output_file = params.positional(2, SLOPE_USAGE)?;
+    let derivative = match params.get("derivative").unwrap_or("slope") {
+        "slope" => Derivative::SlopeDegrees,
+        "slope-percent" => Derivative::SlopePercent,
+        "aspect" => Derivative::Aspect,
+        "plan-curvature" => Derivative::PlanCurvature,
+        "profile-curvature" => Derivative::ProfileCurvature,
+        other => return Err(format!("Unknown derivative {}", other).into()),
+    };
+    let grid = derivatives::extract_derivative(&mut data, &bbox, derivative, method, raster::NODATA_METRES)?;
+    let mut file_buffer = BufWriter::new(fs::File::create(output_file)?);
+    for value in &grid.values {
+        file_buffer.write_all(&value.to_le_bytes())?;
+    }
+    file_buffer.flush()?;
+    println!(
+        "Grid of {} columns x {} rows with SW corner {}, {} written to {:?} (rows run N to S).",
+        grid.cols, grid.rows, grid.min_easting, grid.min_northing, output_file
+    );
+    Ok(())
+}
+
+// Reports whether a target can be seen from an observer and what blocks the view
+fn los(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, LOS_USAGE)?)?;
+    let observer = coords::parse_coord(params.positional(1, LOS_USAGE)?)?;
+    let target = coords::parse_coord(params.positional(2, LOS_USAGE)?)?;
+
+    let result = line_of_sight::line_of_sight(&mut data, observer, target, &sight_options(params)?)?;
+    let distance = utils::format_int(result.distance.round() as isize);
+    match result.first_obstruction {
+        None => println!("The target is visible at {}m.", distance),
+        Some(obstruction) => println!(
+            "The target at {}m is hidden. The first obstruction is at {}, {} ({}m away), \
+             which is {:.1}m high and {:.1}m above the line of sight.",
+            distance,
+            obstruction.point.easting,
+            obstruction.point.northing,
+            utils::format_int(obstruction.point.distance.round() as isize),
+            obstruction.point.elevation,
+            obstruction.height_above_sight_line
+        ),
+    }
+    Ok(())
+}
+
+// Writes the area visible from an observer as a mask image or polygons
+fn viewshed(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, VIEWSHED_USAGE)?)?;
+    let observer = coords::parse_coord(params.positional(1, VIEWSHED_USAGE)?)?;
+    let output_file = path::Path::new(params.positional(2, VIEWSHED_USAGE)?);
+
+    let options = ViewshedOptions {
+        radius: params.get_or("radius", ViewshedOptions::default().radius)?,
+        sight: sight_options(params)?,
+    };
+    let result = viewshed::viewshed(&mut data, observer, &options)?;
+    let extension = output_file.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    match extension.to_lowercase().as_str() {
+        "png" => viewshed::write_png(&result, output_file)?,
+        "tif" | "tiff" => geotiff::write_geotiff_u8(&result.mask, output_file, true)?,
+        "geojson" | "json" => geojson::write_file(output_file, &viewshed::to_geojson(&result))?,
+        _ => return Err(format!("Unknown output type {:?}. Use .png, .tif or .geojson", output_file).into()),
+    }
+    println!("Visible area {:.2}km² written to {:?}.", result.visible_area, output_file);
+    Ok(())
+}
+
+// Gets the heights above ground and earth curvature options for visibility commands
+fn sight_options(params: &Options) -> Result<SightOptions, Box<dyn Error>> {
+    let defaults = SightOptions::default();
+    Ok(SightOptions {
+        observer_height: params.get_or("observer-height", defaults.observer_height)?,
+        target_height: params.get_or("target-height", defaults.target_height)?,
+        earth_curvature: params.has("curvature"),
+        refraction: params.get_or("refraction", defaults.refraction)?,
+    })
+}
+
+// Reports the terrain clearance of a radio path and optionally writes its profile as CSV
+fn radio(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, RADIO_USAGE)?)?;
+    let tx = coords::parse_coord(params.positional(1, RADIO_USAGE)?)?;
+    let rx = coords::parse_coord(params.positional(2, RADIO_USAGE)?)?;
+
+    let defaults = RadioOptions::default();
+    let options = RadioOptions {
+        frequency_mhz: match params.get("frequency") {
+            Some(_) => params.get_or("frequency", defaults.frequency_mhz)?,
+            None => return Err(format!("Missing --frequency. Usage: {}", RADIO_USAGE).into()),
+        },
+        tx_height: params.get_or("tx-height", defaults.tx_height)?,
+        rx_height: params.get_or("rx-height", defaults.rx_height)?,
+        k_factor: params.get_or("k", defaults.k_factor)?,
+    };
+    let path = radio::radio_path(&mut data, tx, rx, &options)?;
+
+    println!("Path length: {}m", utils::format_int(path.distance.round() as isize));
+    println!("Line of sight: {}", if path.line_of_sight { "yes" } else { "no" });
+    println!(
+        "First Fresnel zone {}60% clear",
+        if path.fresnel_clear { "" } else { "less than " }
+    );
+    if let Some(worst) = path.worst {
+        println!(
+            "Worst obstruction: {}, {} at {}m, {:.1}m clearance ({:.0}% of the first Fresnel zone)",
+            worst.ground.easting,
+            worst.ground.northing,
+            utils::format_int(worst.ground.distance.round() as isize),
+            worst.clearance,
+            worst.clearance_ratio() * 100.0
+        );
+    }
+    println!("Free space loss: {:.1}dB", path.free_space_loss);
+    println!("Diffraction loss: {:.1}dB", path.diffraction_loss);
+
+    if let Some(profile_file) = params.get("profile") {
+        let mut file_buffer = BufWriter::new(fs::File::create(profile_file)?);
+        writeln!(file_buffer, "distance,easting,northing,elevation,earth_bulge,beam_height,fresnel_radius,clearance")?;
+        for point in &path.points {
+            writeln!(
+                file_buffer,
+                "{:.1},{},{},{:.1},{:.2},{:.2},{:.2},{:.2}",
+                point.ground.distance,
+                point.ground.easting,
+                point.ground.northing,
+                point.ground.elevation,
+                point.earth_bulge,
+                point.beam_height,
+                point.fresnel_radius,
+                point.clearance
+            )?;
+        }
+        file_buffer.flush()?;
+        println!("Profile written to {:?}.", profile_file);
+    }
+    Ok(())
+}
+
+// Lists the summits in a region ranked by prominence, optionally as CSV
+fn summits(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, SUMMITS_USAGE)?)?;
+    let bbox = BoundingBox::for_region(params.positional(1, SUMMITS_USAGE)?)?;
+    let min_prominence = params.get_or("min-prominence", 150.0)?;
+
+    let grid = raster::extract_x10(&mut data, &bbox, raster::NODATA_X10)?;
+    let summits = summits::find_summits(&grid, min_prominence);
+    let grid_ref = |easting: f64, northing: f64| {
+        coords::to_grid_ref(easting as i64, northing as i64).unwrap_or_default()
+    };
+
+    let mut lines = vec!["rank,grid_ref,elevation,prominence,key_col_grid_ref,key_col_elevation".to_string()];
+    for (rank, summit) in summits.iter().enumerate() {
+        let (col_ref, col_elevation) = match summit.key_col {
+            Some((easting, northing, elevation)) => (grid_ref(easting, northing), format!("{:.1}", elevation)),
+            None => (String::new(), String::new()),
+        };
+        lines.push(format!(
+            "{},{},{:.1},{:.1},{},{}",
+            rank + 1,
+            grid_ref(summit.easting, summit.northing),
+            summit.elevation,
+            summit.prominence,
+            col_ref,
+            col_elevation
+        ));
+    }
+    match params.get("csv") {
+        Some(output_file) => {
+            fs::write(output_file, lines.join("\n") + "\n")?;
+            println!(
+                "Wrote {} summits with at least {}m prominence to {:?}.",
+                utils::format_int(summits.len() as isize),
+                min_prominence,
+                output_file
+            );
+        }
+        None => lines.iter().for_each(|line| println!("{}", line)),
+    }
+    Ok(())
+}
+
+// Writes the stream network of a region as GeoJSON and optionally its flow directions as a GeoTIFF
+fn streams(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, STREAMS_USAGE)?)?;
+    let bbox = BoundingBox::for_region(params.positional(1, STREAMS_USAGE)?)?;
+    let output_file = path::Path::new(params.positional(2, STREAMS_USAGE)?);
+    let threshold_km2: f64 = params.get_or("threshold", 1.0)?;
+
+    let grid = raster::extract_x10(&mut data, &bbox, raster::NODATA_X10)?;
+    let directions = hydrology::flow_directions(&hydrology::fill_depressions(&grid));
+    let accumulation = hydrology::flow_accumulation(&directions);
+    let cell_area = (grid.cell_size * grid.cell_size) as f64 / 1_000_000.0;
+    let threshold = (threshold_km2 / cell_area).ceil() as u32;
+    let streams = hydrology::extract_streams(&directions, &accumulation, threshold);
+    geojson::write_file(output_file, &hydrology::to_geojson(&streams))?;
+    println!("Wrote {} stream lines to {:?}.", utils::format_int(streams.len() as isize), output_file);
+
+    if let Some(directions_file) = params.get("directions") {
+        geotiff::write_geotiff_u8(&directions, path::Path::new(directions_file), true)?;
+        println!("Flow directions written to {:?}.", directions_file);
+    }
+    Ok(())
+}
+
+// Writes the catchment draining to a pour point as GeoJSON
+fn catchment(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, CATCHMENT_USAGE)?)?;
+    let pour_point = coords::parse_coord(params.positional(1, CATCHMENT_USAGE)?)?;
+    let output_file = path::Path::new(params.positional(2, CATCHMENT_USAGE)?);
+    let snap_cells = params.get_or("snap", catchment::DEFAULT_SNAP_CELLS)?;
+
+    let result = catchment::delineate(&mut data, pour_point, snap_cells)?;
+    geojson::write_file(output_file, &catchment::to_geojson(&result))?;
+    println!(
+        "Catchment of {:.2}km² draining to {} written to {:?} ({} data blocks read).",
+        result.area,
+        coords::to_grid_ref(result.pour_point.0 as i64, result.pour_point.1 as i64).unwrap_or_default(),
+        output_file,
+        result.blocks_read
+    );
+    Ok(())
+}
+
+// Writes a mask of the land flooded by the sea at a water level and lists the flooded area per 100km² grid
+fn flood(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, FLOOD_USAGE)?)?;
+    let bbox = BoundingBox::for_region(params.positional(1, FLOOD_USAGE)?)?;
+    let output_file = path::Path::new(params.positional(2, FLOOD_USAGE)?);
+    let water_level: f64 = match params.get("level") {
+        Some(_) => params.get_or("level", 0.0)?,
+        None => return Err(format!("Missing --level. Usage: {}", FLOOD_USAGE).into()),
+    };
+
+    let grid = raster::extract_x10(&mut data, &bbox, raster::NODATA_X10)?;
+    let mask = inundation::inundate(&grid, water_level);
+    geotiff::write_geotiff_u8(&mask, output_file, true)?;
+
+    let areas = inundation::flooded_area_by_grid(&mask);
+    println!("grid,flooded_km2");
+    for (grid, area) in &areas {
+        println!("{},{:.2}", grid, area);
+    }
+    let total: f64 = areas.iter().map(|(_, area)| area).sum();
+    println!("Flooded area {:.2}km² at {}m written to {:?}.", total, water_level, output_file);
+    Ok(())
+}
+
+// Prints the elevation statistics of each polygon feature in a GeoJSON file as CSV,
+// and optionally writes the features back out with the statistics added to their properties
+fn zonal(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, ZONAL_USAGE)?)?;
+    let input = geojson::read_file(path::Path::new(params.positional(1, ZONAL_USAGE)?))?;
+    let bin_width = params.get_or("bin", zonal::DEFAULT_BIN_WIDTH)?;
+
+    println!("feature,name,count,min,max,mean,std_dev");
+    let mut features = Vec::new();
+    for (i, feature) in geojson::features(&input)?.into_iter().enumerate() {
+        let polygons = geojson::polygons(&feature["geometry"]).map_err(|e| format!("Feature {}: {}", i + 1, e))?;
+        let stats = zonal::polygon_stats(&mut data, &polygons, bin_width)?;
+        let name = feature["properties"]["name"].as_str().unwrap_or_default();
+        let summary = match &stats {
+            Some(stats) => format!(
+                "{},{:.1},{:.1},{:.2},{:.2}",
+                stats.count, stats.min, stats.max, stats.mean, stats.std_dev
+            ),
+            None => "0,,,,".to_string(),
+        };
+        println!("{},{},{}", i + 1, utils::csv_field(name), summary);
+
+        let mut feature = feature.clone();
+        feature["properties"]["elevation_stats"] = match stats {
+            Some(stats) => json!({
+                "count": stats.count,
+                "min": stats.min,
+                "max": stats.max,
+                "mean": (stats.mean * 100.0).round() / 100.0,
+                "std_dev": (stats.std_dev * 100.0).round() / 100.0,
+                "histogram": stats.histogram,
+            }),
+            None => json!({ "count": 0 }),
+        };
+        features.push(feature);
+    }
+
+    if let Some(output_file) = params.get("output") {
+        geojson::write_file(path::Path::new(output_file), &geojson::feature_collection(features))?;
+        println!("Statistics written to {:?}.", output_file);
+    }
+    Ok(())
+}
+
+// Answers HTTP elevation queries until stopped
+fn serve(params: &Options) -> Result<(), Box<dyn Error>> {
+    let data_file = params.positional(0, SERVE_USAGE)?;
+    let host = params.get("host").unwrap_or("127.0.0.1");
+    let port: u16 = params.get_or("port", 8080)?;
+
+    let server = Server::bind(data_file, &format!("{}:{}", host, port))?;
+    println!("Serving elevations from {} at http://{}", data_file, server.local_addr()?);
+    server.run()
+}
+
+// Adds elevations (and optionally slopes) to the rows of a CSV file, writing to a file or stdout
+fn enrich(params: &Options) -> Result<(), Box<dyn Error>> {
+    const MAX_REPORTED: usize = 100; // Rows reported individually before just counting them
+
+    let mut data = DataFile::open(params.positional(0, ENRICH_USAGE)?)?;
+    let input_file = params.positional(1, ENRICH_USAGE)?;
+    let column = |name: &str| params.get(name).map(|column| column.to_string());
+    let columns = match (column("ref"), column("easting"), column("northing"), column("lat"), column("lon")) {
+        (Some(grid_ref), None, None, None, None) => Some(Columns::GridRef(grid_ref)),
+        (None, Some(easting), Some(northing), None, None) => Some(Columns::EastingNorthing(easting, northing)),
+        (None, None, None, Some(lat), Some(lon)) => Some(Columns::LatLon(lat, lon)),
+        (None, None, None, None, None) => None,
+        _ => return Err(format!("Give one set of location columns. Usage: {}", ENRICH_USAGE).into()),
+    };
+    let options = EnrichOptions {
+        columns,
+        slope: params.has("slope"),
+    };
+
+    let input: Box<dyn BufRead> = match input_file {
+        "-" => Box::new(io::stdin().lock()),
+        _ => Box::new(BufReader::new(fs::File::open(input_file)?)),
+    };
+    let output: Box<dyn Write> = match params.get("output") {
+        Some(output_file) => Box::new(BufWriter::new(fs::File::create(output_file)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    let report = |row: usize, reason: &str| {
+        if row <= MAX_REPORTED {
+            eprintln!("Row {}: {}", row, reason);
+        }
+    };
+    let summary = enrich::enrich_csv(&mut data, input, output, &options, report)?;
+
+    let message = format!(
+        "Enriched {} rows, {} of which could not be parsed.",
+        utils::format_int(summary.rows as isize),
+        utils::format_int(summary.failed as isize)
+    );
+    match params.get("output") {
+        Some(output_file) => println!("{} Written to {:?}.", message, output_file),
+        None => eprintln!("{}", message),
+    }
+    Ok(())
+}
+
+// Writes a copy of a GeoJSON file with elevations added to every position as Z coordinates
+fn drape(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, DRAPE_USAGE)?)?;
+    let input = geojson::read_file(path::Path::new(params.positional(1, DRAPE_USAGE)?))?;
+    let output_file = path::Path::new(params.positional(2, DRAPE_USAGE)?);
+    let crs = match params.get("crs") {
+        None => None,
+        Some("bng" | "27700") => Some(Crs::BritishNationalGrid),
+        Some("wgs84" | "4326") => Some(Crs::Wgs84),
+        Some(other) => return Err(format!("Unknown CRS {:?}. Use bng or wgs84", other).into()),
+    };
+    let options = DrapeOptions {
+        crs,
+        densify: params.has("densify"),
+    };
+
+    let (output, summary) = drape::drape(&mut data, &input, &options)?;
+    geojson::write_file(output_file, &output)?;
+    println!(
+        "Added elevations to {} positions ({} added by densifying, {} outside the grid left unchanged) in {} coordinates, written to {:?}.",
+        utils::format_int(summary.positions as isize),
+        utils::format_int(summary.added as isize),
+        utils::format_int(summary.outside as isize),
+        match summary.crs {
+            Crs::BritishNationalGrid => "British National Grid",
+            Crs::Wgs84 => "WGS84",
+        },
+        output_file
+    );
+    Ok(())
+}
+
+// Writes a copy of a KML or KMZ file with heights added to its Point & LineString Placemarks,
+// and prints the elevation profile of each Placemark
+fn kml(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, KML_USAGE)?)?;
+    let input_file = path::Path::new(params.positional(1, KML_USAGE)?);
+    let output_file = path::Path::new(params.positional(2, KML_USAGE)?);
+
+    let (output, placemarks) = kml::add_heights(&mut data, &kml::read_file(input_file)?)?;
+    kml::write_file(output_file, &output, input_file)?;
+
+    println!("placemark,name,positions,outside,distance_km,ascent,descent,min,max");
+    for (i, placemark) in placemarks.iter().enumerate() {
+        let profile = match &placemark.profile {
+            Some(profile) => format!(
+                "{:.2},{:.0},{:.0},{:.1},{:.1}",
+                profile.distance / 1000.0,
+                profile.ascent,
+                profile.descent,
+                profile.min,
+                profile.max
+            ),
+            None => ",,,,".to_string(),
+        };
+        println!("{},{},{},{},{}", i + 1, utils::csv_field(&placemark.name), placemark.positions, placemark.outside, profile);
+    }
+    println!("Heights added to {} Placemarks, written to {:?}.", placemarks.len(), output_file);
+    Ok(())
+}
+
+// Writes a copy of a FIT or TCX activity file with its altitudes corrected with the terrain
+// elevations, and prints the recomputed total ascent
+fn correct(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, CORRECT_USAGE)?)?;
+    let input_file = path::Path::new(params.positional(1, CORRECT_USAGE)?);
+    let output_file = path::Path::new(params.positional(2, CORRECT_USAGE)?);
+    let options = CorrectionOptions {
+        blend: params.get_or("blend", 1.0)?,
+        smooth: params.get_or("smooth", 0)?,
+    };
+
+    let extension = input_file.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+    let summary = match extension.to_ascii_lowercase().as_str() {
+        "fit" => {
+            let (output, summary) = fit::correct_fit(&mut data, &fs::read(input_file)?, &options)?;
+            fs::write(output_file, output)?;
+            summary
+        }
+        "tcx" => {
+            let (output, summary) = tcx::correct_tcx(&mut data, &fs::read_to_string(input_file)?, &options)?;
+            fs::write(output_file, output)?;
+            summary
+        }
+        _ => return Err(format!("Unknown activity file type {:?}. Use .fit or .tcx", input_file).into()),
+    };
+
+    println!(
+        "Corrected {} of {} track points. Total ascent {:.0}m (recorded {:.0}m), total descent {:.0}m, written to {:?}.",
+        utils::format_int(summary.corrected as isize),
+        utils::format_int(summary.points as isize),
+        summary.ascent,
+        summary.recorded_ascent,
+        summary.descent,
+        output_file
+    );
+    Ok(())
+}
+
+// Prints the least height above the terrain of an IGC flight log and the fixes below a
+// threshold, and optionally writes the height of every fix as CSV
+fn igc(params: &Options) -> Result<(), Box<dyn Error>> {
+    let mut data = DataFile::open(params.positional(0, IGC_USAGE)?)?;
+    let fixes = igc::parse_fixes(&fs::read_to_string(params.positional(1, IGC_USAGE)?)?)?;
+    let source = match params.get("altitude") {
+        None | Some("gps") => AltitudeSource::Gps,
+        Some("pressure") => AltitudeSource::Pressure,
+        Some(other) => return Err(format!("Unknown altitude {:?}. Use gps or pressure", other).into()),
+    };
+    let threshold = params.get_or("threshold", igc::DEFAULT_THRESHOLD)?;
+
+    let analysis = igc::analyse(&mut data, &fixes, source, threshold)?;
+    let grid_ref = |i: usize| {
+        let clearance = &analysis.clearances[i];
+        coords::to_grid_ref(clearance.easting, clearance.northing).unwrap_or_default()
+    };
+    let value = |value: Option<f64>| value.map(|value| format!("{:.1}", value)).unwrap_or_default();
+
+    println!("Read {} fixes.", utils::format_int(fixes.len() as isize));
+    match analysis.minimum {
+        Some(i) => println!(
+            "Least clearance in flight {}m at {} over {} ({}m terrain).",
+            value(analysis.clearances[i].agl),
+            fixes[i].time,
+            grid_ref(i),
+            value(analysis.clearances[i].terrain)
+        ),
+        None => println!("No fix in flight has a height above the terrain, as each is outside the grid, over the sea or without a 3D GPS fix."),
+    }
+    println!("{} fixes in flight were below {}m:", utils::format_int(analysis.below.len() as isize), threshold);
+    for &i in &analysis.below {
+        println!("  {} {} {}m", fixes[i].time, grid_ref(i), value(analysis.clearances[i].agl));
+    }
+
+    if let Some(output_file) = params.get("csv") {
+        let mut below = vec![false; fixes.len()];
+        for &i in &analysis.below {
+            below[i] = true;
+        }
+        let mut lines = vec!["time,lat,lon,grid_ref,altitude,terrain,agl,in_flight,below".to_string()];
+        for (i, (fix, clearance)) in fixes.iter().zip(&analysis.clearances).enumerate() {
+            lines.push(format!(
+                "{},{:.6},{:.6},{},{},{},{},{},{}",
+                fix.time,
+                fix.lat,
+                fix.lon,
+                grid_ref(i),
+                value(clearance.altitude),
+                value(clearance.terrain),
+                value(clearance.agl),
+                clearance.in_flight,
+                below[i]
+            ));
+        }
+        fs::write(output_file, lines.join("\n") + "\n")?;
+        println!("Heights above the terrain written to {:?}.", output_file);
+    }
+    Ok(())
+}
+
+#[test]
+fn flags_take_no_value() {
+    let args: Vec<String> = ["data.bin", "--tiled", "SU", "out.tif", "--compress", "deflate", "--tint"].iter().map(|arg| arg.to_string()).collect();
+    let params = Options::parse(&args);
+    assert_eq!(vec!["data.bin", "SU", "out.tif"], params.positional);
+    assert!(params.has("tiled") && params.has("tint"));
+    assert_eq!(Some("deflate"), params.get("compress"));
+}
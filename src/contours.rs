@@ -0,0 +1,198 @@
+use crate::geojson;
+use crate::raster::Raster;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+
+/***********************************************************************
+    Code for generating contour lines from a raster using marching squares
+
+    Each square has the centres of four neighbouring cells as its corners.
+    Where a contour level passes between two corners, the crossing point is
+    interpolated along that edge of the square. The segments found in each
+    square are then joined into lines through the edges they share, so lines
+    run unbroken across the 10km² data block boundaries of the binary file.
+    Squares with a corner in the sea (nodata) are skipped, so lines stop
+    at the coast.
+************************************************************************/
+
+#[derive(Debug, Clone)]
+pub struct Contour {
+    pub elevation: f64,
+    pub points: Vec<(f64, f64)>, // Eastings & northings. Closed lines end at their start point.
+}
+
+// Identifies the edge of a square between two cell centres: the cell at its
+// west or north end and whether the edge runs east (horizontal) or south.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Edge {
+    col: usize,
+    row: usize,
+    horizontal: bool,
+}
+
+// Generates contours at every multiple of the interval (offset by the base)
+// within the range of elevations in a raster of x10 values
+pub fn contours(grid: &Raster<i16>, interval: f64, base: f64) -> Result<Vec<Contour>, Box<dyn Error>> {
+    if interval <= 0.0 {
+        return Err("The contour interval must be greater than 0".into());
+    }
+    let land: Vec<i16> = grid.values.iter().copied().filter(|&v| v != grid.nodata).collect();
+    let (min, max) = match (land.iter().min(), land.iter().max()) {
+        (Some(&min), Some(&max)) => (min as f64 / 10.0, max as f64 / 10.0),
+        _ => return Ok(Vec::new()),
+    };
+
+    let mut all_contours = Vec::new();
+    let first = ((min - base) / interval).ceil() as i64;
+    let last = ((max - base) / interval).floor() as i64;
+    for step in first..=last {
+        all_contours.extend(contours_at(grid, step as f64 * interval + base));
+    }
+    Ok(all_contours)
+}
+
+// Generates the contours for a single level
+pub fn contours_at(grid: &Raster<i16>, level: f64) -> Vec<Contour> {
+    let mut segments: Vec<(Edge, Edge)> = Vec::new();
+    let value = |col: usize, row: usize| grid.get(col, row) as f64 / 10.0;
+
+    for row in 0..grid.rows.saturating_sub(1) {
+        for col in 0..grid.cols.saturating_sub(1) {
+            let corners = [(col, row), (col + 1, row), (col + 1, row + 1), (col, row + 1)];
+            if corners.iter().any(|&(c, r)| grid.is_nodata(c, r)) {
+                continue;
+            }
+            let [tl, tr, br, bl] = corners.map(|(c, r)| value(c, r));
+            let case = (tl >= level) as u8 * 8 + (tr >= level) as u8 * 4 + (br >= level) as u8 * 2 + (bl >= level) as u8;
+
+            let top = Edge { col, row, horizontal: true };
+            let bottom = Edge { col, row: row + 1, horizontal: true };
+            let left = Edge { col, row, horizontal: false };
+            let right = Edge { col: col + 1, row, horizontal: false };
+            let centre_above = (tl + tr + br + bl) / 4.0 >= level;
+
+            match case {
+                1 | 14 => segments.push((left, bottom)),
+                2 | 13 => segments.push((bottom, right)),
+                3 | 12 => segments.push((left, right)),
+                4 | 11 => segments.push((top, right)),
+                6 | 9 => segments.push((top, bottom)),
+                7 | 8 => segments.push((left, top)),
+                // Saddles are resolved by the average of the corners
+                5 if centre_above => segments.extend([(left, top), (bottom, right)]),
+                5 => segments.extend([(top, right), (left, bottom)]),
+                10 if centre_above => segments.extend([(top, right), (left, bottom)]),
+                10 => segments.extend([(left, top), (bottom, right)]),
+                _ => {}
+            }
+        }
+    }
+
+    join_segments(&segments)
+        .into_iter()
+        .map(|edges| Contour {
+            elevation: level,
+            points: edges.iter().map(|edge| crossing_point(grid, edge, level)).collect(),
+        })
+        .collect()
+}
+
+// Joins segments which share an edge into lines of edges
+fn join_segments(segments: &[(Edge, Edge)]) -> Vec<Vec<Edge>> {
+    // Each edge is shared by at most two squares, so by at most two segments
+    let mut by_edge: HashMap<Edge, Vec<usize>> = HashMap::new();
+    for (i, (a, b)) in segments.iter().enumerate() {
+        by_edge.entry(*a).or_default().push(i);
+        by_edge.entry(*b).or_default().push(i);
+    }
+    let mut used = vec![false; segments.len()];
+
+    // Follows unused segments on from an edge, adding their far edges to the line
+    let extend = |line: &mut Vec<Edge>, used: &mut Vec<bool>| {
+        while let Some(&next) = by_edge[line.last().unwrap()].iter().find(|&&i| !used[i]) {
+            used[next] = true;
+            let (a, b) = segments[next];
+            line.push(if a == *line.last().unwrap() { b } else { a });
+        }
+    };
+
+    let mut lines = Vec::new();
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let mut line = vec![segments[start].0, segments[start].1];
+        extend(&mut line, &mut used);
+        // Then go back the other way from the start for lines which aren't closed
+        line.reverse();
+        extend(&mut line, &mut used);
+        lines.push(line);
+    }
+    lines
+}
+
+// Interpolates where a contour level crosses an edge between two cell centres
+fn crossing_point(grid: &Raster<i16>, edge: &Edge, level: f64) -> (f64, f64) {
+    let (col2, row2) = if edge.horizontal { (edge.col + 1, edge.row) } else { (edge.col, edge.row + 1) };
+    let (v1, v2) = (grid.get(edge.col, edge.row) as f64 / 10.0, grid.get(col2, row2) as f64 / 10.0);
+    let t = if v1 == v2 { 0.5 } else { ((level - v1) / (v2 - v1)).clamp(0.0, 1.0) };
+
+    let cell_size = grid.cell_size as f64;
+    let easting = grid.min_easting as f64 + (edge.col as f64 + 0.5) * cell_size;
+    let northing = grid.max_northing() as f64 - (edge.row as f64 + 0.5) * cell_size;
+    if edge.horizontal {
+        (easting + t * cell_size, northing)
+    } else {
+        (easting, northing - t * cell_size)
+    }
+}
+
+// Converts contours to a GeoJSON feature collection of LineStrings with an elevation property
+pub fn to_geojson(contours: &[Contour]) -> Value {
+    let features = contours
+        .iter()
+        .map(|contour| geojson::feature(geojson::line_string(&contour.points), json!({ "elevation": contour.elevation })))
+        .collect();
+    geojson::feature_collection(features)
+}
+
+#[cfg(test)]
+fn test_grid(values: &[i16], cols: usize) -> Raster<i16> {
+    use crate::raster::{self, BoundingBox};
+    let rows = values.len() / cols;
+    let bbox = BoundingBox::new(0, 0, cols as i64 * 50, rows as i64 * 50).unwrap();
+    let mut grid = Raster::new(&bbox, raster::NODATA_X10);
+    grid.values.copy_from_slice(values);
+    grid
+}
+
+#[test]
+fn closed_contour_around_a_peak() {
+    let grid = test_grid(&[0, 0, 0, 0, 200, 0, 0, 0, 0], 3);
+    let lines = contours_at(&grid, 10.0);
+    assert_eq!(1, lines.len());
+    let points = &lines[0].points;
+    assert_eq!(5, points.len());
+    assert_eq!(points[0], points[4]);
+    // Halfway between the peak and its west neighbour
+    assert!(points.contains(&(50.0, 75.0)));
+}
+
+#[test]
+fn open_contour_across_a_slope() {
+    let grid = test_grid(&[0, 100, 200, 0, 100, 200, 0, 100, 200], 3);
+    let lines = contours(&grid, 5.0, 0.0).unwrap();
+    // Levels 0, 5, 10, 15 & 20 with 0 and 20 touching the edges
+    let at_15: Vec<&Contour> = lines.iter().filter(|c| c.elevation == 15.0).collect();
+    assert_eq!(1, at_15.len());
+    assert_eq!(vec![(100.0, 25.0), (100.0, 75.0), (100.0, 125.0)], sorted(&at_15[0].points));
+}
+
+#[cfg(test)]
+fn sorted(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    points
+}
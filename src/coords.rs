@@ -0,0 +1,335 @@
+use crate::os;
+use std::collections::HashMap;
+use std::error::Error;
+
+/*
+   OS eastings & northings (eastings always precede northings) can be expressed either
+   as pairs of digits, or pairs of digits preceded by a two-character grid ref.
+   E.g.: 485669,092167 or SZ 85669 92167.
+
+   See: https://en.wikipedia.org/wiki/Ordnance_Survey_National_Grid for grid details.
+
+   A grid ref reduces the number coords required. Where the grid ref is omitted,
+   the numeric values must have their origin at the south west corner of the full GB grid.
+
+   Alphanumeric coordinates
+    • may be separated by spaces but not always, e.g. SZ8554592142
+    • should have the same number of digits in the eastings & northings
+
+   Alphanumeric coordinates may be shortened to reduce accuracy:
+   SZ 85545 92142   10 digit pair accurate to    1 m
+   SZ 8554 9214      8 digit pair accurate to   10 m
+   SZ 855 921        6 digit pair accurate to  100 m
+   SZ 85 92          4 digit pair accurate to    1 km
+   SX 8 9            2 digit pair accurate to   10 km
+   SZ                0 digit pair accurate to  100 km
+
+   Numeric coordinates
+    • may be separated by a space, a comma, or both
+    • pairs usually contain the same number of digits but may contain different
+      numbers of digits in Orkney & Shetland where northings extend beyond 999999
+      to require 7 digits, e.g. Ronas Hill (Shetland): 430530,1183500 (HU 30530 83500)
+*/
+
+#[derive(Debug, Clone, Copy)]
+pub struct OSCoords {
+    pub easting: i64,
+    pub northing: i64,
+    pub elevation: Option<f32>,
+}
+
+pub fn get_infills(coord_start: OSCoords, coord_end: OSCoords, include_start: bool) -> Vec<OSCoords> {
+    /*
+       Creates infill locations approx. 50m apart between the two parameter locations
+
+       The include_start parameter controls whether the start is included in the output
+       in order to avoid double insertions when later merging infilled locations
+
+       Example: for 4 locations requiring infills:
+       1---2               get_infills(1, 2, true)  returns 1st, infills & 2nd location
+           ---3            get_infills(2, 3, false) returns infills & 3rd location
+               ---4        get_infills(3, 4, false) returns infills & 4th location
+        so merging the three results contains all 4 locations and no duplicates
+
+       Example: for 2 locations where start and end are 200m apart:
+                               • end
+                           •   |
+        diagonal_diff  •       |  northing_diff
+                   •           |
+         start •_______________|
+                easting_diff
+
+        • 3 infill coords are required
+        • 5 coords are returned if include_start = true
+    */
+
+    // Build the output vec
+    let mut coords: Vec<OSCoords> = Vec::new();
+
+    if include_start {
+        coords.push(coord_start);
+    }
+
+    // NB: work in floats for cumulative calcs to avoid rounding
+    // inaccuracies which become noticeable over long distances
+
+    // Get the diagonal difference between the start and end coords
+    let easting_diff = coord_end.easting - coord_start.easting;
+    let northing_diff = coord_end.northing - coord_start.northing;
+    let diagonal_diff =
+        ((easting_diff * easting_diff) as f64 + (northing_diff * northing_diff) as f64).sqrt();
+
+    // Only create infills where the two locations are greater than 50m apart
+    if diagonal_diff > os::ELEVATION_DISTANCE as f64 {
+        // Get the infill easting & northing deltas
+        // as a proportion of the infill diagonal diff
+        let infill_diag_diff = diagonal_diff / os::ELEVATION_DISTANCE as f64;
+        let delta_east = easting_diff as f64 / infill_diag_diff;
+        let delta_north = northing_diff as f64 / infill_diag_diff;
+
+        // Prepare an object to hold the generated infill location
+        let mut infill_coords = OSCoords {
+            easting: 0,
+            northing: 0,
+            elevation: None,
+        };
+
+        // Cumulatively add the delta_east & delta_north diffs
+        // to create the required number of infill coords
+
+        // Begin with the start location
+        let mut cumulative_east = coord_start.easting as f64;
+        let mut cumulative_north = coord_start.northing as f64;
+
+        // Get the number of infills required
+        let infills_required = infill_diag_diff.ceil() as i64 - 1;
+
+        // Create the infill locations
+        for _ in 0..infills_required {
+            cumulative_east += delta_east;
+            cumulative_north += delta_north;
+
+            // Store the infill location rounded to integer values
+            infill_coords.easting = cumulative_east.round() as i64;
+            infill_coords.northing = cumulative_north.round() as i64;
+            coords.push(infill_coords);
+        }
+    }
+    coords.push(coord_end);
+    coords
+}
+
+pub fn parse_coords(input: &[&str]) -> Vec<OSCoords> {
+    // Converts variously-styled input coordinates
+    // to full grid origin coordinate pairs.
+    // Panics on any invalid coordinates - use parse_coord to handle them instead.
+
+    let mut clean_coords: Vec<OSCoords> = Vec::new();
+    for coord in input {
+        match parse_coord_or_grid(coord) {
+            Ok(Some(coords)) => clean_coords.push(coords),
+            // A bare 100km² grid identifier is skipped
+            Ok(None) => {}
+            Err(e) => panic!("{}", e),
+        }
+    }
+    clean_coords
+}
+
+pub fn parse_coord(coord: &str) -> Result<OSCoords, Box<dyn Error>> {
+    // Converts a single variously-styled input coordinate to a full grid origin
+    // coordinate pair, or returns an error saying why it's invalid
+
+    parse_coord_or_grid(coord)?.ok_or_else(|| format!("{} has no eastings or northings", coord).into())
+}
+
+fn parse_coord_or_grid(coord: &str) -> Result<Option<OSCoords>, Box<dyn Error>> {
+    // Parses a coordinate, returning None for a bare 100km² grid identifier
+
+    // Conversion multipliers for 500 Km² grid as [e, n]
+    let grid_500: HashMap<char, [i64; 2]> = [
+        ('S', [0, 0]),
+        ('T', [1, 0]),
+        ('N', [0, 1]),
+        ('O', [1, 1]),
+        ('H', [0, 2]),
+        ('J', [1, 2]),
+    ]
+    .iter()
+    .cloned()
+    .collect();
+
+    // Conversion multipliers for 100 Km² grid as [e, n]
+    // NB: 'I' is not used
+    let grid_100: HashMap<char, [i64; 2]> = [
+        ('V', [0, 0]),
+        ('W', [1, 0]),
+        ('X', [2, 0]),
+        ('Y', [3, 0]),
+        ('Z', [4, 0]),
+        ('Q', [0, 1]),
+        ('R', [1, 1]),
+        ('S', [2, 1]),
+        ('T', [3, 1]),
+        ('U', [4, 1]),
+        ('L', [0, 2]),
+        ('M', [1, 2]),
+        ('N', [2, 2]),
+        ('O', [3, 2]),
+        ('P', [4, 2]),
+        ('F', [0, 3]),
+        ('G', [1, 3]),
+        ('H', [2, 3]),
+        ('J', [3, 3]),
+        ('K', [4, 3]),
+        ('A', [0, 4]),
+        ('B', [1, 4]),
+        ('C', [2, 4]),
+        ('D', [3, 4]),
+        ('E', [4, 4]),
+    ]
+    .iter()
+    .cloned()
+    .collect();
+
+    let mut coords = OSCoords {
+        easting: 0,
+        northing: 0,
+        elevation: None,
+    };
+
+    // Try to parse as alphanumeric coordinate
+    let mut chars = coord.chars();
+    let first_char = chars.next().ok_or("The coordinate is empty")?;
+    if let Some(multipliers_500) = grid_500.get(&first_char) {
+        // Remove any spaces from the coordinate string because we can now treat it
+        // as "AAn1n2" where n1 & n2 have an equal number of digits
+        let coords_no_spaces = &coord.replace(' ', "");
+
+        // Convert first char to full grid origin coordinates
+        coords.easting += os::METRES_IN_500_GRID * multipliers_500[0];
+        coords.northing += os::METRES_IN_500_GRID * multipliers_500[1];
+
+        let second_char = chars.next().unwrap_or(' ');
+        let multipliers_100 = grid_100
+            .get(&second_char)
+            .ok_or_else(|| format!("2nd letter in {} is invalid", &coord))?;
+
+        // Convert 2nd char to full grid origin coordinates
+        // and add to the 1st char coordinates
+        coords.easting += os::METRES_IN_100_GRID * multipliers_100[0];
+        coords.northing += os::METRES_IN_100_GRID * multipliers_100[1];
+
+        if coords_no_spaces.len() <= 2 {
+            return Ok(None);
+        }
+
+        // It's not just a 2 char string so try to split the
+        // remainder of the string in two parts
+        let remainder = &coords_no_spaces[2..];
+        if !remainder.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("Coordinate {} is not a number", remainder).into());
+        }
+        let str_digit_pairs: Vec<String> = vec![
+            remainder[..remainder.len() / 2].to_string(),
+            remainder[remainder.len() / 2..].to_string(),
+        ];
+        // Get the numeric values and add to the full origin coordinates
+        let final_coords = get_full_coord_pair(str_digit_pairs)?;
+        coords.easting += final_coords.easting;
+        coords.northing += final_coords.northing;
+        return Ok(Some(coords));
+    }
+
+    // Not an alphanumeric coordinate string, so should just be numbers
+
+    // First try to split on white space
+    let coords_pair_space: Vec<&str> = coord.split_whitespace().collect();
+    if coords_pair_space.len() == 2 {
+        let mut vec_no_commas: Vec<String> = Vec::new();
+        for str_coord in coords_pair_space {
+            // Remove any commas
+            vec_no_commas.push(str_coord.replace(',', ""));
+        }
+        return Ok(Some(get_full_coord_pair(vec_no_commas)?));
+    }
+
+    // No white space, so try to split on comma
+    let coords_pair_comma: Vec<&str> = coord.split(',').collect();
+    if coords_pair_comma.len() == 2 {
+        let mut vec_no_spaces: Vec<String> = Vec::new();
+        for str_coord in coords_pair_comma {
+            // Remove any commas
+            vec_no_spaces.push(str_coord.replace(' ', ""));
+        }
+        return Ok(Some(get_full_coord_pair(vec_no_spaces)?));
+    }
+    Err(format!("Cannot split {} into numeric coordinate pairs", coord).into())
+}
+
+pub fn to_grid_ref(easting: i64, northing: i64) -> Option<String> {
+    // Converts full grid origin coordinates to a 10 digit alphanumeric grid ref,
+    // e.g. SZ 85669 92167, or None if they are outside the 91 100km² grids
+
+    if !(0..os::GRIDS_PER_ROW_100 * os::METRES_IN_100_GRID).contains(&easting)
+        || !(0..os::GRIDS_PER_COL_100 * os::METRES_IN_100_GRID).contains(&northing)
+    {
+        return None;
+    }
+    let grid_index = (northing / os::METRES_IN_100_GRID) * os::GRIDS_PER_ROW_100 + easting / os::METRES_IN_100_GRID;
+    Some(format!(
+        "{} {:05} {:05}",
+        os::GRID_100[grid_index as usize],
+        easting % os::METRES_IN_100_GRID,
+        northing % os::METRES_IN_100_GRID
+    ))
+}
+
+fn get_full_coord_pair(str_pair: Vec<String>) -> Result<OSCoords, Box<dyn Error>> {
+    // Converts a vec of two numeric strings to five digit coordinates
+
+    if str_pair.len() != 2 {
+        return Err("Coordinate string must have 2 numeric values".into());
+    }
+
+    let mut coords = OSCoords {
+        easting: 0,
+        northing: 0,
+        elevation: None,
+    };
+
+    for (i, item) in str_pair.iter().enumerate() {
+        // Right-pad with zeros if required
+        let str_digit = format!("{:0<5}", item);
+
+        // Convert to i64
+        if let Ok(number) = str_digit.parse::<i64>() {
+            if i == 0 {
+                coords.easting = number;
+            } else if i == 1 {
+                coords.northing = number;
+            }
+        } else {
+            return Err(format!("Coordinate {} is not a number", str_digit).into());
+        }
+    }
+    Ok(coords)
+}
+
+#[test]
+fn grid_ref_round_trip() {
+    assert_eq!(Some("SZ 85669 92167".to_string()), to_grid_ref(485_669, 92_167));
+    assert_eq!(Some("HU 30530 83500".to_string()), to_grid_ref(430_530, 1_183_500));
+    let coords = parse_coords(&["NN 16690 71270"]);
+    assert_eq!(Some("NN 16690 71270".to_string()), to_grid_ref(coords[0].easting, coords[0].northing));
+    assert_eq!(None, to_grid_ref(-1, 0));
+}
+
+#[test]
+fn invalid_coords_are_errors() {
+    assert_eq!(485_669, parse_coord("SZ 85669 92167").unwrap().easting);
+    assert_eq!(1_183_500, parse_coord("430530,1183500").unwrap().northing);
+    for invalid in ["", "SI 1234", "SZ 12a4", "SZ", "12345", "1,2,3", "ab,cd", "Ω"] {
+        assert!(parse_coord(invalid).is_err(), "{:?}", invalid);
+    }
+}
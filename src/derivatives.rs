@@ -0,0 +1,204 @@
+use crate::raster::{self, BoundingBox, Raster};
+use crate::reader::DataFile;
+use std::error::Error;
+
+/***********************************************************************
+    Code for calculating slope, aspect and curvature from the elevations
+
+    Each value is worked out from the 3 x 3 window of cells centred on a
+    cell. Rasters are extracted with a one cell margin so that cells on
+    the edge of a bounding box still have their true neighbours, which may
+    lie in another 10km² data block or 100km² grid. Neighbours in the sea
+    (nodata) or off the edge of a raster take the value of the centre cell.
+
+    The gradient is found by either:
+      Horn's method, which weights all 8 neighbours and smooths out noise
+      The Zevenbergen-Thorne method, which uses just the 4 adjacent cells
+
+    Curvature always uses the Zevenbergen-Thorne quadratic surface fitted
+    through the window, as Horn's method doesn't define one.
+************************************************************************/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Horn,
+    ZevenbergenThorne,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Derivative {
+    SlopeDegrees,
+    SlopePercent, // Rise over run x 100
+    Aspect, // Compass direction the slope faces in degrees clockwise from north
+    PlanCurvature, // Curvature across the slope: positive on convex ground like ridges, where flow diverges
+    ProfileCurvature, // Curvature down the slope: positive on convex ground, where the slope steepens
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Derivatives {
+    pub slope_degrees: f64,
+    pub slope_percent: f64,
+    pub aspect: Option<f64>, // None on flat ground
+    pub plan_curvature: f64, // Per metre
+    pub profile_curvature: f64, // Per metre
+}
+
+// Elevations in metres of the 3 x 3 cells around a cell, N to S and W to E
+pub type Window = [f64; 9];
+
+// Gets the window of elevations around a cell of a raster of x10 values,
+// or None if the cell itself has no data
+pub fn window(grid: &Raster<i16>, col: usize, row: usize) -> Option<Window> {
+    if grid.is_nodata(col, row) {
+        return None;
+    }
+    let centre = grid.get(col, row) as f64 / 10.0;
+    let mut window = [centre; 9];
+    for (i, value) in window.iter_mut().enumerate() {
+        let (c, r) = (col as isize + i as isize % 3 - 1, row as isize + i as isize / 3 - 1);
+        if c >= 0 && r >= 0 && c < grid.cols as isize && r < grid.rows as isize && !grid.is_nodata(c as usize, r as usize) {
+            *value = grid.get(c as usize, r as usize) as f64 / 10.0;
+        }
+    }
+    Some(window)
+}
+
+// Gets the rates of change of elevation to the east and to the north
+pub fn gradient(z: &Window, cell_size: f64, method: Method) -> (f64, f64) {
+    match method {
+        Method::Horn => (
+            ((z[2] + 2.0 * z[5] + z[8]) - (z[0] + 2.0 * z[3] + z[6])) / (8.0 * cell_size),
+            ((z[0] + 2.0 * z[1] + z[2]) - (z[6] + 2.0 * z[7] + z[8])) / (8.0 * cell_size),
+        ),
+        Method::ZevenbergenThorne => ((z[5] - z[3]) / (2.0 * cell_size), (z[1] - z[7]) / (2.0 * cell_size)),
+    }
+}
+
+// Calculates all the derivatives for a window
+pub fn derivatives(z: &Window, cell_size: f64, method: Method) -> Derivatives {
+    let (dz_de, dz_dn) = gradient(z, cell_size, method);
+    let rise = (dz_de * dz_de + dz_dn * dz_dn).sqrt();
+
+    // The slope faces downhill, i.e. against the gradient
+    let aspect = if rise == 0.0 {
+        None
+    } else {
+        Some((-dz_de).atan2(-dz_dn).to_degrees().rem_euclid(360.0))
+    };
+
+    // Coefficients of the Zevenbergen-Thorne surface
+    let l2 = cell_size * cell_size;
+    let d = ((z[3] + z[5]) / 2.0 - z[4]) / l2;
+    let e = ((z[1] + z[7]) / 2.0 - z[4]) / l2;
+    let f = (-z[0] + z[2] + z[6] - z[8]) / (4.0 * l2);
+    let (g, h) = ((z[5] - z[3]) / (2.0 * cell_size), (z[1] - z[7]) / (2.0 * cell_size));
+    let g2_h2 = g * g + h * h;
+    let (plan_curvature, profile_curvature) = if g2_h2 == 0.0 {
+        (0.0, 0.0)
+    } else {
+        (
+            -2.0 * (d * h * h + e * g * g - f * g * h) / g2_h2,
+            -2.0 * (d * g * g + e * h * h + f * g * h) / g2_h2,
+        )
+    };
+
+    Derivatives {
+        slope_degrees: rise.atan().to_degrees(),
+        slope_percent: rise * 100.0,
+        aspect,
+        plan_curvature,
+        profile_curvature,
+    }
+}
+
+impl Derivatives {
+    // Gets one of the values, or None for the aspect of flat ground
+    pub fn get(&self, derivative: Derivative) -> Option<f64> {
+        match derivative {
+            Derivative::SlopeDegrees => Some(self.slope_degrees),
+            Derivative::SlopePercent => Some(self.slope_percent),
+            Derivative::Aspect => self.aspect,
+            Derivative::PlanCurvature => Some(self.plan_curvature),
+            Derivative::ProfileCurvature => Some(self.profile_curvature),
+        }
+    }
+}
+
+// Calculates the derivatives at the 50m cell containing an easting & northing.
+// Returns None if the cell is in the sea.
+pub fn derivatives_at(
+    data: &mut DataFile,
+    easting: f64,
+    northing: f64,
+    method: Method,
+) -> Result<Option<Derivatives>, Box<dyn Error>> {
+    let (easting, northing) = (easting.floor() as i64, northing.floor() as i64);
+    let cell = BoundingBox::new(easting, northing, easting + 1, northing + 1)?.snap_to_cells();
+    let grid = raster::extract_x10(data, &with_margin(&cell)?, raster::NODATA_X10)?;
+    Ok(window(&grid, 1, 1).map(|z| derivatives(&z, grid.cell_size as f64, method)))
+}
+
+// Calculates one derivative for every 50m cell of a bounding box.
+// Sea cells and the aspect of flat ground are set to the nodata value.
+pub fn extract_derivative(
+    data: &mut DataFile,
+    bbox: &BoundingBox,
+    derivative: Derivative,
+    method: Method,
+    nodata: f32,
+) -> Result<Raster<f32>, Box<dyn Error>> {
+    let grid = raster::extract_x10(data, &with_margin(&bbox.snap_to_cells())?, raster::NODATA_X10)?;
+    let mut result = Raster::new(bbox, nodata);
+    for row in 0..result.rows {
+        for col in 0..result.cols {
+            if let Some(z) = window(&grid, col + 1, row + 1) {
+                if let Some(value) = derivatives(&z, grid.cell_size as f64, method).get(derivative) {
+                    result.set(col, row, value as f32);
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+// Grows a box by one cell on every side
+fn with_margin(cells: &BoundingBox) -> Result<BoundingBox, Box<dyn Error>> {
+    let distance = crate::os::ELEVATION_DISTANCE;
+    BoundingBox::new(
+        cells.min_easting - distance,
+        cells.min_northing - distance,
+        cells.max_easting + distance,
+        cells.max_northing + distance,
+    )
+}
+
+#[test]
+fn plane_slope_and_aspect() {
+    // Ground falling 50m per 50m cell both eastwards and northwards, so facing north east
+    let z = [-10.0, -60.0, -110.0, 40.0, -10.0, -60.0, 90.0, 40.0, -10.0];
+    for method in [Method::Horn, Method::ZevenbergenThorne] {
+        let result = derivatives(&z, 50.0, method);
+        assert!((result.slope_percent - 2f64.sqrt() * 100.0).abs() < 1e-9);
+        assert!((result.slope_degrees - 2f64.sqrt().atan().to_degrees()).abs() < 1e-9);
+        assert!((result.aspect.unwrap() - 45.0).abs() < 1e-9, "Aspect {:?}", result.aspect);
+        assert!(result.plan_curvature.abs() < 1e-12 && result.profile_curvature.abs() < 1e-12);
+    }
+}
+
+#[test]
+fn flat_ground_has_no_aspect() {
+    let result = derivatives(&[100.0; 9], 50.0, Method::Horn);
+    assert_eq!(0.0, result.slope_degrees);
+    assert_eq!(None, result.aspect);
+}
+
+#[test]
+fn curvature_of_a_valley_and_a_ridge() {
+    // A valley running N to S which falls to the south: flow converges across it
+    let valley = [12.0, 10.0, 12.0, 11.0, 9.0, 11.0, 10.0, 8.0, 10.0];
+    assert!(derivatives(&valley, 50.0, Method::ZevenbergenThorne).plan_curvature < 0.0);
+    // A ridge falling to the south: flow diverges either side of it
+    let ridge = valley.map(|v| 20.0 - v);
+    let ridge = [ridge[6], ridge[7], ridge[8], ridge[3], ridge[4], ridge[5], ridge[0], ridge[1], ridge[2]];
+    assert!(derivatives(&ridge, 50.0, Method::ZevenbergenThorne).plan_curvature > 0.0);
+}
@@ -0,0 +1,256 @@
+use crate::coords::{self, OSCoords};
+use crate::projection;
+use crate::reader::{self, DataFile};
+use serde_json::{json, Value};
+use std::error::Error;
+
+/***********************************************************************
+    Code for adding elevations to GeoJSON features as Z coordinates
+
+    Every position of the Point, LineString and Polygon geometries (and
+    their Multi & GeometryCollection forms) gets a third coordinate from
+    the binary file, replacing any Z it already had. Lines and polygon
+    rings can optionally be densified first, with positions added every
+    50m or so along each segment as for infilled profiles.
+
+    The positions may be OS National Grid eastings & northings or WGS84
+    longitudes & latitudes. The input's "crs" member is used if it has
+    one, otherwise the coordinates' size shows which they are. Densified
+    positions are given in the same coordinates as the input. Positions
+    outside the National Grid are left as they are and counted, and if
+    none are inside it, which usually means the CRS is wrong, that's an
+    error.
+
+    All the positions are gathered first and then looked up together with
+    read_elevations_batch, so each data block is read only once.
+************************************************************************/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crs {
+    BritishNationalGrid, // EPSG:27700 eastings & northings
+    Wgs84, // Longitudes & latitudes, as RFC 7946 GeoJSON
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DrapeOptions {
+    pub crs: Option<Crs>, // Found from the input if None
+    pub densify: bool, // Add positions every 50m or so along lines & rings
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrapeSummary {
+    pub crs: Crs,
+    pub positions: usize, // Positions given an elevation
+    pub outside: usize, // Positions outside the grid, left unchanged
+    pub added: usize, // Positions added by densifying
+}
+
+// Gets a copy of a GeoJSON FeatureCollection, Feature or geometry with an elevation
+// added to every position
+pub fn drape(data: &mut DataFile, geojson: &Value, options: &DrapeOptions) -> Result<(Value, DrapeSummary), Box<dyn Error>> {
+    let crs = match options.crs {
+        Some(crs) => crs,
+        None => detect_crs(geojson),
+    };
+    let mut output = geojson.clone();
+
+    // Densify the lines and gather the grid coordinates of every position
+    let mut locations = Vec::new();
+    let mut added = 0;
+    visit_geometries(&mut output, &mut |geometry| {
+        let (depth, is_line) = nesting(geometry)?;
+        added += prepare(&mut geometry["coordinates"], depth, options.densify && is_line, crs, &mut locations)?;
+        Ok(())
+    })?;
+
+    let inside = locations.iter().filter(|coord| reader::in_grid(coord.easting, coord.northing)).count();
+    if inside == 0 && !locations.is_empty() {
+        return Err(format!(
+            "None of the {} positions is inside the National Grid. Are they in {}?",
+            locations.len(),
+            match crs {
+                Crs::BritishNationalGrid => "BNG eastings & northings rather than longitudes & latitudes (--crs wgs84)",
+                Crs::Wgs84 => "WGS84 longitudes & latitudes rather than eastings & northings (--crs bng)",
+            }
+        )
+        .into());
+    }
+
+    // Then add the elevations to the positions in the same order
+    let elevations = data.read_elevations_batch(&locations)?;
+    let mut elevations = elevations.iter().map(|coord| {
+        reader::in_grid(coord.easting, coord.northing).then(|| coord.elevation.unwrap_or_default())
+    });
+    visit_geometries(&mut output, &mut |geometry| {
+        let (depth, _) = nesting(geometry)?;
+        add_elevations(&mut geometry["coordinates"], depth, &mut elevations);
+        Ok(())
+    })?;
+
+    let summary = DrapeSummary {
+        crs,
+        positions: inside,
+        outside: locations.len() - inside,
+        added,
+    };
+    Ok((output, summary))
+}
+
+// Works out whether the positions are grid coordinates or longitudes & latitudes
+pub fn detect_crs(geojson: &Value) -> Crs {
+    if let Some(name) = geojson["crs"]["properties"]["name"].as_str() {
+        return if name.contains("27700") { Crs::BritishNationalGrid } else { Crs::Wgs84 };
+    }
+    // Eastings & northings are almost all larger than any longitude & latitude
+    match first_position(geojson) {
+        Some((x, y)) if x.abs() <= 180.0 && y.abs() <= 90.0 => Crs::Wgs84,
+        _ => Crs::BritishNationalGrid,
+    }
+}
+
+fn first_position(value: &Value) -> Option<(f64, f64)> {
+    match value {
+        Value::Array(items) => match (items.first().and_then(Value::as_f64), items.get(1).and_then(Value::as_f64)) {
+            (Some(x), Some(y)) => Some((x, y)),
+            _ => items.iter().find_map(first_position),
+        },
+        Value::Object(members) => ["features", "geometry", "geometries", "coordinates"]
+            .iter()
+            .filter_map(|name| members.get(*name))
+            .find_map(first_position),
+        _ => None,
+    }
+}
+
+// Calls a function with each geometry (other than a GeometryCollection) in a GeoJSON object
+fn visit_geometries(
+    value: &mut Value,
+    visit: &mut impl FnMut(&mut Value) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    match value["type"].as_str() {
+        Some("FeatureCollection") => {
+            let features = value["features"].as_array_mut().ok_or("The feature collection has no features array")?;
+            for feature in features {
+                visit_geometries(feature, visit)?;
+            }
+            Ok(())
+        }
+        // Features without a location have a null geometry
+        Some("Feature") if value["geometry"].is_null() => Ok(()),
+        Some("Feature") => visit_geometries(&mut value["geometry"], visit),
+        Some("GeometryCollection") => {
+            let geometries = value["geometries"].as_array_mut().ok_or("The geometry collection has no geometries array")?;
+            for geometry in geometries {
+                visit_geometries(geometry, visit)?;
+            }
+            Ok(())
+        }
+        _ => visit(value),
+    }
+}
+
+// Gets how deeply a geometry's positions are nested in arrays, and whether they form lines
+fn nesting(geometry: &Value) -> Result<(usize, bool), Box<dyn Error>> {
+    match geometry["type"].as_str() {
+        Some("Point") => Ok((0, false)),
+        Some("MultiPoint") => Ok((1, false)),
+        Some("LineString") => Ok((1, true)),
+        Some("MultiLineString" | "Polygon") => Ok((2, true)),
+        Some("MultiPolygon") => Ok((3, true)),
+        Some(other) => Err(format!("Unknown geometry type {:?}", other).into()),
+        None => Err("A geometry has no type".into()),
+    }
+}
+
+// Densifies the lines in a geometry's coordinates if asked, and adds the grid coordinates
+// of each position to the locations. Returns the number of positions added.
+fn prepare(coordinates: &mut Value, depth: usize, densify: bool, crs: Crs, locations: &mut Vec<OSCoords>) -> Result<usize, Box<dyn Error>> {
+    if depth == 0 {
+        locations.push(to_grid(coordinates, crs)?);
+        return Ok(0);
+    }
+    let mut added = 0;
+    if depth == 1 && densify {
+        let positions = coordinates.as_array().ok_or("Line coordinates are not an array")?;
+        let mut densified = Vec::with_capacity(positions.len());
+        for (i, position) in positions.iter().enumerate() {
+            if i > 0 {
+                // The infills run from just after the previous position to the current one, inclusive
+                let infills = coords::get_infills(to_grid(&positions[i - 1], crs)?, to_grid(position, crs)?, false);
+                for infill in &infills[..infills.len() - 1] {
+                    densified.push(from_grid(infill, crs));
+                    added += 1;
+                }
+            }
+            densified.push(position.clone());
+        }
+        *coordinates = Value::Array(densified);
+    }
+    let items = coordinates.as_array_mut().ok_or("Geometry coordinates are not nested as their type needs")?;
+    for item in items {
+        added += prepare(item, depth - 1, densify, crs, locations)?;
+    }
+    Ok(added)
+}
+
+// Sets the third coordinate of each position to the next elevation, if it has one
+fn add_elevations(coordinates: &mut Value, depth: usize, elevations: &mut impl Iterator<Item = Option<f32>>) {
+    if let Some(items) = coordinates.as_array_mut() {
+        if depth == 0 {
+            if let Some(elevation) = elevations.next().flatten() {
+                items.truncate(2);
+                items.push(json!((elevation as f64 * 10.0).round() / 10.0));
+            }
+        } else {
+            for item in items {
+                add_elevations(item, depth - 1, elevations);
+            }
+        }
+    }
+}
+
+// Gets the grid coordinates of a GeoJSON position
+fn to_grid(position: &Value, crs: Crs) -> Result<OSCoords, Box<dyn Error>> {
+    let (x, y) = match (position.get(0).and_then(Value::as_f64), position.get(1).and_then(Value::as_f64)) {
+        (Some(x), Some(y)) => (x, y),
+        _ => return Err(format!("The position {} is not numeric", position).into()),
+    };
+    let (easting, northing) = match crs {
+        Crs::BritishNationalGrid => (x, y),
+        Crs::Wgs84 => {
+            if !(-180.0..=180.0).contains(&x) || !(-90.0..=90.0).contains(&y) {
+                return Err(format!("The position {} is not a valid longitude & latitude", position).into());
+            }
+            projection::wgs84_to_os(y, x)
+        }
+    };
+    Ok(OSCoords {
+        easting: easting.round() as i64,
+        northing: northing.round() as i64,
+        elevation: None,
+    })
+}
+
+// Gets a GeoJSON position for grid coordinates, rounded to around 0.1m
+fn from_grid(coord: &OSCoords, crs: Crs) -> Value {
+    match crs {
+        Crs::BritishNationalGrid => json!([coord.easting as f64, coord.northing as f64]),
+        Crs::Wgs84 => {
+            let (lat, lon) = projection::os_to_wgs84(coord.easting as f64, coord.northing as f64);
+            json!([(lon * 1e6).round() / 1e6, (lat * 1e6).round() / 1e6])
+        }
+    }
+}
+
+#[test]
+fn crs_detection() {
+    let feature = |coordinates: Value| json!({ "type": "Feature", "geometry": { "type": "LineString", "coordinates": coordinates } });
+    assert_eq!(Crs::Wgs84, detect_crs(&feature(json!([[-1.8, 51.5], [-1.7, 51.6]]))));
+    assert_eq!(Crs::BritishNationalGrid, detect_crs(&feature(json!([[412345, 167890], [412400, 167900]]))));
+    let named = json!({
+        "type": "FeatureCollection",
+        "crs": { "type": "name", "properties": { "name": "urn:ogc:def:crs:EPSG::27700" } },
+        "features": [feature(json!([[100, 50], [150, 60]]))],
+    });
+    assert_eq!(Crs::BritishNationalGrid, detect_crs(&named));
+}
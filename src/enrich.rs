@@ -0,0 +1,255 @@
+use crate::coords::{self, OSCoords};
+use crate::derivatives::{self, Method};
+use crate::projection;
+use crate::reader::{self, DataFile};
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+/***********************************************************************
+    Code for adding elevations (and optionally slopes) to the rows of a
+    CSV file
+
+    The locations are found by column name in the header row: a grid
+    reference, an easting & northing, or a WGS84 latitude & longitude.
+    The rows are streamed through in batches, so files of any size can be
+    enriched in a little memory. Each batch is looked up with
+    read_elevations_batch, which reads each data block once.
+
+    Rows are written out unchanged with the new columns added at the end.
+    A row whose location can't be parsed, or lies outside the National
+    Grid, gets empty new columns and is reported rather than stopping the
+    run.
+************************************************************************/
+
+const BATCH_ROWS: usize = 10_000;
+const SLOPE_CACHE_BYTES: usize = 64_000_000; // Each slope reads a 3 x 3 cell window, often from the same blocks
+
+// The columns holding the locations
+#[derive(Debug, Clone, PartialEq)]
+pub enum Columns {
+    GridRef(String),
+    EastingNorthing(String, String),
+    LatLon(String, String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EnrichOptions {
+    pub columns: Option<Columns>, // Found from the header if None
+    pub slope: bool, // Add the slope in degrees too
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnrichSummary {
+    pub rows: usize, // Data rows read
+    pub failed: usize, // Rows whose location couldn't be parsed or is outside the grid
+}
+
+// The positions of the location columns in each row
+enum Positions {
+    GridRef(usize),
+    EastingNorthing(usize, usize),
+    LatLon(usize, usize),
+}
+
+// Copies CSV rows from input to output adding an elevation column (and a slope column
+// if asked). Rows which can't be parsed are passed to report with their row number.
+pub fn enrich_csv(
+    data: &mut DataFile,
+    input: impl BufRead,
+    mut output: impl Write,
+    options: &EnrichOptions,
+    mut report: impl FnMut(usize, &str),
+) -> Result<EnrichSummary, Box<dyn Error>> {
+    if options.slope && data.cache_stats().is_none() {
+        data.enable_cache(SLOPE_CACHE_BYTES)?;
+    }
+    let mut records = Records { lines: input.lines() };
+    let header = records.next().ok_or("The CSV file is empty")??;
+    let names = split_record(&header);
+    let positions = find_columns(&names, options.columns.as_ref())?;
+    writeln!(output, "{},elevation{}", header, if options.slope { ",slope" } else { "" })?;
+
+    let mut summary = EnrichSummary { rows: 0, failed: 0 };
+    let mut batch: Vec<(String, Result<OSCoords, String>)> = Vec::with_capacity(BATCH_ROWS);
+    loop {
+        let record = records.next().transpose()?;
+        if let Some(record) = record.as_ref().filter(|record| !record.is_empty()) {
+            summary.rows += 1;
+            let location = parse_location(&split_record(record), &positions);
+            if let Err(e) = &location {
+                summary.failed += 1;
+                report(summary.rows, e);
+            }
+            batch.push((record.clone(), location));
+        }
+        if batch.len() == BATCH_ROWS || (record.is_none() && !batch.is_empty()) {
+            write_batch(data, &batch, &mut output, options)?;
+            batch.clear();
+        }
+        if record.is_none() {
+            break;
+        }
+    }
+    output.flush()?;
+    Ok(summary)
+}
+
+// Looks up the elevations (and slopes) of a batch of rows and writes them out
+fn write_batch(
+    data: &mut DataFile,
+    batch: &[(String, Result<OSCoords, String>)],
+    output: &mut impl Write,
+    options: &EnrichOptions,
+) -> Result<(), Box<dyn Error>> {
+    let locations: Vec<OSCoords> = batch.iter().filter_map(|(_, location)| location.clone().ok()).collect();
+    let mut elevations = data.read_elevations_batch(&locations)?.into_iter();
+
+    for (record, location) in batch {
+        let mut columns = String::new();
+        if let Ok(location) = location {
+            let elevation = elevations.next().and_then(|coords| coords.elevation).unwrap_or_default();
+            columns = format!("{:.1}", elevation);
+            if options.slope {
+                let slope = derivatives::derivatives_at(data, location.easting as f64, location.northing as f64, Method::Horn)?
+                    .map(|derivatives| format!("{:.1}", derivatives.slope_degrees))
+                    .unwrap_or_default();
+                columns = format!("{},{}", columns, slope);
+            }
+        } else if options.slope {
+            columns.push(',');
+        }
+        writeln!(output, "{},{}", record, columns)?;
+    }
+    Ok(())
+}
+
+// Finds the positions of the named location columns, or of the usual names if none are given
+fn find_columns(names: &[String], columns: Option<&Columns>) -> Result<Positions, Box<dyn Error>> {
+    let find = |candidates: &[&str]| {
+        names
+            .iter()
+            .position(|name| candidates.iter().any(|candidate| name.trim().eq_ignore_ascii_case(candidate)))
+    };
+    let require = |name: &str| find(&[name]).ok_or_else(|| format!("There is no {} column in the CSV header", name));
+    match columns {
+        Some(Columns::GridRef(name)) => Ok(Positions::GridRef(require(name)?)),
+        Some(Columns::EastingNorthing(easting, northing)) => Ok(Positions::EastingNorthing(require(easting)?, require(northing)?)),
+        Some(Columns::LatLon(lat, lon)) => Ok(Positions::LatLon(require(lat)?, require(lon)?)),
+        None => {
+            if let Some(grid_ref) = find(&["grid_ref", "gridref", "grid ref", "os_grid_ref", "ref"]) {
+                return Ok(Positions::GridRef(grid_ref));
+            }
+            if let (Some(easting), Some(northing)) = (find(&["easting", "eastings", "e"]), find(&["northing", "northings", "n"])) {
+                return Ok(Positions::EastingNorthing(easting, northing));
+            }
+            if let (Some(lat), Some(lon)) = (find(&["lat", "latitude"]), find(&["lon", "lng", "long", "longitude"])) {
+                return Ok(Positions::LatLon(lat, lon));
+            }
+            Err("Cannot find grid ref, easting & northing, or lat & lon columns in the CSV header".into())
+        }
+    }
+}
+
+// Gets the location of a row inside the grid, or a description of what's wrong with it
+fn parse_location(fields: &[String], positions: &Positions) -> Result<OSCoords, String> {
+    let location = parse_any_location(fields, positions)?;
+    if !reader::in_grid(location.easting, location.northing) {
+        return Err(format!("{},{} is outside the National Grid", location.easting, location.northing));
+    }
+    Ok(location)
+}
+
+fn parse_any_location(fields: &[String], positions: &Positions) -> Result<OSCoords, String> {
+    let field = |position: usize| match fields.get(position).map(|field| field.trim()) {
+        Some(field) if !field.is_empty() => Ok(field),
+        _ => Err(format!("column {} is empty", position + 1)),
+    };
+    let number = |position: usize| -> Result<f64, String> {
+        let text = field(position)?;
+        text.parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .ok_or_else(|| format!("{:?} is not a number", text))
+    };
+    let coords = |easting: f64, northing: f64| OSCoords {
+        easting: easting.round() as i64,
+        northing: northing.round() as i64,
+        elevation: None,
+    };
+    match *positions {
+        Positions::GridRef(position) => coords::parse_coord(field(position)?).map_err(|e| e.to_string()),
+        Positions::EastingNorthing(easting, northing) => Ok(coords(number(easting)?, number(northing)?)),
+        Positions::LatLon(lat, lon) => {
+            let (lat, lon) = (number(lat)?, number(lon)?);
+            if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+                return Err(format!("{},{} is not a valid latitude & longitude", lat, lon));
+            }
+            let (easting, northing) = projection::wgs84_to_os(lat, lon);
+            Ok(coords(easting, northing))
+        }
+    }
+}
+
+// Reads whole CSV records, joining lines where a quoted field holds a line break
+struct Records<L> {
+    lines: L,
+}
+
+impl<L: Iterator<Item = std::io::Result<String>>> Iterator for Records<L> {
+    type Item = std::io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = match self.lines.next()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e)),
+        };
+        while record.matches('"').count() % 2 == 1 {
+            match self.lines.next() {
+                Some(Ok(line)) => {
+                    record.push('\n');
+                    record.push_str(&line);
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => break,
+            }
+        }
+        if record.ends_with('\r') {
+            record.pop();
+        }
+        Some(Ok(record))
+    }
+}
+
+// Splits a CSV record into its fields, removing quotes
+fn split_record(record: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = record.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                fields.last_mut().unwrap().push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+#[test]
+fn csv_fields_and_locations() {
+    assert_eq!(vec!["a", "b,c", "say \"hi\"", ""], split_record(r#"a,"b,c","say ""hi""","#));
+
+    let names = split_record("Name,Latitude,Longitude");
+    let positions = find_columns(&names, None).unwrap();
+    assert!(matches!(positions, Positions::LatLon(1, 2)));
+    assert!(parse_location(&split_record("x,51.5,-1.8"), &positions).is_ok());
+    assert_eq!(Err("\"abc\" is not a number".to_string()), parse_location(&split_record("x,abc,-1.8"), &positions).map(|_| ()));
+    assert_eq!(Err("column 3 is empty".to_string()), parse_location(&split_record("x,51.5"), &positions).map(|_| ()));
+
+    let columns = Columns::EastingNorthing("x".to_string(), "missing".to_string());
+    assert!(find_columns(&names, Some(&columns)).is_err());
+}
@@ -0,0 +1,294 @@
+use crate::activity::{self, Correction, CorrectionOptions, CorrectionSummary, TrackPoint};
+use crate::profile;
+use crate::reader::DataFile;
+use std::error::Error;
+
+/***********************************************************************
+    Code for reading the track of a Garmin FIT activity file and writing
+    it back with corrected altitudes
+
+    A FIT file is a 12 or 14 byte header, a run of messages and a CRC.
+    Each definition message describes the fields of the data messages
+    which follow with its local message type, and a data message holds
+    the fields' values with no further framing. Several FIT files may be
+    chained one after another.
+
+    The track points are the record messages (global message 20), with a
+    position in semicircles (fields 0 & 1) and an altitude (field 2, or
+    enhanced_altitude field 78) in 1/5 metres offset by 500m. The altitudes
+    are corrected by overwriting these fields where they are, so the rest
+    of the file is written back byte for byte. Records without an altitude
+    field are left as they are, as adding one would mean rewriting the
+    definitions. The total ascent & descent of each session message
+    (global message 18, fields 22 & 23) are recomputed from the records
+    timed between its start_time (field 2) and timestamp (field 253), or
+    from all the records if it doesn't have both, and the CRCs are updated
+    to match. The record times come from their timestamp fields or their
+    compressed timestamp headers.
+************************************************************************/
+
+const RECORD: u16 = 20;
+const SESSION: u16 = 18;
+const POSITION_LAT: u8 = 0;
+const POSITION_LONG: u8 = 1;
+const ALTITUDE: u8 = 2;
+const ENHANCED_ALTITUDE: u8 = 78;
+const START_TIME: u8 = 2;
+const TOTAL_ASCENT: u8 = 22;
+const TOTAL_DESCENT: u8 = 23;
+const TIMESTAMP: u8 = 253;
+
+// The CRC-16 of the FIT protocol, calculated a nibble at a time
+const CRC_TABLE: [u16; 16] = [
+    0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800, 0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+];
+
+// The fields of the data messages with a local message type
+struct Definition {
+    big_endian: bool,
+    global: u16,
+    fields: Vec<(u8, usize, usize)>, // Field number, offset in the message & size
+    length: usize, // Including any developer fields
+}
+
+// Where a value is in the file
+#[derive(Clone, Copy)]
+struct Field {
+    offset: usize,
+    size: usize,
+    big_endian: bool,
+}
+
+struct Record {
+    point: TrackPoint,
+    altitude_fields: Vec<Field>,
+    timestamp: Option<u32>,
+}
+
+struct Session {
+    ascent: Option<Field>,
+    descent: Option<Field>,
+    span: Option<(u32, u32)>, // The start & end timestamps
+}
+
+// The parts of a FIT file to be read & rewritten
+struct Layout {
+    records: Vec<Record>,
+    sessions: Vec<Session>,
+    files: Vec<(usize, usize)>, // The start & end of the data of each chained file, before its CRC
+}
+
+// Gets the track points of the record messages
+pub fn track_points(fit: &[u8]) -> Result<Vec<TrackPoint>, Box<dyn Error>> {
+    Ok(parse(fit)?.records.into_iter().map(|record| record.point).collect())
+}
+
+// Gets a copy of a FIT file with the record altitudes corrected with the terrain
+// elevations and the session total ascent & descent recomputed
+pub fn correct_fit(data: &mut DataFile, fit: &[u8], options: &CorrectionOptions) -> Result<(Vec<u8>, CorrectionSummary), Box<dyn Error>> {
+    let layout = parse(fit)?;
+    let points: Vec<TrackPoint> = layout.records.iter().map(|record| record.point).collect();
+    let Correction { altitudes, summary } = activity::correct_altitudes(data, &points, options)?;
+
+    let mut output = fit.to_vec();
+    for (record, altitude) in layout.records.iter().zip(&altitudes) {
+        if let Some(altitude) = *altitude {
+            for field in &record.altitude_fields {
+                let max = if field.size == 2 { (u16::MAX - 1) as u64 } else { (u32::MAX - 1) as u64 };
+                let value = ((altitude + 500.0) * 5.0).round().clamp(0.0, max as f64) as u64;
+                write_uint(&mut output, field, value);
+            }
+        }
+    }
+    for session in &layout.sessions {
+        let session_altitudes: Vec<f64> = layout
+            .records
+            .iter()
+            .zip(&altitudes)
+            .filter(|(record, _)| match (session.span, record.timestamp) {
+                (Some((start, end)), Some(timestamp)) => (start..=end).contains(&timestamp),
+                (Some(_), None) => false,
+                (None, _) => true,
+            })
+            .filter_map(|(_, altitude)| *altitude)
+            .collect();
+        // The descent forwards is the ascent backwards
+        let ascent = profile::total_ascent(session_altitudes.iter().copied());
+        let descent = profile::total_ascent(session_altitudes.iter().rev().copied());
+        for (field, total) in [(session.ascent, ascent), (session.descent, descent)] {
+            if let Some(field) = field {
+                write_uint(&mut output, &field, total.round().min((u16::MAX - 1) as f64) as u64);
+            }
+        }
+    }
+    for &(start, end) in &layout.files {
+        let crc = crc(&output[start..end]);
+        output[end..end + 2].copy_from_slice(&crc.to_le_bytes());
+    }
+    Ok((output, summary))
+}
+
+fn parse(fit: &[u8]) -> Result<Layout, Box<dyn Error>> {
+    let mut layout = Layout {
+        records: Vec::new(),
+        sessions: Vec::new(),
+        files: Vec::new(),
+    };
+    let mut start = 0;
+    while start < fit.len() {
+        let header_size = fit[start] as usize;
+        if header_size < 12 || fit.len() < start + header_size || &fit[start + 8..start + 12] != b".FIT" {
+            return Err("The file is not a FIT file".into());
+        }
+        let data_size = u32::from_le_bytes([fit[start + 4], fit[start + 5], fit[start + 6], fit[start + 7]]) as usize;
+        let end = start + header_size + data_size;
+        if fit.len() < end + 2 {
+            return Err("The FIT file is shorter than its header says".into());
+        }
+
+        let mut definitions: Vec<Option<Definition>> = (0..16).map(|_| None).collect();
+        let mut last_timestamp: Option<u32> = None;
+        let mut position = start + header_size;
+        while position < end {
+            let header = fit[position];
+            position += 1;
+            let mut compressed_timestamp = None;
+            let local = if header & 0x80 != 0 {
+                // A compressed timestamp header, which is always for a data message.
+                // Its 5 bit offset is from the last full timestamp, rolling over every 32s
+                let offset = (header & 0x1F) as u32;
+                compressed_timestamp = last_timestamp.map(|last| {
+                    let rollover = if offset < last & 0x1F { 0x20 } else { 0 };
+                    (last & !0x1F) + offset + rollover
+                });
+                last_timestamp = compressed_timestamp.or(last_timestamp);
+                (header >> 5) & 0x03
+            } else if header & 0x40 != 0 {
+                let (definition, length) = parse_definition(&fit[position..end], header & 0x20 != 0)?;
+                definitions[(header & 0x0F) as usize] = Some(definition);
+                position += length;
+                continue;
+            } else {
+                header & 0x0F
+            };
+
+            let definition = definitions[local as usize]
+                .as_ref()
+                .ok_or("The FIT file has a data message before its definition")?;
+            if position + definition.length > end {
+                return Err("The FIT file ends part way through a message".into());
+            }
+            let field = |number: u8, size: usize| {
+                definition
+                    .fields
+                    .iter()
+                    .find(|&&(field_number, _, field_size)| field_number == number && field_size == size)
+                    .map(|&(_, offset, size)| Field {
+                        offset: position + offset,
+                        size,
+                        big_endian: definition.big_endian,
+                    })
+            };
+            let value = |field: Option<Field>, invalid: u64| field.map(|field| read_uint(fit, &field)).filter(|&value| value != invalid);
+            let timestamp = value(field(TIMESTAMP, 4), 0xFFFF_FFFF).map(|value| value as u32);
+            if timestamp.is_some() {
+                last_timestamp = timestamp;
+            }
+            match definition.global {
+                RECORD => {
+                    let semicircles = |number: u8| value(field(number, 4), 0x7FFF_FFFF).map(|value| value as u32 as i32 as f64 * 180.0 / 2f64.powi(31));
+                    let altitude = value(field(ENHANCED_ALTITUDE, 4), 0xFFFF_FFFF)
+                        .or_else(|| value(field(ALTITUDE, 2), 0xFFFF))
+                        .map(|value| value as f64 / 5.0 - 500.0);
+                    let lat_lon = match (semicircles(POSITION_LAT), semicircles(POSITION_LONG)) {
+                        (Some(lat), Some(lon)) => Some((lat, lon)),
+                        _ => None,
+                    };
+                    layout.records.push(Record {
+                        point: TrackPoint { position: lat_lon, altitude },
+                        altitude_fields: [field(ALTITUDE, 2), field(ENHANCED_ALTITUDE, 4)].into_iter().flatten().collect(),
+                        timestamp: timestamp.or(compressed_timestamp),
+                    });
+                }
+                SESSION => {
+                    let start_time = value(field(START_TIME, 4), 0xFFFF_FFFF).map(|value| value as u32);
+                    layout.sessions.push(Session {
+                        ascent: field(TOTAL_ASCENT, 2),
+                        descent: field(TOTAL_DESCENT, 2),
+                        span: start_time.zip(timestamp),
+                    });
+                }
+                _ => (),
+            }
+            position += definition.length;
+        }
+        layout.files.push((start, end));
+        start = end + 2;
+    }
+    Ok(layout)
+}
+
+// Gets a definition message's definition and length
+fn parse_definition(bytes: &[u8], developer_fields: bool) -> Result<(Definition, usize), Box<dyn Error>> {
+    let too_short = "The FIT file ends part way through a definition";
+    let fixed = bytes.get(..5).ok_or(too_short)?;
+    let big_endian = fixed[1] == 1;
+    let global = match big_endian {
+        true => u16::from_be_bytes([fixed[2], fixed[3]]),
+        false => u16::from_le_bytes([fixed[2], fixed[3]]),
+    };
+    let field_count = fixed[4] as usize;
+    let mut length = 5 + field_count * 3;
+    let mut fields = Vec::with_capacity(field_count);
+    let mut offset = 0;
+    for field in bytes.get(5..length).ok_or(too_short)?.chunks_exact(3) {
+        fields.push((field[0], offset, field[1] as usize));
+        offset += field[1] as usize;
+    }
+    if developer_fields {
+        let developer_count = *bytes.get(length).ok_or(too_short)? as usize;
+        let developer = bytes.get(length + 1..length + 1 + developer_count * 3).ok_or(too_short)?;
+        offset += developer.chunks_exact(3).map(|field| field[1] as usize).sum::<usize>();
+        length += 1 + developer_count * 3;
+    }
+    Ok((
+        Definition {
+            big_endian,
+            global,
+            fields,
+            length: offset,
+        },
+        length,
+    ))
+}
+
+fn read_uint(bytes: &[u8], field: &Field) -> u64 {
+    let value = &bytes[field.offset..field.offset + field.size];
+    let fold = |value: u64, &byte: &u8| value << 8 | byte as u64;
+    match field.big_endian {
+        true => value.iter().fold(0, fold),
+        false => value.iter().rev().fold(0, fold),
+    }
+}
+
+fn write_uint(bytes: &mut [u8], field: &Field, value: u64) {
+    let value = &value.to_le_bytes()[..field.size];
+    let target = &mut bytes[field.offset..field.offset + field.size];
+    target.copy_from_slice(value);
+    if field.big_endian {
+        target.reverse();
+    }
+}
+
+pub fn crc(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, &byte| {
+        let crc = (crc >> 4) ^ CRC_TABLE[(crc & 0x0F) as usize] ^ CRC_TABLE[(byte & 0x0F) as usize];
+        (crc >> 4) ^ CRC_TABLE[(crc & 0x0F) as usize] ^ CRC_TABLE[(byte >> 4) as usize]
+    })
+}
+
+#[test]
+fn fit_crc() {
+    // The check value of CRC-16/ARC, which the FIT CRC is
+    assert_eq!(0xBB3D, crc(b"123456789"));
+}
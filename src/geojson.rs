@@ -0,0 +1,108 @@
+use crate::polygonize::Polygon;
+use serde_json::{json, Value};
+use std::{error::Error, fs, io, path};
+
+/***********************************************************************
+    Helpers for writing GeoJSON output in OS National Grid coordinates,
+    and for reading polygons from GeoJSON input in the same coordinates
+************************************************************************/
+
+// Coordinates are rounded to 0.1m, which is ample for 50m data
+fn position(point: &(f64, f64)) -> Value {
+    json!([(point.0 * 10.0).round() / 10.0, (point.1 * 10.0).round() / 10.0])
+}
+
+pub fn point(point: &(f64, f64)) -> Value {
+    json!({ "type": "Point", "coordinates": position(point) })
+}
+
+pub fn line_string(points: &[(f64, f64)]) -> Value {
+    let coordinates: Vec<Value> = points.iter().map(position).collect();
+    json!({ "type": "LineString", "coordinates": coordinates })
+}
+
+// The first ring is the outer boundary and any others are holes
+pub fn polygon(rings: &[Vec<(f64, f64)>]) -> Value {
+    let coordinates: Vec<Vec<Value>> = rings.iter().map(|ring| ring.iter().map(position).collect()).collect();
+    json!({ "type": "Polygon", "coordinates": coordinates })
+}
+
+pub fn feature(geometry: Value, properties: Value) -> Value {
+    json!({ "type": "Feature", "properties": properties, "geometry": geometry })
+}
+
+// Wraps features in a collection which names the British National Grid as its CRS
+pub fn feature_collection(features: Vec<Value>) -> Value {
+    json!({
+        "type": "FeatureCollection",
+        "crs": { "type": "name", "properties": { "name": "urn:ogc:def:crs:EPSG::27700" } },
+        "features": features,
+    })
+}
+
+pub fn write_file(output_file: &path::Path, geojson: &Value) -> Result<(), Box<dyn Error>> {
+    let file = fs::File::create(output_file)?;
+    serde_json::to_writer(io::BufWriter::new(file), geojson)?;
+    Ok(())
+}
+
+pub fn read_file(input_file: &path::Path) -> Result<Value, Box<dyn Error>> {
+    let file = fs::File::open(input_file)?;
+    Ok(serde_json::from_reader(io::BufReader::new(file))?)
+}
+
+// Gets the features of a feature collection, or a single feature as a collection of one
+pub fn features(geojson: &Value) -> Result<Vec<&Value>, Box<dyn Error>> {
+    match geojson["type"].as_str() {
+        Some("FeatureCollection") => Ok(geojson["features"]
+            .as_array()
+            .ok_or("The feature collection has no features array")?
+            .iter()
+            .collect()),
+        Some("Feature") => Ok(vec![geojson]),
+        _ => Err("The GeoJSON is not a Feature or FeatureCollection".into()),
+    }
+}
+
+// Gets the polygons of a Polygon or MultiPolygon geometry
+pub fn polygons(geometry: &Value) -> Result<Vec<Polygon>, Box<dyn Error>> {
+    let coordinates = &geometry["coordinates"];
+    match geometry["type"].as_str() {
+        Some("Polygon") => Ok(vec![parse_rings(coordinates)?]),
+        Some("MultiPolygon") => coordinates
+            .as_array()
+            .ok_or("The MultiPolygon has no coordinates")?
+            .iter()
+            .map(parse_rings)
+            .collect(),
+        _ => Err("The geometry is not a Polygon or MultiPolygon".into()),
+    }
+}
+
+fn parse_rings(rings: &Value) -> Result<Polygon, Box<dyn Error>> {
+    let parse_position = |position: &Value| -> Option<(f64, f64)> {
+        Some((position.get(0)?.as_f64()?, position.get(1)?.as_f64()?))
+    };
+    rings
+        .as_array()
+        .ok_or("The polygon has no rings")?
+        .iter()
+        .map(|ring| {
+            ring.as_array()
+                .ok_or("A polygon ring is not an array")?
+                .iter()
+                .map(|position| parse_position(position).ok_or_else(|| "A polygon position is not numeric".into()))
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn polygon_round_trip() {
+    let rings = vec![vec![(0.0, 0.0), (100.0, 0.0), (100.0, 50.0), (0.0, 0.0)]];
+    let geometry = polygon(&rings);
+    assert_eq!(vec![rings.clone()], polygons(&geometry).unwrap());
+    let multi = json!({ "type": "MultiPolygon", "coordinates": [geometry["coordinates"], geometry["coordinates"]] });
+    assert_eq!(vec![rings.clone(), rings], polygons(&multi).unwrap());
+    assert!(polygons(&point(&(0.0, 0.0))).is_err());
+}
@@ -0,0 +1,321 @@
+use crate::os;
+use crate::raster::{self, BoundingBox, Raster};
+use crate::reader::DataFile;
+use flate2::{write::ZlibEncoder, Compression};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::{error::Error, fs, path};
+
+/***********************************************************************
+    Code for writing regions of the OS binary file as a GeoTIFF
+
+    The image is written as a classic little endian TIFF in bands of rows
+    so that even the whole of GB never has to be held in memory at once.
+    Each band is extracted from the binary file, split into strips or tiles
+    and written out, then the image file directory (IFD) describing where
+    they are is appended and its address back-filled into the TIFF header.
+************************************************************************/
+
+const TIFF_HEADER_LENGTH: u64 = 8; // Byte order, magic number & IFD address
+const TILE_SIZE: usize = 256; // Width & height of internal tiles (must be a multiple of 16)
+const ROWS_PER_STRIP: usize = os::ELEVATIONS_PER_ROW; // One row of 10km² data blocks per strip when not tiled
+const EPSG_BRITISH_NATIONAL_GRID: u16 = 27700;
+
+// TIFF field types
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_DOUBLE: u16 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleType {
+    I16, // Raw x10 elevations
+    F32, // Elevations in metres
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GeoTiffOptions {
+    pub sample_type: SampleType,
+    pub nodata: f32,
+    pub tiled: bool,
+    pub deflate: bool,
+}
+
+impl Default for GeoTiffOptions {
+    fn default() -> GeoTiffOptions {
+        GeoTiffOptions {
+            sample_type: SampleType::F32,
+            nodata: raster::NODATA_METRES,
+            tiled: false,
+            deflate: false,
+        }
+    }
+}
+
+// A TIFF directory entry with its value already encoded as little endian bytes
+struct Field {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    value: Vec<u8>,
+}
+
+impl Field {
+    fn shorts(tag: u16, values: &[u16]) -> Field {
+        Field {
+            tag,
+            field_type: TYPE_SHORT,
+            count: values.len() as u32,
+            value: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn longs(tag: u16, values: &[u32]) -> Field {
+        Field {
+            tag,
+            field_type: TYPE_LONG,
+            count: values.len() as u32,
+            value: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn doubles(tag: u16, values: &[f64]) -> Field {
+        Field {
+            tag,
+            field_type: TYPE_DOUBLE,
+            count: values.len() as u32,
+            value: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn ascii(tag: u16, value: &str) -> Field {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        Field {
+            tag,
+            field_type: TYPE_ASCII,
+            count: bytes.len() as u32,
+            value: bytes,
+        }
+    }
+}
+
+pub fn write_geotiff(
+    data: &mut DataFile,
+    bbox: &BoundingBox,
+    output_file: &path::Path,
+    options: &GeoTiffOptions,
+) -> Result<(), Box<dyn Error>> {
+    let cells = bbox.snap_to_cells();
+    let (cols, rows) = cells.size_in_cells();
+    let file = fs::File::create(output_file)?;
+    let mut file_buffer = BufWriter::new(file);
+
+    // Write the header with a placeholder for the IFD address (to be populated later)
+    file_buffer.write_all(b"II")?;
+    file_buffer.write_all(&42u16.to_le_bytes())?;
+    file_buffer.write_all(&0u32.to_le_bytes())?;
+
+    // Strips span the full width of the image, tiles are square
+    let (chunk_width, chunk_height) = if options.tiled {
+        (TILE_SIZE, TILE_SIZE)
+    } else {
+        (cols, ROWS_PER_STRIP)
+    };
+    let chunks_across = cols.div_ceil(chunk_width);
+    let mut chunk_offsets: Vec<u32> = Vec::new();
+    let mut chunk_byte_counts: Vec<u32> = Vec::new();
+
+    raster::extract_bands_x10(data, &cells, chunk_height, |grid| {
+        for chunk in 0..chunks_across {
+            // Tiles are always full size so are padded with nodata past the image edges
+            let chunk_rows = if options.tiled { chunk_height } else { grid.rows };
+            let mut bytes: Vec<u8> = Vec::new();
+            for row in 0..chunk_rows {
+                for col in chunk * chunk_width..(chunk + 1) * chunk_width {
+                    let elev_x10 = if row < grid.rows && col < grid.cols {
+                        grid.get(col, row)
+                    } else {
+                        raster::NODATA_X10
+                    };
+                    encode_sample(elev_x10, options, &mut bytes);
+                }
+            }
+            if options.deflate {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&bytes)?;
+                bytes = encoder.finish()?;
+            }
+
+            chunk_offsets.push(tiff_address(file_buffer.stream_position()?)?);
+            chunk_byte_counts.push(bytes.len() as u32);
+            file_buffer.write_all(&bytes)?;
+        }
+        Ok(())
+    })?;
+
+    /***********************************************************************
+       Write out the IFD describing the image and its georeferencing
+    ************************************************************************/
+
+    let (bits_per_sample, sample_format) = match options.sample_type {
+        SampleType::I16 => (16, 2), // Signed integer
+        SampleType::F32 => (32, 3), // IEEE floating point
+    };
+    let mut fields = image_fields(cols, rows, bits_per_sample, sample_format, options.deflate);
+    fields.extend(georeferencing_fields(
+        cells.min_easting,
+        cells.max_northing,
+        os::ELEVATION_DISTANCE,
+        &nodata_text(options),
+    ));
+    if options.tiled {
+        fields.push(Field::longs(322, &[TILE_SIZE as u32])); // TileWidth
+        fields.push(Field::longs(323, &[TILE_SIZE as u32])); // TileLength
+        fields.push(Field::longs(324, &chunk_offsets)); // TileOffsets
+        fields.push(Field::longs(325, &chunk_byte_counts)); // TileByteCounts
+    } else {
+        fields.push(Field::longs(273, &chunk_offsets)); // StripOffsets
+        fields.push(Field::longs(278, &[ROWS_PER_STRIP as u32])); // RowsPerStrip
+        fields.push(Field::longs(279, &chunk_byte_counts)); // StripByteCounts
+    }
+    write_ifd(&mut file_buffer, fields)
+}
+
+// Writes a raster of 8 bit values (such as a mask) as a GeoTIFF with a single
+// strip, as rasters held in memory are small enough not to need tiling
+pub fn write_geotiff_u8(grid: &Raster<u8>, output_file: &path::Path, deflate: bool) -> Result<(), Box<dyn Error>> {
+    let file = fs::File::create(output_file)?;
+    let mut file_buffer = BufWriter::new(file);
+    file_buffer.write_all(b"II")?;
+    file_buffer.write_all(&42u16.to_le_bytes())?;
+    file_buffer.write_all(&0u32.to_le_bytes())?;
+
+    let mut bytes = grid.values.clone();
+    if deflate {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&bytes)?;
+        bytes = encoder.finish()?;
+    }
+    let strip_offset = tiff_address(file_buffer.stream_position()?)?;
+    file_buffer.write_all(&bytes)?;
+
+    let mut fields = image_fields(grid.cols, grid.rows, 8, 1, deflate); // Unsigned integer
+    fields.extend(georeferencing_fields(
+        grid.min_easting,
+        grid.max_northing(),
+        grid.cell_size,
+        &grid.nodata.to_string(),
+    ));
+    fields.push(Field::longs(273, &[strip_offset])); // StripOffsets
+    fields.push(Field::longs(278, &[grid.rows as u32])); // RowsPerStrip
+    fields.push(Field::longs(279, &[bytes.len() as u32])); // StripByteCounts
+    write_ifd(&mut file_buffer, fields)
+}
+
+// The fields describing a single band image
+fn image_fields(cols: usize, rows: usize, bits_per_sample: u16, sample_format: u16, deflate: bool) -> Vec<Field> {
+    vec![
+        Field::longs(256, &[cols as u32]),                  // ImageWidth
+        Field::longs(257, &[rows as u32]),                  // ImageLength
+        Field::shorts(258, &[bits_per_sample]),             // BitsPerSample
+        Field::shorts(259, &[if deflate { 8 } else { 1 }]), // Compression
+        Field::shorts(262, &[1]),                           // PhotometricInterpretation
+        Field::shorts(277, &[1]),                           // SamplesPerPixel
+        Field::shorts(284, &[1]),                           // PlanarConfiguration
+        Field::shorts(339, &[sample_format]),               // SampleFormat
+    ]
+}
+
+// The fields locating the image on the British National Grid
+fn georeferencing_fields(min_easting: i64, max_northing: i64, cell_size: i64, nodata: &str) -> Vec<Field> {
+    vec![
+        // ModelPixelScale: the cell size
+        Field::doubles(33550, &[cell_size as f64, cell_size as f64, 0.0]),
+        // ModelTiepoint: the top left corner of the image is the north west corner of the region
+        Field::doubles(33922, &[0.0, 0.0, 0.0, min_easting as f64, max_northing as f64, 0.0]),
+        // GeoKeyDirectory: projected, pixels are areas, British National Grid
+        Field::shorts(
+            34735,
+            &[1, 1, 0, 3, 1024, 0, 1, 1, 1025, 0, 1, 1, 3072, 0, 1, EPSG_BRITISH_NATIONAL_GRID],
+        ),
+        // GDAL_NODATA
+        Field::ascii(42113, nodata),
+    ]
+}
+
+// Appends the IFD after the image data and back-fills its address into the header
+fn write_ifd(file_buffer: &mut BufWriter<fs::File>, mut fields: Vec<Field>) -> Result<(), Box<dyn Error>> {
+    // IFD entries must be in ascending tag order
+    fields.sort_by_key(|field| field.tag);
+
+    // The IFD must start on a word boundary
+    if file_buffer.stream_position()? % 2 == 1 {
+        file_buffer.write_all(&[0])?;
+    }
+    let ifd_address = tiff_address(file_buffer.stream_position()?)?;
+
+    // Values longer than four bytes are stored after the IFD with their address in the entry
+    let mut overflow_address = ifd_address as u64 + 2 + fields.len() as u64 * 12 + 4;
+    let mut overflow: Vec<u8> = Vec::new();
+
+    file_buffer.write_all(&(fields.len() as u16).to_le_bytes())?;
+    for field in &fields {
+        file_buffer.write_all(&field.tag.to_le_bytes())?;
+        file_buffer.write_all(&field.field_type.to_le_bytes())?;
+        file_buffer.write_all(&field.count.to_le_bytes())?;
+        if field.value.len() <= 4 {
+            let mut value = field.value.clone();
+            value.resize(4, 0);
+            file_buffer.write_all(&value)?;
+        } else {
+            file_buffer.write_all(&tiff_address(overflow_address)?.to_le_bytes())?;
+            overflow.extend(&field.value);
+            if field.value.len() % 2 == 1 {
+                overflow.push(0);
+            }
+            overflow_address += field.value.len().next_multiple_of(2) as u64;
+        }
+    }
+    // No further IFDs
+    file_buffer.write_all(&0u32.to_le_bytes())?;
+    file_buffer.write_all(&overflow)?;
+
+    // Back-fill the IFD address in the header
+    file_buffer.seek(SeekFrom::Start(TIFF_HEADER_LENGTH - 4))?;
+    file_buffer.write_all(&ifd_address.to_le_bytes())?;
+    file_buffer.flush()?;
+    Ok(())
+}
+
+fn encode_sample(elev_x10: i16, options: &GeoTiffOptions, bytes: &mut Vec<u8>) {
+    match options.sample_type {
+        SampleType::I16 => {
+            let value = if elev_x10 == raster::NODATA_X10 {
+                options.nodata as i16
+            } else {
+                elev_x10
+            };
+            bytes.extend(value.to_le_bytes());
+        }
+        SampleType::F32 => {
+            let value = if elev_x10 == raster::NODATA_X10 {
+                options.nodata
+            } else {
+                elev_x10 as f32 / 10f32
+            };
+            bytes.extend(value.to_le_bytes());
+        }
+    }
+}
+
+fn nodata_text(options: &GeoTiffOptions) -> String {
+    match options.sample_type {
+        SampleType::I16 => (options.nodata as i16).to_string(),
+        SampleType::F32 => options.nodata.to_string(),
+    }
+}
+
+// Classic TIFF addresses are 32 bit
+fn tiff_address(position: u64) -> Result<u32, Box<dyn Error>> {
+    u32::try_from(position).map_err(|_| "The GeoTIFF is too large for a classic TIFF file".into())
+}
@@ -0,0 +1,345 @@
+use crate::geojson;
+use crate::raster::{self, Raster};
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+/***********************************************************************
+    Code for tracing how water flows over the terrain
+
+    1. Depressions are filled using the Priority-Flood method: the cells
+       are flooded inwards from the edges of the region and the coast,
+       always from the lowest cell reached so far. A cell lower than the
+       one it's reached from is raised to just above it, so that every
+       cell (including those on flats) has a downhill path to an outlet.
+    2. Each cell drains to the one of its 8 neighbours down the steepest
+       slope (D8), using the ESRI direction codes.
+    3. The flow accumulation of a cell is the number of cells which drain
+       through it, including itself.
+    4. Streams are the cells draining more than a threshold area, traced
+       into lines which are split where streams join and given their
+       Strahler order.
+************************************************************************/
+
+// The rise given to each filled cell above the one it drains to, so flats still drain
+const FILL_INCREMENT: f64 = 0.000_001;
+
+// D8 direction codes clockwise from east, with their column & row offsets
+pub const DIRECTIONS: [(u8, isize, isize); 8] = [
+    (1, 1, 0),    // E
+    (2, 1, 1),    // SE
+    (4, 0, 1),    // S
+    (8, -1, 1),   // SW
+    (16, -1, 0),  // W
+    (32, -1, -1), // NW
+    (64, 0, -1),  // N
+    (128, 1, -1), // NE
+];
+pub const OUTLET: u8 = 0; // Drains off the edge of the region or into the sea
+pub const NO_DIRECTION: u8 = 255; // Sea cells (the nodata value)
+
+#[derive(Debug, Clone)]
+pub struct Stream {
+    pub points: Vec<(f64, f64)>, // Cell centre eastings & northings from upstream to downstream
+    pub order: u32, // Strahler order
+    pub area: f64, // The area draining to the downstream end in km²
+}
+
+// A cell waiting to be flooded, ordered so the heap gives the lowest first
+struct Flood {
+    elevation: f64,
+    index: usize,
+}
+
+impl PartialEq for Flood {
+    fn eq(&self, other: &Flood) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Flood {}
+
+impl PartialOrd for Flood {
+    fn partial_cmp(&self, other: &Flood) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Flood {
+    fn cmp(&self, other: &Flood) -> Ordering {
+        other.elevation.total_cmp(&self.elevation).then(other.index.cmp(&self.index))
+    }
+}
+
+// Gets the cell index of a neighbour, or None if it's off the edge of the raster
+fn neighbour<T>(grid: &Raster<T>, index: usize, dc: isize, dr: isize) -> Option<usize> {
+    let (col, row) = ((index % grid.cols) as isize + dc, (index / grid.cols) as isize + dr);
+    if col < 0 || row < 0 || col >= grid.cols as isize || row >= grid.rows as isize {
+        None
+    } else {
+        Some(row as usize * grid.cols + col as usize)
+    }
+}
+
+// Fills the depressions in a raster of x10 values, giving elevations in metres.
+// Sea cells are set to the nodata value.
+pub fn fill_depressions(grid: &Raster<i16>) -> Raster<f64> {
+    let mut filled = grid.map(raster::NODATA_METRES as f64, |elev_x10| elev_x10 as f64 / 10.0);
+    let mut flooded = vec![false; filled.values.len()];
+    let mut queue = BinaryHeap::new();
+
+    // Start from the land cells on the edges of the region or next to the sea
+    for (index, is_flooded) in flooded.iter_mut().enumerate() {
+        if grid.values[index] == grid.nodata {
+            continue;
+        }
+        let on_edge = DIRECTIONS.iter().any(|&(_, dc, dr)| match neighbour(grid, index, dc, dr) {
+            Some(next) => grid.values[next] == grid.nodata,
+            None => true,
+        });
+        if on_edge {
+            *is_flooded = true;
+            queue.push(Flood {
+                elevation: filled.values[index],
+                index,
+            });
+        }
+    }
+
+    while let Some(Flood { elevation, index }) = queue.pop() {
+        for &(_, dc, dr) in &DIRECTIONS {
+            if let Some(next) = neighbour(grid, index, dc, dr) {
+                if flooded[next] || grid.values[next] == grid.nodata {
+                    continue;
+                }
+                flooded[next] = true;
+                if filled.values[next] <= elevation {
+                    filled.values[next] = elevation + FILL_INCREMENT;
+                }
+                queue.push(Flood {
+                    elevation: filled.values[next],
+                    index: next,
+                });
+            }
+        }
+    }
+    filled
+}
+
+// Gets the D8 flow direction of every cell of a filled raster
+pub fn flow_directions(filled: &Raster<f64>) -> Raster<u8> {
+    let mut directions = filled.map(NO_DIRECTION, |_| OUTLET);
+    let cell_size = filled.cell_size as f64;
+    for index in 0..filled.values.len() {
+        if filled.values[index] == filled.nodata {
+            continue;
+        }
+        let mut steepest = 0.0;
+        for &(code, dc, dr) in &DIRECTIONS {
+            if let Some(next) = neighbour(filled, index, dc, dr) {
+                if filled.values[next] == filled.nodata {
+                    continue;
+                }
+                let distance = if dc != 0 && dr != 0 { cell_size * 2f64.sqrt() } else { cell_size };
+                let slope = (filled.values[index] - filled.values[next]) / distance;
+                if slope > steepest {
+                    steepest = slope;
+                    directions.values[index] = code;
+                }
+            }
+        }
+    }
+    directions
+}
+
+// Gets the cell a cell drains to, if any
+pub fn downstream(directions: &Raster<u8>, index: usize) -> Option<usize> {
+    let code = directions.values[index];
+    let &(_, dc, dr) = DIRECTIONS.iter().find(|&&(direction, _, _)| direction == code)?;
+    neighbour(directions, index, dc, dr)
+}
+
+// Lists the land cells so that every cell comes after all the cells draining into it
+fn upstream_first(directions: &Raster<u8>) -> Vec<usize> {
+    let mut inflows = vec![0u8; directions.values.len()];
+    for index in 0..directions.values.len() {
+        if let Some(next) = downstream(directions, index) {
+            inflows[next] += 1;
+        }
+    }
+    let mut queue: VecDeque<usize> = (0..directions.values.len())
+        .filter(|&index| inflows[index] == 0 && directions.values[index] != NO_DIRECTION)
+        .collect();
+    let mut order = Vec::with_capacity(directions.values.len());
+    while let Some(index) = queue.pop_front() {
+        order.push(index);
+        if let Some(next) = downstream(directions, index) {
+            inflows[next] -= 1;
+            if inflows[next] == 0 {
+                queue.push_back(next);
+            }
+        }
+    }
+    order
+}
+
+// Gets the number of cells draining through every cell, including itself.
+// Sea cells are 0.
+pub fn flow_accumulation(directions: &Raster<u8>) -> Raster<u32> {
+    let mut accumulation = directions.map(0, |_| 1);
+    for index in upstream_first(directions) {
+        if let Some(next) = downstream(directions, index) {
+            accumulation.values[next] += accumulation.values[index];
+        }
+    }
+    accumulation
+}
+
+// Marks the cells which drain through a cell (including the cell itself) with 1,
+// and all other cells with 0
+pub fn upstream_mask(directions: &Raster<u8>, outlet: usize) -> Raster<u8> {
+    let mut mask = directions.map(0, |_| 0);
+    mask.values[outlet] = 1;
+    let mut queue = VecDeque::from([outlet]);
+    while let Some(index) = queue.pop_front() {
+        for &(_, dc, dr) in &DIRECTIONS {
+            if let Some(next) = neighbour(directions, index, dc, dr) {
+                if mask.values[next] == 0 && downstream(directions, next) == Some(index) {
+                    mask.values[next] = 1;
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    mask
+}
+
+// Traces the cells draining at least the threshold number of cells into stream lines
+pub fn extract_streams(directions: &Raster<u8>, accumulation: &Raster<u32>, threshold: u32) -> Vec<Stream> {
+    let is_stream = |index: usize| accumulation.values[index] >= threshold.max(1);
+
+    // Work out the Strahler order of each stream cell from the top down
+    let mut order = vec![0u32; directions.values.len()];
+    let mut stream_inflows = vec![0u8; directions.values.len()];
+    let mut highest_inflow = vec![(0u32, 0u8); directions.values.len()]; // Highest order flowing in & how many have it
+    for index in upstream_first(directions) {
+        if !is_stream(index) {
+            continue;
+        }
+        order[index] = match highest_inflow[index] {
+            (0, _) => 1,
+            (highest, count) if count > 1 => highest + 1,
+            (highest, _) => highest,
+        };
+        if let Some(next) = downstream(directions, index) {
+            stream_inflows[next] += 1;
+            let (highest, count) = highest_inflow[next];
+            highest_inflow[next] = match order[index].cmp(&highest) {
+                Ordering::Greater => (order[index], 1),
+                Ordering::Equal => (highest, count + 1),
+                Ordering::Less => (highest, count),
+            };
+        }
+    }
+
+    // Streams start at their sources and at junctions, and run down to the next junction
+    let cell_size = directions.cell_size as f64;
+    let centre = |index: usize| {
+        (
+            directions.min_easting as f64 + ((index % directions.cols) as f64 + 0.5) * cell_size,
+            directions.max_northing() as f64 - ((index / directions.cols) as f64 + 0.5) * cell_size,
+        )
+    };
+    let cell_area = cell_size * cell_size / 1_000_000.0;
+    let mut streams = Vec::new();
+    for start in 0..directions.values.len() {
+        if !is_stream(start) || stream_inflows[start] == 1 {
+            continue;
+        }
+        let mut points = vec![centre(start)];
+        let mut end = start;
+        while let Some(next) = downstream(directions, end) {
+            points.push(centre(next));
+            end = next;
+            if stream_inflows[next] > 1 {
+                break;
+            }
+        }
+        if points.len() > 1 {
+            streams.push(Stream {
+                points,
+                order: order[start],
+                area: accumulation.values[end] as f64 * cell_area,
+            });
+        }
+    }
+    streams
+}
+
+// Converts streams to a GeoJSON feature collection of LineStrings with their order & area
+pub fn to_geojson(streams: &[Stream]) -> Value {
+    let features = streams
+        .iter()
+        .map(|stream| {
+            let properties = json!({ "order": stream.order, "area_km2": (stream.area * 100.0).round() / 100.0 });
+            geojson::feature(geojson::line_string(&stream.points), properties)
+        })
+        .collect();
+    geojson::feature_collection(features)
+}
+
+#[cfg(test)]
+fn test_grid(values: &[i16], cols: usize) -> Raster<i16> {
+    use crate::raster::BoundingBox;
+    let rows = values.len() / cols;
+    let bbox = BoundingBox::new(0, 0, cols as i64 * 50, rows as i64 * 50).unwrap();
+    let mut grid = Raster::new(&bbox, raster::NODATA_X10);
+    grid.values.copy_from_slice(values);
+    grid
+}
+
+#[test]
+fn pit_is_filled_and_drains() {
+    #[rustfmt::skip]
+    let grid = test_grid(&[
+        90, 90, 90, 90,
+        90, 10, 50, 90,
+        90, 90, 90, 20,
+    ], 4);
+    let filled = fill_depressions(&grid);
+    // The pit is raised to just above its pour point
+    assert_eq!(5.0, filled.get(2, 1));
+    assert!(filled.get(1, 1) > 5.0 && filled.get(1, 1) < 5.001);
+
+    let directions = flow_directions(&filled);
+    assert_eq!(1, directions.get(1, 1)); // East to the pour point
+    assert_eq!(2, directions.get(2, 1)); // South east to the outlet
+    assert_eq!(OUTLET, directions.get(3, 2));
+
+    let accumulation = flow_accumulation(&directions);
+    assert_eq!(1, accumulation.get(0, 0));
+    assert!(accumulation.get(3, 2) >= 3);
+    assert_eq!(12, accumulation.values.iter().filter(|&&a| a > 0).count());
+}
+
+#[test]
+fn streams_join_with_strahler_order() {
+    // Two valleys meet and flow south
+    #[rustfmt::skip]
+    let grid = test_grid(&[
+        90, 50, 90, 50, 90,
+        90, 40, 90, 40, 90,
+        90, 90, 30, 90, 90,
+        90, 90, 20, 90, 90,
+        90, 90, 10, 90, 90,
+    ], 5);
+    let directions = flow_directions(&fill_depressions(&grid));
+    let accumulation = flow_accumulation(&directions);
+    let streams = extract_streams(&directions, &accumulation, 2);
+    let mut orders: Vec<u32> = streams.iter().map(|stream| stream.order).collect();
+    orders.sort();
+    assert_eq!(vec![1, 1, 2], orders);
+    // The order 2 stream runs from the junction to the outlet
+    let main = streams.iter().find(|stream| stream.order == 2).unwrap();
+    assert_eq!(vec![(125.0, 125.0), (125.0, 75.0), (125.0, 25.0)], main.points);
+}
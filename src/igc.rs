@@ -0,0 +1,214 @@
+use crate::coords::OSCoords;
+use crate::line_of_sight;
+use crate::projection;
+use crate::reader::DataFile;
+use std::error::Error;
+use std::ops::Range;
+
+/***********************************************************************
+    Code for reading the fixes of an IGC flight log (as recorded by
+    paraglider & glider flight recorders) and finding their height above
+    the terrain
+
+    Each fix is a B record, e.g.
+
+      B1101355206343N00006198WA0058700558
+       time  latitude  longitude  pressure & GPS altitudes
+
+    with the latitude & longitude in degrees & thousandths of minutes on
+    WGS84, a validity flag (A for a 3D fix, V for 2D or none) and the
+    altitudes in metres. The GPS altitude is above the geoid, which is
+    within a metre or two of the OS heights. The pressure altitude is
+    referenced to the standard 1013.25hPa, not the day's sea level
+    pressure, so can be out by tens of metres.
+
+    The take-off & landing are on the ground, so the minimum clearance
+    and the fixes below the threshold are only looked for in the flight:
+    from the end of the first leg between fixes faster than TAKE_OFF_SPEED
+    to the start of the last, which leaves out walking about before and
+    after. If no leg is that fast, the whole track is taken as the flight.
+************************************************************************/
+
+pub const DEFAULT_THRESHOLD: f64 = 50.0; // Metres above the terrain
+pub const TAKE_OFF_SPEED: f64 = 5.0; // Ground speed in m/s, faster than walking or running
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AltitudeSource {
+    Gps,
+    Pressure,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    pub time: String, // UTC as HH:MM:SS
+    pub lat: f64,
+    pub lon: f64,
+    pub valid: bool, // A 3D GPS fix
+    pub pressure_altitude: f64,
+    pub gps_altitude: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clearance {
+    pub easting: i64,
+    pub northing: i64,
+    pub terrain: Option<f64>, // None outside the grid or in a sea block without data
+    pub altitude: Option<f64>, // From the chosen source, None for an invalid GPS fix
+    pub agl: Option<f64>, // Height above the terrain
+    pub in_flight: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlightAnalysis {
+    pub clearances: Vec<Clearance>, // One for each fix
+    pub minimum: Option<usize>, // The fix with the least clearance in flight
+    pub below: Vec<usize>, // The fixes in flight below the threshold
+}
+
+// Gets the fixes of the B records of an IGC file
+pub fn parse_fixes(igc: &str) -> Result<Vec<Fix>, Box<dyn Error>> {
+    igc.lines()
+        .enumerate()
+        .filter(|(_, line)| line.starts_with('B'))
+        .map(|(i, line)| parse_fix(line.trim_end()).map_err(|e| format!("Line {} of the IGC file: {}", i + 1, e).into()))
+        .collect()
+}
+
+fn parse_fix(line: &str) -> Result<Fix, String> {
+    let field = |start: usize, end: usize| line.get(start..end).ok_or_else(|| format!("The B record {:?} is too short", line));
+    let number = |start: usize, end: usize| -> Result<f64, String> {
+        let text = field(start, end)?;
+        text.parse().map_err(|_| format!("{:?} in the B record {:?} is not a number", text, line))
+    };
+    // Degrees, then minutes to 3 decimal places
+    let angle = |start: usize, degree_digits: usize, positive: char, negative: char| -> Result<f64, String> {
+        let degrees = number(start, start + degree_digits)?;
+        let minutes = number(start + degree_digits, start + degree_digits + 5)? / 1000.0;
+        if minutes >= 60.0 {
+            return Err(format!("The B record {:?} has over 60 minutes", line));
+        }
+        let sign = match field(start + degree_digits + 5, start + degree_digits + 6)?.chars().next() {
+            Some(c) if c == positive => 1.0,
+            Some(c) if c == negative => -1.0,
+            _ => return Err(format!("The B record {:?} has an invalid hemisphere", line)),
+        };
+        Ok(sign * (degrees + minutes / 60.0))
+    };
+
+    let time = field(1, 7)?;
+    if !time.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(format!("The B record {:?} has an invalid time", line));
+    }
+    let (lat, lon) = (angle(7, 2, 'N', 'S')?, angle(15, 3, 'E', 'W')?);
+    if lat.abs() > 90.0 || lon.abs() > 180.0 {
+        return Err(format!("The B record {:?} has an invalid latitude & longitude", line));
+    }
+    Ok(Fix {
+        time: format!("{}:{}:{}", &time[0..2], &time[2..4], &time[4..6]),
+        lat,
+        lon,
+        valid: field(24, 25)? == "A",
+        pressure_altitude: number(25, 30)?,
+        gps_altitude: number(30, 35)?,
+    })
+}
+
+// Finds the height of each fix above the terrain, the least clearance in flight
+// and the fixes in flight below the threshold
+pub fn analyse(data: &mut DataFile, fixes: &[Fix], source: AltitudeSource, threshold: f64) -> Result<FlightAnalysis, Box<dyn Error>> {
+    let mut clearances: Vec<Clearance> = fixes
+        .iter()
+        .map(|fix| {
+            let (easting, northing) = projection::wgs84_to_os(fix.lat, fix.lon);
+            let altitude = match source {
+                AltitudeSource::Gps => fix.valid.then_some(fix.gps_altitude),
+                AltitudeSource::Pressure => Some(fix.pressure_altitude),
+            };
+            Clearance {
+                easting: easting.round() as i64,
+                northing: northing.round() as i64,
+                terrain: None,
+                altitude,
+                agl: None,
+                in_flight: false,
+            }
+        })
+        .collect();
+
+    // Look up the fixes with data together, so each data block is read once
+    let mut inside = Vec::new();
+    for (i, clearance) in clearances.iter().enumerate() {
+        if data.data_block_address(clearance.easting, clearance.northing)?.is_some() {
+            inside.push(i);
+        }
+    }
+    let locations: Vec<OSCoords> = inside
+        .iter()
+        .map(|&i| OSCoords {
+            easting: clearances[i].easting,
+            northing: clearances[i].northing,
+            elevation: None,
+        })
+        .collect();
+    for (&i, coord) in inside.iter().zip(data.read_elevations_batch(&locations)?) {
+        let clearance = &mut clearances[i];
+        clearance.terrain = coord.elevation.map(|elevation| elevation as f64);
+        clearance.agl = clearance.altitude.zip(clearance.terrain).map(|(altitude, terrain)| altitude - terrain);
+    }
+
+    let mut minimum: Option<usize> = None;
+    let mut below = Vec::new();
+    for i in flight(fixes) {
+        clearances[i].in_flight = true;
+        let agl = match clearances[i].agl {
+            Some(agl) => agl,
+            None => continue,
+        };
+        if minimum.is_none_or(|minimum| agl < clearances[minimum].agl.unwrap_or(f64::INFINITY)) {
+            minimum = Some(i);
+        }
+        if agl < threshold {
+            below.push(i);
+        }
+    }
+    Ok(FlightAnalysis { clearances, minimum, below })
+}
+
+// Gets the fixes from take-off to landing (see the top of the file)
+fn flight(fixes: &[Fix]) -> Range<usize> {
+    // The legs are numbered by the fix they end at
+    let fast = |&i: &usize| {
+        let (from, to) = (&fixes[i - 1], &fixes[i]);
+        // Allowing for flights over midnight UTC
+        let seconds = (seconds_of_day(&to.time) - seconds_of_day(&from.time)).rem_euclid(86_400);
+        let north = (to.lat - from.lat).to_radians();
+        let east = (to.lon - from.lon).to_radians() * ((to.lat + from.lat) / 2.0).to_radians().cos();
+        seconds > 0 && line_of_sight::EARTH_RADIUS * north.hypot(east) / seconds as f64 > TAKE_OFF_SPEED
+    };
+    match ((1..fixes.len()).find(fast), (1..fixes.len()).rfind(fast)) {
+        (Some(first), Some(last)) => first..last.max(first + 1),
+        _ => 0..fixes.len(),
+    }
+}
+
+// Converts a fix's HH:MM:SS time to seconds
+fn seconds_of_day(time: &str) -> i64 {
+    time.split(':').fold(0, |seconds, part| seconds * 60 + part.parse::<i64>().unwrap_or(0))
+}
+
+#[test]
+fn b_records() {
+    let igc = "AXXXABC\nHFDTE150725\nB1101355206343N00006198WA0058700558\nB1101365206343S00006198EV-001200000\nLXXX comment\n";
+    let fixes = parse_fixes(igc).unwrap();
+    assert_eq!(2, fixes.len());
+    assert_eq!("11:01:35", fixes[0].time);
+    assert!((fixes[0].lat - (52.0 + 6.343 / 60.0)).abs() < 1e-9);
+    assert!((fixes[0].lon + 6.198 / 60.0).abs() < 1e-9);
+    assert!(fixes[0].valid && !fixes[1].valid);
+    assert_eq!((587.0, 558.0), (fixes[0].pressure_altitude, fixes[0].gps_altitude));
+    assert_eq!(-12.0, fixes[1].pressure_altitude);
+    assert!(fixes[1].lat < 0.0 && fixes[1].lon > 0.0);
+
+    let error = parse_fixes("B1101355206343N00006198WA00587").unwrap_err();
+    assert!(error.to_string().starts_with("Line 1 of the IGC file"));
+}
@@ -0,0 +1,132 @@
+use crate::os;
+use crate::raster::Raster;
+use std::collections::VecDeque;
+
+/***********************************************************************
+    Code for mapping the land flooded by a rise in sea level
+
+    A plain threshold on elevation would flood every hollow below the
+    water level, however far inland, so the water is spread out from the
+    sea instead. The sea is every cell without data (the 10km² blocks with
+    no data address) plus the 0m cells on the coast, as the OS data holds
+    the sea as 0m within coastal blocks: those joined to a cell without
+    data, or to the edge of the region, through other 0m cells. Inland 0m
+    cells are land. The water then floods outwards into every neighbouring
+    cell (8-connected) at or below the water level, including land below
+    sea level such as the Fens. NB: the water cannot reach a low area
+    through land outside the region, so choose a region which reaches the
+    open sea.
+************************************************************************/
+
+// Mask values
+pub const DRY: u8 = 0;
+pub const FLOODED: u8 = 1;
+pub const SEA: u8 = 255; // Sea cells (the mask's nodata value)
+
+// Marks the cells of a raster of x10 values flooded by the sea at a water level in metres
+pub fn inundate(grid: &Raster<i16>, water_level: f64) -> Raster<u8> {
+    let mut mask = grid.map(SEA, |_| DRY);
+    let floods = |value: i16| value as f64 / 10.0 <= water_level;
+
+    let sea_level = |col: usize, row: usize| !grid.is_nodata(col, row) && grid.get(col, row) == 0 && floods(0);
+
+    // Start from the cells without data and the 0m cells on the edge of the region
+    let mut queue = VecDeque::new();
+    for row in 0..grid.rows {
+        for col in 0..grid.cols {
+            let edge = row == 0 || col == 0 || row == grid.rows - 1 || col == grid.cols - 1;
+            if grid.is_nodata(col, row) || (edge && sea_level(col, row)) {
+                mask.set(col, row, SEA);
+                queue.push_back((col, row));
+            }
+        }
+    }
+
+    // Then add the 0m cells joined to them, which are the sea along the coast
+    let mut sea = queue.clone();
+    while let Some((col, row)) = sea.pop_front() {
+        for (c, r) in neighbours(grid, col, row) {
+            if mask.get(c, r) == DRY && sea_level(c, r) {
+                mask.set(c, r, SEA);
+                sea.push_back((c, r));
+                queue.push_back((c, r));
+            }
+        }
+    }
+
+    // And flood from the sea
+    while let Some((col, row)) = queue.pop_front() {
+        for (c, r) in neighbours(grid, col, row) {
+            if mask.get(c, r) == DRY && floods(grid.get(c, r)) {
+                mask.set(c, r, FLOODED);
+                queue.push_back((c, r));
+            }
+        }
+    }
+    mask
+}
+
+// Gets the cells around a cell (8-connected) within the raster
+fn neighbours<T>(grid: &Raster<T>, col: usize, row: usize) -> impl Iterator<Item = (usize, usize)> {
+    let (cols, rows) = (grid.cols as isize, grid.rows as isize);
+    (-1..=1isize)
+        .flat_map(move |dr| (-1..=1isize).map(move |dc| (col as isize + dc, row as isize + dr)))
+        .filter(move |&(c, r)| c >= 0 && r >= 0 && c < cols && r < rows && (c, r) != (col as isize, row as isize))
+        .map(|(c, r)| (c as usize, r as usize))
+}
+
+// Gets the flooded area in km² within each 100km² grid which has any flooding,
+// in the order of os::GRID_100
+pub fn flooded_area_by_grid(mask: &Raster<u8>) -> Vec<(&'static str, f64)> {
+    let mut cells = [0usize; os::GRID_100.len()];
+    let cell_size = mask.cell_size as f64;
+    for row in 0..mask.rows {
+        let northing = mask.max_northing() - (row as i64 + 1) * mask.cell_size;
+        for col in 0..mask.cols {
+            if mask.get(col, row) != FLOODED {
+                continue;
+            }
+            let easting = mask.min_easting + col as i64 * mask.cell_size;
+            let grid_index = (northing / os::METRES_IN_100_GRID) * os::GRIDS_PER_ROW_100 + easting / os::METRES_IN_100_GRID;
+            cells[grid_index as usize] += 1;
+        }
+    }
+    let cell_area = cell_size * cell_size / 1_000_000.0;
+    os::GRID_100
+        .iter()
+        .zip(cells)
+        .filter(|&(_, count)| count > 0)
+        .map(|(&grid, count)| (grid, count as f64 * cell_area))
+        .collect()
+}
+
+#[test]
+fn only_low_land_connected_to_the_sea_floods() {
+    use crate::raster::{self, BoundingBox};
+    // Sea, a low coastal strip, a ridge, then an inland hollow below the water level
+    let bbox = BoundingBox::new(0, 0, 300, 50).unwrap();
+    let mut grid = Raster::new(&bbox, raster::NODATA_X10);
+    grid.values.copy_from_slice(&[raster::NODATA_X10, 10, 20, 50, 10, 20]);
+
+    let mask = inundate(&grid, 2.0);
+    assert_eq!(vec![SEA, FLOODED, FLOODED, DRY, DRY, DRY], mask.values);
+    assert_eq!(vec![("SV", 0.005)], flooded_area_by_grid(&mask));
+
+    // Sea level cells count as sea
+    grid.values[0] = 0;
+    assert_eq!(vec![SEA, FLOODED, FLOODED, DRY, DRY, DRY], inundate(&grid, 2.0).values);
+    assert_eq!(vec![DRY; 6], inundate(&grid, -1.0).values);
+
+    // 0m on the coast is sea, and the land below sea level behind it floods, but a 0m
+    // hollow behind a ridge is land until the water rises over the ridge
+    let bbox = BoundingBox::new(0, 0, 300, 150).unwrap();
+    let mut grid = Raster::new(&bbox, raster::NODATA_X10);
+    let n = raster::NODATA_X10;
+    grid.values.copy_from_slice(&[
+        20, 20, 20, 20, 20, 20,
+        n,   0, -5, 10,  0, 20,
+        20, 20, 20, 20, 20, 20,
+    ]);
+    assert_eq!(vec![SEA, SEA, FLOODED, DRY, DRY, DRY], inundate(&grid, 0.5).values[6..12]);
+    assert_eq!(vec![SEA, SEA, FLOODED, FLOODED, FLOODED, DRY], inundate(&grid, 1.0).values[6..12]);
+}
@@ -0,0 +1,339 @@
+use crate::coords::OSCoords;
+use crate::profile::{self, ProfileSummary};
+use crate::projection;
+use crate::reader::{self, DataFile};
+use crate::xml::{self, Element};
+use std::error::Error;
+use std::io::{Read, Write};
+use std::{fs, path};
+use zip::write::FileOptions;
+use zip::CompressionMethod;
+
+/***********************************************************************
+    Code for reading the Point & LineString Placemarks of KML and KMZ
+    files (e.g. routes from Google Earth) and writing them back with
+    heights from the binary file
+
+    The KML is edited as text rather than rebuilt, so styles, folders and
+    anything else in the file come through untouched. Each Point and
+    LineString position gets the elevation as its altitude, with an
+    altitudeMode of absolute, and each Placemark's description gets a
+    line summarising its elevation profile (replacing the line added by an
+    earlier run). The OS heights are above mean sea level, which is what
+    absolute altitudes in KML are measured from. A Point or LineString
+    which reaches outside the National Grid is left unchanged, as it can't
+    be given a height everywhere.
+
+    A KMZ is a zip holding the KML and any images it uses. Its doc.kml (or
+    else its first .kml file) is read, and any other files are copied
+    across when a KMZ is written.
+************************************************************************/
+
+const SUMMARY_PREFIX: &str = "OS Terrain 50 "; // Starts the description line added to each Placemark
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeometryKind {
+    Point,
+    LineString,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placemark {
+    pub name: String,
+    pub geometries: Vec<(GeometryKind, Vec<(f64, f64)>)>, // The longitude & latitude of each position
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacemarkHeights {
+    pub name: String,
+    pub positions: usize, // Positions given a height
+    pub outside: usize, // Points & LineStrings left unchanged as they reach outside the grid
+    pub profile: Option<ProfileSummary>, // Of the LineStrings infilled every 50m or so, if there are any
+}
+
+// A Point or LineString in the KML text, with the longitude & latitude text of each position
+struct Geometry {
+    kind: GeometryKind,
+    element: Element,
+    coordinates: Element,
+    positions: Vec<(String, String)>,
+}
+
+// Gets the Point & LineString Placemarks of a KML document
+pub fn placemarks(kml: &str) -> Result<Vec<Placemark>, Box<dyn Error>> {
+    let mut placemarks = Vec::new();
+    for placemark in xml::find_elements(kml, "Placemark", 0, kml.len()) {
+        let mut geometries = Vec::new();
+        for geometry in find_geometries(kml, &placemark)? {
+            let positions = geometry
+                .positions
+                .iter()
+                .map(|position| parse_position(position))
+                .collect::<Result<_, _>>()?;
+            geometries.push((geometry.kind, positions));
+        }
+        placemarks.push(Placemark {
+            name: placemark_name(kml, &placemark),
+            geometries,
+        });
+    }
+    Ok(placemarks)
+}
+
+// Gets a copy of a KML document with heights added to the Point & LineString positions
+// and a profile summary added to the description of each Placemark
+pub fn add_heights(data: &mut DataFile, kml: &str) -> Result<(String, Vec<PlacemarkHeights>), Box<dyn Error>> {
+    let mut edits: Vec<(usize, usize, String)> = Vec::new(); // Replace start..end with the text
+    let mut results = Vec::new();
+    for placemark in xml::find_elements(kml, "Placemark", 0, kml.len()) {
+        let name = placemark_name(kml, &placemark);
+        let geometries = find_geometries(kml, &placemark)?;
+        if geometries.is_empty() {
+            continue;
+        }
+
+        let mut positions = 0;
+        let mut outside = 0;
+        let mut vertex_elevations = Vec::new();
+        let mut profile: Option<ProfileSummary> = None;
+        for geometry in &geometries {
+            let coords = geometry
+                .positions
+                .iter()
+                .map(|position| to_grid(position))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("Placemark {:?}: {}", name, e))?;
+            if coords.is_empty() {
+                continue;
+            }
+            if !coords.iter().all(|coord| reader::in_grid(coord.easting, coord.northing)) {
+                outside += 1;
+                continue;
+            }
+            let elevations = data.read_elevations(&coords, false)?;
+            vertex_elevations.extend(elevations.iter().map(|coord| coord.elevation.unwrap_or_default()));
+            positions += coords.len();
+
+            // Write the positions with their heights, keeping the longitude & latitude text as it was
+            let coordinates: Vec<String> = geometry
+                .positions
+                .iter()
+                .zip(&elevations)
+                .map(|((lon, lat), coord)| format!("{},{},{:.1}", lon, lat, coord.elevation.unwrap_or_default()))
+                .collect();
+            edits.push((geometry.coordinates.content_start, geometry.coordinates.content_end, coordinates.join(" ")));
+            edits.push(altitude_mode_edit(kml, geometry));
+
+            if geometry.kind == GeometryKind::LineString && coords.len() > 1 {
+                if let Some(summary) = profile::summarise(&data.read_elevations(&coords, true)?) {
+                    profile = Some(match profile {
+                        Some(profile) => profile.combine(&summary),
+                        None => summary,
+                    });
+                }
+            }
+        }
+
+        let summary = match (&profile, vertex_elevations.first()) {
+            (Some(profile), _) => format!(
+                "{}profile: {:.2}km, ascent {:.0}m, descent {:.0}m, lowest {:.1}m, highest {:.1}m",
+                SUMMARY_PREFIX,
+                profile.distance / 1000.0,
+                profile.ascent,
+                profile.descent,
+                profile.min,
+                profile.max
+            ),
+            (None, Some(elevation)) => format!("{}elevation: {:.1}m", SUMMARY_PREFIX, elevation),
+            (None, None) => String::new(),
+        };
+        if !summary.is_empty() {
+            edits.push(description_edit(kml, &placemark, &summary));
+        }
+        results.push(PlacemarkHeights {
+            name,
+            positions,
+            outside,
+            profile,
+        });
+    }
+
+    Ok((xml::apply_edits(kml, edits), results))
+}
+
+// Reads the KML from a .kml file, or from the first .kml file in a .kmz
+pub fn read_file(input_file: &path::Path) -> Result<String, Box<dyn Error>> {
+    if !is_kmz(input_file) {
+        return Ok(fs::read_to_string(input_file)?);
+    }
+    let mut archive = zip::ZipArchive::new(fs::File::open(input_file)?)?;
+    let entry = kml_entry(&mut archive)?;
+    let mut kml = String::new();
+    archive.by_name(&entry)?.read_to_string(&mut kml)?;
+    Ok(kml)
+}
+
+// Writes KML to a .kml file, or to a .kmz along with any other files from the input KMZ
+pub fn write_file(output_file: &path::Path, kml: &str, input_file: &path::Path) -> Result<(), Box<dyn Error>> {
+    if !is_kmz(output_file) {
+        fs::write(output_file, kml)?;
+        return Ok(());
+    }
+    let mut archive = match is_kmz(input_file) {
+        true => Some(zip::ZipArchive::new(fs::File::open(input_file)?)?),
+        false => None,
+    };
+    let entry = match archive.as_mut() {
+        Some(archive) => kml_entry(archive)?,
+        None => "doc.kml".to_string(),
+    };
+
+    // The KML goes first as readers use the first .kml file in the archive
+    let mut writer = zip::ZipWriter::new(fs::File::create(output_file)?);
+    writer.start_file(entry.as_str(), FileOptions::default().compression_method(CompressionMethod::Deflated))?;
+    writer.write_all(kml.as_bytes())?;
+    if let Some(archive) = archive.as_mut() {
+        for i in 0..archive.len() {
+            let file = archive.by_index_raw(i)?;
+            if file.name() != entry {
+                writer.raw_copy_file(file)?;
+            }
+        }
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn is_kmz(file: &path::Path) -> bool {
+    file.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("kmz"))
+}
+
+// Gets the name of the KML file in a KMZ: doc.kml, or else the first .kml file in archive order
+fn kml_entry<R: Read + std::io::Seek>(archive: &mut zip::ZipArchive<R>) -> Result<String, Box<dyn Error>> {
+    let mut first = None;
+    for i in 0..archive.len() {
+        let name = archive.by_index_raw(i)?.name().to_string();
+        if name == "doc.kml" {
+            return Ok(name);
+        }
+        if first.is_none() && name.to_ascii_lowercase().ends_with(".kml") {
+            first = Some(name);
+        }
+    }
+    Ok(first.ok_or("The KMZ file holds no .kml file")?)
+}
+
+// Finds the Point & LineString elements in a Placemark, including those in a MultiGeometry
+fn find_geometries(kml: &str, placemark: &Element) -> Result<Vec<Geometry>, Box<dyn Error>> {
+    let mut geometries = Vec::new();
+    for (kind, name) in [(GeometryKind::Point, "Point"), (GeometryKind::LineString, "LineString")] {
+        for element in xml::find_elements(kml, name, placemark.content_start, placemark.content_end) {
+            let coordinates = xml::find_element(kml, "coordinates", element.content_start, element.content_end)
+                .ok_or_else(|| format!("A {} has no coordinates", name))?;
+            let positions = kml[coordinates.content_start..coordinates.content_end]
+                .split_whitespace()
+                .map(|tuple| {
+                    let mut values = tuple.split(',');
+                    match (values.next(), values.next()) {
+                        (Some(lon), Some(lat)) => Ok((lon.to_string(), lat.to_string())),
+                        _ => Err(format!("The KML position {:?} is not longitude,latitude[,altitude]", tuple)),
+                    }
+                })
+                .collect::<Result<_, _>>()?;
+            geometries.push(Geometry {
+                kind,
+                element,
+                coordinates,
+                positions,
+            });
+        }
+    }
+    // Keep the order they appear in
+    geometries.sort_by_key(|geometry| geometry.element.start);
+    Ok(geometries)
+}
+
+// Sets a geometry's altitudeMode to absolute, replacing any altitudeMode or gx:altitudeMode
+fn altitude_mode_edit(kml: &str, geometry: &Geometry) -> (usize, usize, String) {
+    let (start, end) = (geometry.element.content_start, geometry.element.content_end);
+    let mode = "<altitudeMode>absolute</altitudeMode>".to_string();
+    if let Some(element) = xml::find_element(kml, "altitudeMode", start, end) {
+        return (element.content_start, element.content_end, "absolute".to_string());
+    }
+    if let Some(element) = xml::find_element(kml, "gx:altitudeMode", start, end) {
+        return (element.start, element.end, mode);
+    }
+    // altitudeMode comes just before the coordinates
+    (geometry.coordinates.start, geometry.coordinates.start, mode)
+}
+
+// Adds the summary line to a Placemark's description, replacing any from an earlier run
+fn description_edit(kml: &str, placemark: &Element, summary: &str) -> (usize, usize, String) {
+    match xml::find_element(kml, "description", placemark.content_start, placemark.content_end) {
+        Some(description) if description.content_start < description.content_end => {
+            let mut text = &kml[description.content_start..description.content_end];
+            if let Some(previous) = text.rfind(SUMMARY_PREFIX) {
+                text = text[..previous].trim_end();
+            }
+            let separator = if text.is_empty() { "" } else { "\n" };
+            (description.content_start, description.content_end, format!("{}{}{}", text, separator, xml::escape(summary)))
+        }
+        Some(description) => (description.start, description.end, format!("<description>{}</description>", xml::escape(summary))),
+        None => {
+            // The description follows the name if there is one
+            let position = xml::find_element(kml, "name", placemark.content_start, placemark.content_end)
+                .map_or(placemark.content_start, |name| name.end);
+            (position, position, format!("<description>{}</description>", xml::escape(summary)))
+        }
+    }
+}
+
+fn placemark_name(kml: &str, placemark: &Element) -> String {
+    xml::find_element(kml, "name", placemark.content_start, placemark.content_end)
+        .map(|name| xml::unescape(kml[name.content_start..name.content_end].trim()))
+        .unwrap_or_default()
+}
+
+fn parse_position((lon, lat): &(String, String)) -> Result<(f64, f64), Box<dyn Error>> {
+    match (lon.trim().parse::<f64>(), lat.trim().parse::<f64>()) {
+        (Ok(lon), Ok(lat)) if (-180.0..=180.0).contains(&lon) && (-90.0..=90.0).contains(&lat) => Ok((lon, lat)),
+        _ => Err(format!("{},{} is not a valid longitude & latitude", lon, lat).into()),
+    }
+}
+
+fn to_grid(position: &(String, String)) -> Result<OSCoords, Box<dyn Error>> {
+    let (lon, lat) = parse_position(position)?;
+    let (easting, northing) = projection::wgs84_to_os(lat, lon);
+    Ok(OSCoords {
+        easting: easting.round() as i64,
+        northing: northing.round() as i64,
+        elevation: None,
+    })
+}
+
+#[test]
+fn finds_placemarks() {
+    let kml = r#"<kml><Document><Style id="s"><PointStyle/></Style>
+        <Placemark><name>Tom &amp; Jerry</name><Point><coordinates>-1.8,51.5,0</coordinates></Point></Placemark>
+        <Placemark><name><![CDATA[Walk]]></name><MultiGeometry>
+          <LineString><tessellate>1</tessellate><coordinates>
+            -1.8,51.5 -1.7,51.6
+          </coordinates></LineString>
+          <Point><coordinates>-1.6,51.7</coordinates></Point>
+        </MultiGeometry></Placemark>
+        <Placemark><name>Area</name><Polygon/></Placemark>
+        </Document></kml>"#;
+    let placemarks = placemarks(kml).unwrap();
+    assert_eq!(3, placemarks.len());
+    assert_eq!("Tom & Jerry", placemarks[0].name);
+    assert_eq!(vec![(GeometryKind::Point, vec![(-1.8, 51.5)])], placemarks[0].geometries);
+    assert_eq!("Walk", placemarks[1].name);
+    assert_eq!(
+        vec![
+            (GeometryKind::LineString, vec![(-1.8, 51.5), (-1.7, 51.6)]),
+            (GeometryKind::Point, vec![(-1.6, 51.7)])
+        ],
+        placemarks[1].geometries
+    );
+    assert!(placemarks[2].geometries.is_empty());
+}
@@ -0,0 +1,42 @@
+/****************************************************************************************
+   Builds and reads the OS Terrain 50 simple binary elevation data file
+
+   See the README.md for a full description of the file format
+****************************************************************************************/
+
+pub mod activity;
+pub mod ascii_grid;
+pub mod cache;
+pub mod catchment;
+pub mod contours;
+pub mod coords;
+pub mod derivatives;
+pub mod drape;
+pub mod enrich;
+pub mod fit;
+pub mod geojson;
+pub mod geotiff;
+pub mod hydrology;
+pub mod igc;
+pub mod inundation;
+pub mod kml;
+pub mod line_of_sight;
+pub mod mapped;
+pub mod os;
+pub mod output;
+pub mod polygonize;
+pub mod profile;
+pub mod projection;
+pub mod radio;
+pub mod raster;
+pub mod reader;
+pub mod render;
+pub mod server;
+pub mod summits;
+pub mod tcx;
+pub mod terrain_tiles;
+pub mod unzip;
+pub mod utils;
+pub mod viewshed;
+pub mod xml;
+pub mod zonal;
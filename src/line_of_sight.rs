@@ -0,0 +1,135 @@
+use crate::coords::{self, OSCoords};
+use crate::reader::DataFile;
+use std::error::Error;
+
+/***********************************************************************
+    Code for checking the line of sight between two locations
+
+    The ground between the observer and the target is sampled every 50m
+    using the same infill stepping as the elevation profiles. The target
+    is visible if no sample rises above the straight sight line between
+    the observer's eye and the target.
+
+    Over longer distances the curve of the earth raises the ground between
+    the two ends relative to the sight line. This can be allowed for, along
+    with atmospheric refraction which bends the sight line back down
+    towards the ground and so reduces the effect of the curvature.
+************************************************************************/
+
+pub const EARTH_RADIUS: f64 = 6_371_000.0; // Mean radius in metres
+pub const STANDARD_REFRACTION: f64 = 0.13; // Typical refraction coefficient for visible light
+
+#[derive(Debug, Clone, Copy)]
+pub struct SightOptions {
+    pub observer_height: f64, // Height of the observer's eye above the ground in metres
+    pub target_height: f64, // Height of the target above the ground in metres
+    pub earth_curvature: bool,
+    pub refraction: f64, // Refraction coefficient, only used with earth curvature
+}
+
+impl Default for SightOptions {
+    fn default() -> SightOptions {
+        SightOptions {
+            observer_height: 1.7,
+            target_height: 0.0,
+            earth_curvature: false,
+            refraction: STANDARD_REFRACTION,
+        }
+    }
+}
+
+// A sample of the ground along a path between two locations
+#[derive(Debug, Clone, Copy)]
+pub struct ProfilePoint {
+    pub easting: i64,
+    pub northing: i64,
+    pub distance: f64, // From the start of the path in metres
+    pub elevation: f64, // In metres, with sea areas at 0m
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Obstruction {
+    pub point: ProfilePoint,
+    pub height_above_sight_line: f64, // Including any earth curvature correction
+}
+
+#[derive(Debug, Clone)]
+pub struct LineOfSight {
+    pub visible: bool,
+    pub distance: f64, // Between the observer and the target in metres
+    pub first_obstruction: Option<Obstruction>, // The obstruction nearest the observer
+}
+
+// Gets the ground elevations at 50m intervals from the start to the end of a path
+pub fn path_profile(data: &mut DataFile, start: OSCoords, end: OSCoords) -> Result<Vec<ProfilePoint>, Box<dyn Error>> {
+    coords::get_infills(start, end, true)
+        .iter()
+        .map(|location| {
+            let elev_x10 = data.read_elevation_x10(location.easting, location.northing)?;
+            let (de, dn) = ((location.easting - start.easting) as f64, (location.northing - start.northing) as f64);
+            Ok(ProfilePoint {
+                easting: location.easting,
+                northing: location.northing,
+                distance: (de * de + dn * dn).sqrt(),
+                elevation: elev_x10.unwrap_or(0) as f64 / 10.0,
+            })
+        })
+        .collect()
+}
+
+// Gets how far the earth's surface rises above the straight line between two
+// points on it, at the given distances from each end. A refraction coefficient
+// of 0 gives the geometric curvature.
+pub fn earth_bulge(distance_1: f64, distance_2: f64, refraction: f64) -> f64 {
+    let effective_radius = EARTH_RADIUS / (1.0 - refraction);
+    distance_1 * distance_2 / (2.0 * effective_radius)
+}
+
+// Checks whether a target can be seen from an observer
+pub fn line_of_sight(
+    data: &mut DataFile,
+    observer: OSCoords,
+    target: OSCoords,
+    options: &SightOptions,
+) -> Result<LineOfSight, Box<dyn Error>> {
+    let profile = path_profile(data, observer, target)?;
+    let (first, last) = (profile[0], profile[profile.len() - 1]);
+    let eye = first.elevation + options.observer_height;
+    let target_top = last.elevation + options.target_height;
+    let distance = last.distance;
+
+    // Only the samples between the two ends can block the view
+    let mut first_obstruction = None;
+    for point in &profile[1..profile.len() - 1] {
+        let sight_line = eye + (target_top - eye) * point.distance / distance;
+        let bulge = if options.earth_curvature {
+            earth_bulge(point.distance, distance - point.distance, options.refraction)
+        } else {
+            0.0
+        };
+        let height_above_sight_line = point.elevation + bulge - sight_line;
+        if height_above_sight_line > 0.0 {
+            first_obstruction = Some(Obstruction {
+                point: *point,
+                height_above_sight_line,
+            });
+            break;
+        }
+    }
+
+    Ok(LineOfSight {
+        visible: first_obstruction.is_none(),
+        distance,
+        first_obstruction,
+    })
+}
+
+#[test]
+fn earth_bulge_over_long_distances() {
+    // Around 1.96m at the middle of a 10km line without refraction
+    let bulge = earth_bulge(5_000.0, 5_000.0, 0.0);
+    assert!((bulge - 1.962).abs() < 0.001, "Bulge {}", bulge);
+    // Refraction flattens the curve
+    assert!(earth_bulge(5_000.0, 5_000.0, STANDARD_REFRACTION) < bulge);
+    assert_eq!(0.0, earth_bulge(0.0, 10_000.0, 0.0));
+}
@@ -1,8 +1,6 @@
-mod os;
-mod output;
-mod unzip;
-mod utils;
+mod commands;
 
+use osterrain50::{output, unzip, utils};
 use std::time::Instant;
 use std::{error::Error, env, path, string};
 
@@ -11,7 +9,8 @@ use std::{error::Error, env, path, string};
 
    Args are either:
      - an OS data zip file containing the zipped elevation data, or
-     - a directory containing already-unzipped elevation data
+     - a directory containing already-unzipped elevation data, or
+     - a command name followed by the command args (see commands.rs)
 ****************************************************************************************/
 
 struct ArgsTypes {
@@ -23,6 +22,14 @@ fn main() {
     let args: Vec<_> = env::args().collect();
     let start_time = Instant::now();
 
+    // Run any command which reads an existing binary data file
+    if let Some(outcome) = commands::run(&args) {
+        if let Err(e) = outcome {
+            utils::die(e);
+        }
+        return;
+    }
+
     // Sanity-check the supplied args
     match args_check(&args) {
         Ok(args) => {
@@ -96,8 +103,11 @@ Usage:
 
 2) {} <directory>   : Creates a binary data file from an OS Terrain 50 data directory.
 
+Commands for reading an existing binary data file:
 ",
         app_name, app_name
     );
+    commands::show_usage(app_name);
+    eprintln!();
     Ok(())
 }
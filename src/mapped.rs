@@ -0,0 +1,105 @@
+use crate::coords::OSCoords;
+use crate::os;
+use crate::reader;
+use memmap2::Mmap;
+use std::error::Error;
+use std::fs::File;
+use std::path;
+
+/***********************************************************************
+    A reader which memory-maps the OS binary file
+
+    The file is mapped into memory once and the header addresses and
+    elevations are then read by indexing into it, with no seeks, reads or
+    copying through a buffer. The operating system pages the file in as
+    it's used, so only the parts of the file touched are read from disk.
+    This makes long runs of lookups (e.g. infilled profiles) much faster
+    than with DataFile, which seeks & reads twice for every location.
+
+    The offsets are worked out exactly as for DataFile (see reader.rs).
+
+    All the reads take &self, and the mapping is Send + Sync, so a single
+    MappedDataFile can be shared between threads in an Arc without a lock.
+************************************************************************/
+
+pub struct MappedDataFile {
+    map: Mmap,
+}
+
+impl MappedDataFile {
+    pub fn open(data_file: &str) -> Result<MappedDataFile, Box<dyn Error>> {
+        let file_path = path::Path::new(&data_file);
+        if !file_path.is_file() {
+            return Err(format!("The data file path {} is not valid", &data_file).into());
+        }
+        let file = File::open(file_path)?;
+        // SAFETY: the data file is only ever read once built. As with any mapped file,
+        // changing it on disk while it's mapped would change the values read.
+        let map = unsafe { Mmap::map(&file)? };
+
+        let header_length = os::FILE_SIG.len() as i64 + os::GRID_100.len() as i64 * os::HEADER_BLOCK_LENGTH;
+        if map.len() < header_length as usize || &map[..os::FILE_SIG.len()] != os::FILE_SIG {
+            return Err(format!("The data file {} is not an OS Terrain 50 binary data file", &data_file).into());
+        }
+        Ok(MappedDataFile { map })
+    }
+
+    // Returns the address of the 10km² data block holding the location,
+    // or None if there is no data for it (i.e. it's a sea area)
+    pub fn data_block_address(&self, easting: i64, northing: i64) -> Result<Option<u64>, Box<dyn Error>> {
+        if !reader::in_grid(easting, northing) {
+            return Ok(None);
+        }
+        let offset = reader::data_block_address_offset(easting, northing) as usize;
+        let address = u32::from_le_bytes(self.bytes(offset)?) as u64;
+        if address == 0 {
+            return Ok(None);
+        }
+        Ok(Some(address))
+    }
+
+    // Returns the raw x10 elevation for a location, or None if there is no data for it
+    pub fn read_elevation_x10(&self, easting: i64, northing: i64) -> Result<Option<i16>, Box<dyn Error>> {
+        match self.data_block_address(easting, northing)? {
+            Some(data_block_address) => {
+                let offset = (data_block_address + reader::elevation_offset(easting, northing)) as usize;
+                Ok(Some(i16::from_le_bytes(self.bytes(offset)?)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // Gets the 40,000 x10 elevations of a data block, ordered W to E then S to N as stored
+    pub fn read_data_block(&self, data_block_address: u64) -> Result<Vec<i16>, Box<dyn Error>> {
+        let start = data_block_address as usize;
+        let block = self
+            .map
+            .get(start..start + os::DATA_BLOCK_LENGTH as usize)
+            .ok_or("The data file is shorter than its data block addresses")?;
+        Ok(block
+            .chunks_exact(os::ELEVATION_DATA_LENGTH as usize)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect())
+    }
+
+    // Returns the supplied coordinates with their elevations, optionally with
+    // infills every 50m or so between them, as for reader::read_elevations
+    pub fn read_elevations(&self, coords_list: &[OSCoords], infill: bool) -> Result<Vec<OSCoords>, Box<dyn Error>> {
+        reader::elevations_of(coords_list, infill, |easting, northing| self.read_elevation_x10(easting, northing))
+    }
+
+    // Gets N bytes from an offset in the file
+    fn bytes<const N: usize>(&self, offset: usize) -> Result<[u8; N], Box<dyn Error>> {
+        let bytes = self
+            .map
+            .get(offset..offset + N)
+            .ok_or("The data file is shorter than its addresses")?;
+        Ok(bytes.try_into()?)
+    }
+}
+
+#[test]
+fn can_be_shared_between_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<MappedDataFile>();
+}
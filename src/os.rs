@@ -42,4 +42,20 @@ pub const ELEVATIONS_PER_ROW: usize = 200; // No. of elevation values in each da
 pub const MAX_NUM_DATA_FILES: i64 = 100; // Maximum number of data files per 10km² grid
 pub const ROWS_IN_10_GRID: i64 = 10; // No. of files per row (and column) per 10km² grid
 pub const GRID_IDENT_LEN: i64 = 2; // Length of a grid identifier ("SV" etc.)
-pub const ADDRESS_LENGTH: i64 = 4; // Length of data addresses stored in the output file
\ No newline at end of file
+pub const ADDRESS_LENGTH: i64 = 4; // Length of data addresses stored in the output file
+
+// Constants specific to the British National Grid
+
+pub const GRIDS_PER_ROW_100: i64 = 7; // No. of grids per row in the full 91 grid block
+pub const GRIDS_PER_COL_100: i64 = 13; // No. of grids per column in the full 91 grid block
+pub const METRES_IN_500_GRID: i64 = 500_000; // No of metres in 500 Km² grid E & N
+pub const METRES_IN_100_GRID: i64 = 100_000; // No of metres in 100 Km² grid E & N
+pub const METRES_IN_10_GRID: i64 = 10_000; // No of metres in 10 Km² grid E & N
+
+// Constants specific to reading the output file
+
+pub const HEADER_BLOCK_LENGTH: i64 = GRID_IDENT_LEN + (MAX_NUM_DATA_FILES * ADDRESS_LENGTH);
+pub const ELEVATION_DATA_LENGTH: i64 = 2; // Length of a single elevation data point
+pub const ELEVATION_DISTANCE: i64 = 50; // Distance between successive elevations points
+pub const DATA_BLOCK_LENGTH: i64 =
+    ELEVATIONS_PER_ROW as i64 * ELEVATIONS_PER_ROW as i64 * ELEVATION_DATA_LENGTH; // Length of a 10km² data block
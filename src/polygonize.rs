@@ -0,0 +1,219 @@
+use crate::raster::Raster;
+use std::collections::HashMap;
+
+/***********************************************************************
+    Code for tracing areas of a raster into polygons
+
+    Every side of a selected cell which borders an unselected cell (or the
+    edge of the raster) becomes a boundary edge, directed so that the
+    selected cell is on its left. Joining the edges end to end then gives
+    anticlockwise outer rings and clockwise holes, as GeoJSON expects.
+
+    Where two selected cells only touch at a corner, the trace turns left
+    to stay with the cell it is following, so the cells become separate
+    polygons. Each hole is then placed in the smallest outer ring around it.
+************************************************************************/
+
+// A corner of a cell as a column & row from the north west corner of the raster
+type Vertex = (usize, usize);
+
+// Polygons as lists of rings of eastings & northings. The first ring of each
+// polygon is its outer boundary and any others are holes.
+pub type Polygon = Vec<Vec<(f64, f64)>>;
+
+// Traces the cells of a raster which pass a test into polygons
+pub fn polygonize<T: Copy + PartialEq>(grid: &Raster<T>, selected: impl Fn(T) -> bool) -> Vec<Polygon> {
+    let is_selected = |col: isize, row: isize| {
+        col >= 0
+            && row >= 0
+            && (col as usize) < grid.cols
+            && (row as usize) < grid.rows
+            && selected(grid.get(col as usize, row as usize))
+    };
+
+    // Collect the boundary edges, keyed by their start vertex
+    let mut edges: Vec<(Vertex, Vertex)> = Vec::new();
+    for row in 0..grid.rows {
+        for col in 0..grid.cols {
+            let (c, r) = (col as isize, row as isize);
+            if !is_selected(c, r) {
+                continue;
+            }
+            let (nw, ne, sw, se) = ((col, row), (col + 1, row), (col, row + 1), (col + 1, row + 1));
+            if !is_selected(c, r - 1) {
+                edges.push((ne, nw));
+            }
+            if !is_selected(c, r + 1) {
+                edges.push((sw, se));
+            }
+            if !is_selected(c - 1, r) {
+                edges.push((nw, sw));
+            }
+            if !is_selected(c + 1, r) {
+                edges.push((se, ne));
+            }
+        }
+    }
+    let mut by_start: HashMap<Vertex, Vec<usize>> = HashMap::new();
+    for (i, edge) in edges.iter().enumerate() {
+        by_start.entry(edge.0).or_default().push(i);
+    }
+
+    // Join the edges into rings
+    let mut used = vec![false; edges.len()];
+    let mut outers: Vec<Vec<Vertex>> = Vec::new();
+    let mut holes: Vec<Vec<Vertex>> = Vec::new();
+    for first in 0..edges.len() {
+        if used[first] {
+            continue;
+        }
+        used[first] = true;
+        let mut ring = vec![edges[first].0];
+        let mut current = first;
+        while edges[current].1 != edges[first].0 {
+            let (from, to) = edges[current];
+            let direction = (to.0 as isize - from.0 as isize, to.1 as isize - from.1 as isize);
+            let next = by_start[&to]
+                .iter()
+                .copied()
+                .filter(|&i| !used[i])
+                .max_by_key(|&i| left_turn(direction, edges[i]))
+                .expect("Boundary edges always form closed rings");
+            used[next] = true;
+            ring.push(to);
+            current = next;
+        }
+        let ring = without_straight_vertices(ring);
+        if signed_area(&ring) > 0.0 {
+            outers.push(ring);
+        } else {
+            holes.push(ring);
+        }
+    }
+
+    // Put each hole in the smallest outer ring around it
+    let mut polygons: Vec<Vec<Vec<Vertex>>> = outers.into_iter().map(|ring| vec![ring]).collect();
+    let areas: Vec<f64> = polygons.iter().map(|polygon| signed_area(&polygon[0])).collect();
+    for hole in holes {
+        // A point just inside the selected cell on the left of the hole's first edge
+        let (from, to) = (hole[0], hole[1]);
+        let (dx, dy) = (to.0 as f64 - from.0 as f64, to.1 as f64 - from.1 as f64);
+        let (x, y) = (from.0 as f64 + dx * 0.5 + dy * 0.25, from.1 as f64 + dy * 0.5 - dx * 0.25);
+        let outer = (0..polygons.len())
+            .filter(|&i| contains(&polygons[i][0], x, y))
+            .min_by(|&a, &b| areas[a].partial_cmp(&areas[b]).unwrap());
+        if let Some(outer) = outer {
+            polygons[outer].push(hole);
+        }
+    }
+
+    // Convert the vertices to eastings & northings and close the rings
+    let cell_size = grid.cell_size as f64;
+    let to_coords = |&(col, row): &Vertex| {
+        (
+            grid.min_easting as f64 + col as f64 * cell_size,
+            grid.max_northing() as f64 - row as f64 * cell_size,
+        )
+    };
+    polygons
+        .iter()
+        .map(|rings| {
+            rings
+                .iter()
+                .map(|ring| {
+                    let mut coords: Vec<(f64, f64)> = ring.iter().map(to_coords).collect();
+                    coords.push(coords[0]);
+                    coords
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Scores how far an edge turns left from a direction: 1 for left, 0 for straight on
+// and -1 for right. NB: rows run down the raster, so a left turn on the map is
+// a clockwise turn in column & row terms.
+fn left_turn(direction: (isize, isize), edge: (Vertex, Vertex)) -> isize {
+    let (from, to) = edge;
+    let next = (to.0 as isize - from.0 as isize, to.1 as isize - from.1 as isize);
+    direction.1 * next.0 - direction.0 * next.1
+}
+
+// Removes the vertices along straight runs of edges
+fn without_straight_vertices(ring: Vec<Vertex>) -> Vec<Vertex> {
+    let count = ring.len();
+    (0..count)
+        .filter(|&i| {
+            let (prev, this, next) = (ring[(i + count - 1) % count], ring[i], ring[(i + 1) % count]);
+            let (dx1, dy1) = (this.0 as isize - prev.0 as isize, this.1 as isize - prev.1 as isize);
+            let (dx2, dy2) = (next.0 as isize - this.0 as isize, next.1 as isize - this.1 as isize);
+            dx1 * dy2 != dy1 * dx2
+        })
+        .map(|i| ring[i])
+        .collect()
+}
+
+// Gets the area of a ring in cells, positive when it runs anticlockwise on the map
+fn signed_area(ring: &[Vertex]) -> f64 {
+    let mut twice_area = 0.0;
+    for i in 0..ring.len() {
+        let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+        // Rows run down the raster, so flip them to go up the map
+        twice_area += a.0 as f64 * -(b.1 as f64) - b.0 as f64 * -(a.1 as f64);
+    }
+    twice_area / 2.0
+}
+
+// Tests whether a point is inside a ring by counting the edges a ray from it crosses
+fn contains(ring: &[Vertex], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    for i in 0..ring.len() {
+        let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+        let (ax, ay, bx, by) = (a.0 as f64, a.1 as f64, b.0 as f64, b.1 as f64);
+        if (ay > y) != (by > y) && x < ax + (y - ay) / (by - ay) * (bx - ax) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+fn test_grid(values: &[u8], cols: usize) -> Raster<u8> {
+    use crate::raster::BoundingBox;
+    let rows = values.len() / cols;
+    let bbox = BoundingBox::new(0, 0, cols as i64 * 50, rows as i64 * 50).unwrap();
+    let mut grid = Raster::new(&bbox, 0);
+    grid.values.copy_from_slice(values);
+    grid
+}
+
+#[test]
+fn ring_with_a_hole() {
+    #[rustfmt::skip]
+    let grid = test_grid(&[
+        1, 1, 1,
+        1, 0, 1,
+        1, 1, 1,
+    ], 3);
+    let polygons = polygonize(&grid, |v| v == 1);
+    assert_eq!(1, polygons.len());
+    assert_eq!(2, polygons[0].len());
+    // Anticlockwise outer ring from the NW corner, closed at its start
+    assert_eq!(
+        vec![(0.0, 150.0), (0.0, 0.0), (150.0, 0.0), (150.0, 150.0), (0.0, 150.0)],
+        polygons[0][0]
+    );
+    assert_eq!(5, polygons[0][1].len());
+}
+
+#[test]
+fn cells_touching_at_corners_are_separate() {
+    #[rustfmt::skip]
+    let grid = test_grid(&[
+        1, 0,
+        0, 1,
+    ], 2);
+    let polygons = polygonize(&grid, |v| v == 1);
+    assert_eq!(2, polygons.len());
+    assert!(polygons.iter().all(|polygon| polygon.len() == 1 && polygon[0].len() == 5));
+}
@@ -0,0 +1,89 @@
+use crate::coords::OSCoords;
+
+/***********************************************************************
+    Summaries of elevation profiles: the length of a route and its
+    lowest & highest points, total ascent and total descent
+
+    The profile should have elevations every 50m or so (e.g. from
+    read_elevations with infills) so that climbs between the route's own
+    points are counted.
+************************************************************************/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProfileSummary {
+    pub distance: f64, // In metres
+    pub min: f64,
+    pub max: f64,
+    pub ascent: f64, // Sum of the rises between each point and the next
+    pub descent: f64, // Sum of the falls, as a positive value
+}
+
+impl ProfileSummary {
+    // Combines the summaries of separate parts of a route
+    pub fn combine(&self, other: &ProfileSummary) -> ProfileSummary {
+        ProfileSummary {
+            distance: self.distance + other.distance,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            ascent: self.ascent + other.ascent,
+            descent: self.descent + other.descent,
+        }
+    }
+}
+
+// Summarises a profile of coordinates with elevations, or None if it has no points
+pub fn summarise(coords: &[OSCoords]) -> Option<ProfileSummary> {
+    let elevation = |coord: &OSCoords| coord.elevation.unwrap_or_default() as f64;
+    let first = elevation(coords.first()?);
+    let mut summary = ProfileSummary {
+        distance: 0.0,
+        min: first,
+        max: first,
+        ascent: 0.0,
+        descent: 0.0,
+    };
+    for pair in coords.windows(2) {
+        let (from, to) = (&pair[0], &pair[1]);
+        summary.distance += ((to.easting - from.easting) as f64).hypot((to.northing - from.northing) as f64);
+        let rise = elevation(to) - elevation(from);
+        if rise > 0.0 {
+            summary.ascent += rise;
+        } else {
+            summary.descent -= rise;
+        }
+        summary.min = summary.min.min(elevation(to));
+        summary.max = summary.max.max(elevation(to));
+    }
+    Some(summary)
+}
+
+// Sums the rises along a sequence of elevations
+pub fn total_ascent(elevations: impl IntoIterator<Item = f64>) -> f64 {
+    let mut ascent = 0.0;
+    let mut previous: Option<f64> = None;
+    for elevation in elevations {
+        if let Some(previous) = previous {
+            ascent += (elevation - previous).max(0.0);
+        }
+        previous = Some(elevation);
+    }
+    ascent
+}
+
+#[test]
+fn up_and_down() {
+    let coord = |easting: i64, elevation: f32| OSCoords {
+        easting,
+        northing: 0,
+        elevation: Some(elevation),
+    };
+    let coords = [coord(0, 10.0), coord(50, 15.0), coord(100, 12.0), coord(150, 20.0)];
+    let summary = summarise(&coords).unwrap();
+    assert_eq!(
+        ProfileSummary { distance: 150.0, min: 10.0, max: 20.0, ascent: 13.0, descent: 3.0 },
+        summary
+    );
+    assert_eq!(13.0, total_ascent([10.0, 15.0, 12.0, 20.0]));
+    assert_eq!(None, summarise(&[]));
+    assert_eq!(300.0, summary.combine(&summary).distance);
+}
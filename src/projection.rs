@@ -0,0 +1,175 @@
+use std::f64::consts::PI;
+
+/***********************************************************************
+    Code for converting between WGS84 latitude/longitude (as used by GPS
+    and web maps) and OS National Grid eastings & northings.
+
+    Uses the formulae in the OS publication "A guide to coordinate systems
+    in Great Britain": a 7 parameter Helmert transformation between the WGS84
+    and OSGB36 datums, and the Transverse Mercator projection of the Airy 1830
+    ellipsoid. This is accurate to around 5m, which is well within a 50m cell.
+************************************************************************/
+
+// Airy 1830 ellipsoid used by OSGB36
+const AIRY_A: f64 = 6_377_563.396;
+const AIRY_B: f64 = 6_356_256.909;
+
+// GRS80 ellipsoid used by WGS84
+const WGS84_A: f64 = 6_378_137.000;
+const WGS84_B: f64 = 6_356_752.314_1;
+
+// National Grid true origin & scale factor
+const F0: f64 = 0.999_601_271_7;
+const LAT0_DEGREES: f64 = 49.0;
+const LON0_DEGREES: f64 = -2.0;
+const E0: f64 = 400_000.0;
+const N0: f64 = -100_000.0;
+
+// Helmert transformation from WGS84 to OSGB36: translations (m), scale (ppm), rotations (arc seconds)
+const HELMERT_T: [f64; 3] = [-446.448, 125.157, -542.060];
+const HELMERT_S: f64 = 20.4894;
+const HELMERT_R: [f64; 3] = [-0.1502, -0.2470, -0.8421];
+
+// Converts a WGS84 latitude & longitude (in decimal degrees) to a National Grid easting & northing
+pub fn wgs84_to_os(latitude: f64, longitude: f64) -> (f64, f64) {
+    let cartesian = to_cartesian(latitude, longitude, WGS84_A, WGS84_B);
+    let osgb36 = helmert(cartesian, 1.0);
+    let (lat, lon) = from_cartesian(osgb36, AIRY_A, AIRY_B);
+    project(lat, lon)
+}
+
+// Converts a National Grid easting & northing to a WGS84 latitude & longitude (in decimal degrees)
+pub fn os_to_wgs84(easting: f64, northing: f64) -> (f64, f64) {
+    let (lat, lon) = unproject(easting, northing);
+    let cartesian = to_cartesian(lat, lon, AIRY_A, AIRY_B);
+    let wgs84 = helmert(cartesian, -1.0);
+    from_cartesian(wgs84, WGS84_A, WGS84_B)
+}
+
+// Projects an OSGB36 latitude & longitude (in decimal degrees) to easting & northing
+pub fn project(latitude: f64, longitude: f64) -> (f64, f64) {
+    let lat = latitude.to_radians();
+    let d_lon = longitude.to_radians() - LON0_DEGREES.to_radians();
+    let (nu, rho, eta2) = radii_of_curvature(lat);
+
+    let (sin, cos, tan) = (lat.sin(), lat.cos(), lat.tan());
+    let tan2 = tan * tan;
+    let i = meridional_arc(lat) + N0;
+    let ii = nu / 2.0 * sin * cos;
+    let iii = nu / 24.0 * sin * cos.powi(3) * (5.0 - tan2 + 9.0 * eta2);
+    let iiia = nu / 720.0 * sin * cos.powi(5) * (61.0 - 58.0 * tan2 + tan2 * tan2);
+    let iv = nu * cos;
+    let v = nu / 6.0 * cos.powi(3) * (nu / rho - tan2);
+    let vi = nu / 120.0 * cos.powi(5) * (5.0 - 18.0 * tan2 + tan2 * tan2 + 14.0 * eta2 - 58.0 * tan2 * eta2);
+
+    let northing = i + ii * d_lon.powi(2) + iii * d_lon.powi(4) + iiia * d_lon.powi(6);
+    let easting = E0 + iv * d_lon + v * d_lon.powi(3) + vi * d_lon.powi(5);
+    (easting, northing)
+}
+
+// Converts an easting & northing back to an OSGB36 latitude & longitude (in decimal degrees)
+pub fn unproject(easting: f64, northing: f64) -> (f64, f64) {
+    // Iterate until the meridional arc matches the northing to within 0.01mm
+    let mut lat = (northing - N0) / (AIRY_A * F0) + LAT0_DEGREES.to_radians();
+    let mut m = meridional_arc(lat);
+    while (northing - N0 - m).abs() >= 0.000_01 {
+        lat += (northing - N0 - m) / (AIRY_A * F0);
+        m = meridional_arc(lat);
+    }
+    let (nu, rho, eta2) = radii_of_curvature(lat);
+
+    let (tan, sec) = (lat.tan(), 1.0 / lat.cos());
+    let tan2 = tan * tan;
+    let vii = tan / (2.0 * rho * nu);
+    let viii = tan / (24.0 * rho * nu.powi(3)) * (5.0 + 3.0 * tan2 + eta2 - 9.0 * tan2 * eta2);
+    let ix = tan / (720.0 * rho * nu.powi(5)) * (61.0 + 90.0 * tan2 + 45.0 * tan2 * tan2);
+    let x = sec / nu;
+    let xi = sec / (6.0 * nu.powi(3)) * (nu / rho + 2.0 * tan2);
+    let xii = sec / (120.0 * nu.powi(5)) * (5.0 + 28.0 * tan2 + 24.0 * tan2 * tan2);
+    let xiia = sec / (5040.0 * nu.powi(7)) * (61.0 + 662.0 * tan2 + 1320.0 * tan2 * tan2 + 720.0 * tan2.powi(3));
+
+    let d_e = easting - E0;
+    let latitude = lat - vii * d_e.powi(2) + viii * d_e.powi(4) - ix * d_e.powi(6);
+    let longitude = LON0_DEGREES.to_radians() + x * d_e - xi * d_e.powi(3) + xii * d_e.powi(5) - xiia * d_e.powi(7);
+    (latitude.to_degrees(), longitude.to_degrees())
+}
+
+// Gets nu, rho & eta² for the Airy ellipsoid at a latitude (in radians)
+fn radii_of_curvature(lat: f64) -> (f64, f64, f64) {
+    let e2 = 1.0 - (AIRY_B * AIRY_B) / (AIRY_A * AIRY_A);
+    let sin2 = lat.sin() * lat.sin();
+    let nu = AIRY_A * F0 / (1.0 - e2 * sin2).sqrt();
+    let rho = AIRY_A * F0 * (1.0 - e2) / (1.0 - e2 * sin2).powf(1.5);
+    (nu, rho, nu / rho - 1.0)
+}
+
+// Gets the meridional arc from the true origin latitude to a latitude (in radians)
+fn meridional_arc(lat: f64) -> f64 {
+    let n = (AIRY_A - AIRY_B) / (AIRY_A + AIRY_B);
+    let (n2, n3) = (n * n, n * n * n);
+    let lat0 = LAT0_DEGREES.to_radians();
+    let (d_lat, s_lat) = (lat - lat0, lat + lat0);
+    AIRY_B
+        * F0
+        * ((1.0 + n + 1.25 * n2 + 1.25 * n3) * d_lat
+            - (3.0 * n + 3.0 * n2 + 2.625 * n3) * d_lat.sin() * s_lat.cos()
+            + (1.875 * n2 + 1.875 * n3) * (2.0 * d_lat).sin() * (2.0 * s_lat).cos()
+            - (35.0 / 24.0) * n3 * (3.0 * d_lat).sin() * (3.0 * s_lat).cos())
+}
+
+fn to_cartesian(latitude: f64, longitude: f64, a: f64, b: f64) -> [f64; 3] {
+    let (lat, lon) = (latitude.to_radians(), longitude.to_radians());
+    let e2 = 1.0 - (b * b) / (a * a);
+    let nu = a / (1.0 - e2 * lat.sin() * lat.sin()).sqrt();
+    [
+        nu * lat.cos() * lon.cos(),
+        nu * lat.cos() * lon.sin(),
+        (1.0 - e2) * nu * lat.sin(),
+    ]
+}
+
+fn from_cartesian(xyz: [f64; 3], a: f64, b: f64) -> (f64, f64) {
+    let e2 = 1.0 - (b * b) / (a * a);
+    let p = (xyz[0] * xyz[0] + xyz[1] * xyz[1]).sqrt();
+    let mut lat = xyz[2].atan2(p * (1.0 - e2));
+    for _ in 0..10 {
+        let nu = a / (1.0 - e2 * lat.sin() * lat.sin()).sqrt();
+        lat = (xyz[2] + e2 * nu * lat.sin()).atan2(p);
+    }
+    (lat.to_degrees(), xyz[1].atan2(xyz[0]).to_degrees())
+}
+
+// Applies the Helmert transformation forwards (direction 1.0: WGS84 to OSGB36)
+// or backwards (direction -1.0: OSGB36 to WGS84)
+fn helmert(xyz: [f64; 3], direction: f64) -> [f64; 3] {
+    let s = 1.0 + direction * HELMERT_S * 1e-6;
+    let r: Vec<f64> = HELMERT_R.iter().map(|r| direction * r / 3600.0 * PI / 180.0).collect();
+    let t: Vec<f64> = HELMERT_T.iter().map(|t| direction * t).collect();
+    [
+        t[0] + s * xyz[0] - r[2] * xyz[1] + r[1] * xyz[2],
+        t[1] + r[2] * xyz[0] + s * xyz[1] - r[0] * xyz[2],
+        t[2] - r[1] * xyz[0] + r[0] * xyz[1] + s * xyz[2],
+    ]
+}
+
+#[test]
+fn projection_matches_os_worked_example() {
+    // Worked example from "A guide to coordinate systems in Great Britain"
+    let latitude = 52.0 + 39.0 / 60.0 + 27.2531 / 3600.0;
+    let longitude = 1.0 + 43.0 / 60.0 + 4.5177 / 3600.0;
+    let (easting, northing) = project(latitude, longitude);
+    assert!((easting - 651_409.903).abs() < 0.001, "Easting {}", easting);
+    assert!((northing - 313_177.270).abs() < 0.001, "Northing {}", northing);
+
+    let (lat, lon) = unproject(651_409.903, 313_177.270);
+    assert!((lat - latitude).abs() < 1e-7 && (lon - longitude).abs() < 1e-7);
+}
+
+#[test]
+fn wgs84_round_trip() {
+    // Ben Nevis summit
+    let (latitude, longitude) = os_to_wgs84(216_690.0, 771_270.0);
+    assert!((latitude - 56.797).abs() < 0.01 && (longitude + 5.004).abs() < 0.01);
+    let (easting, northing) = wgs84_to_os(latitude, longitude);
+    assert!((easting - 216_690.0).abs() < 1.0 && (northing - 771_270.0).abs() < 1.0);
+}
@@ -0,0 +1,175 @@
+use crate::coords::OSCoords;
+use crate::line_of_sight::{self, ProfilePoint};
+use crate::reader::DataFile;
+use std::error::Error;
+
+/***********************************************************************
+    Code for analysing a point to point radio path over the terrain
+
+    The ground is sampled every 50m between the two antennas as for line
+    of sight checks. The curve of the earth is allowed for using the
+    k-factor, which scales the earth's radius to account for the bending
+    of radio waves by the atmosphere (4/3 in standard conditions).
+
+    At each sample the clearance between the ground and the straight line
+    joining the antennas is compared with the radius of the first Fresnel
+    zone. A path is usually planned to keep at least 60% of the zone clear.
+    The loss due to the worst obstruction is estimated by treating it as
+    a single knife edge, using the approximation in ITU-R P.526.
+************************************************************************/
+
+const SPEED_OF_LIGHT: f64 = 299_792_458.0; // In m/s
+pub const STANDARD_K_FACTOR: f64 = 4.0 / 3.0;
+pub const MIN_CLEARANCE_RATIO: f64 = 0.6; // Fraction of the first Fresnel zone usually kept clear
+
+#[derive(Debug, Clone, Copy)]
+pub struct RadioOptions {
+    pub frequency_mhz: f64,
+    pub tx_height: f64, // Antenna heights above the ground in metres
+    pub rx_height: f64,
+    pub k_factor: f64,
+}
+
+impl Default for RadioOptions {
+    fn default() -> RadioOptions {
+        RadioOptions {
+            frequency_mhz: 868.0,
+            tx_height: 10.0,
+            rx_height: 10.0,
+            k_factor: STANDARD_K_FACTOR,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PathPoint {
+    pub ground: ProfilePoint,
+    pub earth_bulge: f64, // Added to the ground height in metres
+    pub beam_height: f64, // Height of the line between the antennas in metres
+    pub fresnel_radius: f64, // Radius of the first Fresnel zone in metres
+    pub clearance: f64, // From the ground (plus bulge) up to the beam, negative where it's blocked
+}
+
+impl PathPoint {
+    // Gets the clearance as a fraction of the first Fresnel zone radius
+    pub fn clearance_ratio(&self) -> f64 {
+        self.clearance / self.fresnel_radius
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RadioPath {
+    pub distance: f64, // In metres
+    pub points: Vec<PathPoint>,
+    pub worst: Option<PathPoint>, // Point with the least clearance ratio between the antennas
+    pub line_of_sight: bool, // The beam itself clears the ground
+    pub fresnel_clear: bool, // At least 60% of the first Fresnel zone is clear everywhere
+    pub free_space_loss: f64, // In dB
+    pub diffraction_loss: f64, // In dB, from the worst obstruction
+}
+
+// Analyses the radio path between a transmitter and a receiver
+pub fn radio_path(
+    data: &mut DataFile,
+    tx: OSCoords,
+    rx: OSCoords,
+    options: &RadioOptions,
+) -> Result<RadioPath, Box<dyn Error>> {
+    if options.frequency_mhz <= 0.0 || options.k_factor <= 0.0 {
+        return Err("The frequency and k-factor must be greater than 0".into());
+    }
+    let wavelength = SPEED_OF_LIGHT / (options.frequency_mhz * 1_000_000.0);
+    // A k-factor of k is the same as a refraction coefficient of 1 - 1/k
+    let refraction = 1.0 - 1.0 / options.k_factor;
+
+    let profile = line_of_sight::path_profile(data, tx, rx)?;
+    let (first, last) = (profile[0], profile[profile.len() - 1]);
+    let tx_top = first.elevation + options.tx_height;
+    let rx_top = last.elevation + options.rx_height;
+    let distance = last.distance;
+
+    let points: Vec<PathPoint> = profile
+        .iter()
+        .map(|ground| {
+            let (d1, d2) = (ground.distance, distance - ground.distance);
+            let earth_bulge = line_of_sight::earth_bulge(d1, d2, refraction);
+            let beam_height = if distance > 0.0 {
+                tx_top + (rx_top - tx_top) * d1 / distance
+            } else {
+                tx_top
+            };
+            PathPoint {
+                ground: *ground,
+                earth_bulge,
+                beam_height,
+                fresnel_radius: fresnel_radius(wavelength, d1, d2),
+                clearance: beam_height - ground.elevation - earth_bulge,
+            }
+        })
+        .collect();
+
+    // Only the points between the antennas can obstruct the path
+    let worst = points[1..points.len() - 1]
+        .iter()
+        .copied()
+        .min_by(|a, b| a.clearance_ratio().total_cmp(&b.clearance_ratio()));
+    let (line_of_sight, fresnel_clear, diffraction_loss) = match worst {
+        Some(worst) => (
+            worst.clearance >= 0.0,
+            worst.clearance_ratio() >= MIN_CLEARANCE_RATIO,
+            knife_edge_loss(-worst.clearance_ratio() * 2f64.sqrt()),
+        ),
+        None => (true, true, 0.0),
+    };
+
+    Ok(RadioPath {
+        distance,
+        worst,
+        line_of_sight,
+        fresnel_clear,
+        free_space_loss: free_space_loss(distance, wavelength),
+        diffraction_loss,
+        points,
+    })
+}
+
+// Gets the radius of the first Fresnel zone at distances d1 & d2 from each end of a path
+pub fn fresnel_radius(wavelength: f64, d1: f64, d2: f64) -> f64 {
+    if d1 + d2 == 0.0 {
+        return 0.0;
+    }
+    (wavelength * d1 * d2 / (d1 + d2)).sqrt()
+}
+
+// Gets the loss in dB over a single knife edge from the ITU-R P.526 approximation.
+// v is the diffraction parameter: √2 x the obstruction height above the beam
+// divided by the first Fresnel zone radius.
+pub fn knife_edge_loss(v: f64) -> f64 {
+    if v <= -0.78 {
+        return 0.0;
+    }
+    6.9 + 20.0 * (((v - 0.1) * (v - 0.1) + 1.0).sqrt() + v - 0.1).log10()
+}
+
+// Gets the free space path loss in dB between isotropic antennas
+pub fn free_space_loss(distance: f64, wavelength: f64) -> f64 {
+    if distance == 0.0 {
+        return 0.0;
+    }
+    20.0 * (4.0 * std::f64::consts::PI * distance / wavelength).log10()
+}
+
+#[test]
+fn fresnel_zone_and_losses() {
+    // 1 GHz over 10km has a Fresnel radius of about 27.4m in the middle
+    let wavelength = SPEED_OF_LIGHT / 1e9;
+    assert!((fresnel_radius(wavelength, 5_000.0, 5_000.0) - 27.37).abs() < 0.01);
+
+    // A knife edge grazing the beam loses 6dB
+    assert!((knife_edge_loss(0.0) - 6.0).abs() < 0.1);
+    assert_eq!(0.0, knife_edge_loss(-1.0));
+    assert!(knife_edge_loss(2.0) > knife_edge_loss(1.0));
+
+    // Free space loss over 10km at 1GHz is about 112.4dB
+    assert!((free_space_loss(10_000.0, wavelength) - 112.4).abs() < 0.1);
+}
@@ -0,0 +1,390 @@
+use crate::os;
+use crate::reader::DataFile;
+use std::error::Error;
+
+/***********************************************************************
+   Code for extracting dense elevation grids from the OS binary file
+************************************************************************/
+
+pub const NODATA_X10: i16 = i16::MIN; // Default nodata value for x10 integer grids
+pub const NODATA_METRES: f32 = -9999.0; // Default nodata value for grids in metres
+const GRID_WIDTH: i64 = os::GRIDS_PER_ROW_100 * os::METRES_IN_100_GRID; // Of the full 91 grid block
+const GRID_HEIGHT: i64 = os::GRIDS_PER_COL_100 * os::METRES_IN_100_GRID;
+// The most 50m cells in a box: as many as in the full 91 grid block with a 10km border,
+// which leaves room for the margins some commands add around a region
+const MAX_CELLS: i128 = ((GRID_WIDTH + 2 * os::METRES_IN_10_GRID) / os::ELEVATION_DISTANCE) as i128
+    * ((GRID_HEIGHT + 2 * os::METRES_IN_10_GRID) / os::ELEVATION_DISTANCE) as i128;
+
+// An easting/northing area on the full GB grid. The minimum edges are
+// inclusive and the maximum edges exclusive, so adjacent boxes never overlap.
+// A box may reach beyond the grid, but must overlap it and have no more than
+// MAX_CELLS, so that its rasters can be allocated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundingBox {
+    pub min_easting: i64,
+    pub min_northing: i64,
+    pub max_easting: i64,
+    pub max_northing: i64,
+}
+
+impl BoundingBox {
+    pub fn new(
+        min_easting: i64,
+        min_northing: i64,
+        max_easting: i64,
+        max_northing: i64,
+    ) -> Result<BoundingBox, Box<dyn Error>> {
+        if max_easting <= min_easting || max_northing <= min_northing {
+            return Err("The bounding box maximum must be greater than its minimum".into());
+        }
+        if !overlaps_grid(min_easting, min_northing, max_easting, max_northing) {
+            return Err(format!(
+                "The bounding box {},{},{},{} is outside the National Grid",
+                min_easting, min_northing, max_easting, max_northing
+            )
+            .into());
+        }
+        // Counted in i128 as the edges may be anywhere outside the grid
+        let distance = os::ELEVATION_DISTANCE as i128;
+        let cells = |min: i64, max: i64| (max as i128 + distance - 1).div_euclid(distance) - (min as i128).div_euclid(distance);
+        if cells(min_easting, max_easting) * cells(min_northing, max_northing) > MAX_CELLS {
+            return Err(format!(
+                "The bounding box {},{},{},{} is too large, as it has more cells than the National Grid",
+                min_easting, min_northing, max_easting, max_northing
+            )
+            .into());
+        }
+        Ok(BoundingBox {
+            min_easting,
+            min_northing,
+            max_easting,
+            max_northing,
+        })
+    }
+
+    // Parses "min_easting,min_northing,max_easting,max_northing"
+    pub fn parse(bbox: &str) -> Result<BoundingBox, Box<dyn Error>> {
+        let values: Vec<i64> = bbox
+            .split(',')
+            .map(|value| value.trim().parse::<i64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("The bounding box {} is not numeric", bbox))?;
+        if values.len() != 4 {
+            return Err("The bounding box must have 4 comma-separated values".into());
+        }
+        BoundingBox::new(values[0], values[1], values[2], values[3])
+    }
+
+    // Gets the box for a named region: a bounding box as above,
+    // a 100km² grid identifier (e.g. "SU"), or "GB" for the full 91 grid block
+    pub fn for_region(region: &str) -> Result<BoundingBox, Box<dyn Error>> {
+        if region.contains(',') {
+            return BoundingBox::parse(region);
+        }
+        let region = region.to_uppercase();
+        if region == "GB" {
+            return BoundingBox::new(0, 0, GRID_WIDTH, GRID_HEIGHT);
+        }
+        match os::GRID_100.iter().position(|grid| *grid == region) {
+            Some(index) => {
+                let min_easting = (index as i64 % os::GRIDS_PER_ROW_100) * os::METRES_IN_100_GRID;
+                let min_northing = (index as i64 / os::GRIDS_PER_ROW_100) * os::METRES_IN_100_GRID;
+                BoundingBox::new(
+                    min_easting,
+                    min_northing,
+                    min_easting + os::METRES_IN_100_GRID,
+                    min_northing + os::METRES_IN_100_GRID,
+                )
+            }
+            None => Err(format!("The region {} is not a bounding box, 100km² grid or GB", region).into()),
+        }
+    }
+
+    // Expands the box outwards to whole 50m cells
+    pub fn snap_to_cells(&self) -> BoundingBox {
+        let distance = os::ELEVATION_DISTANCE;
+        BoundingBox {
+            min_easting: self.min_easting.div_euclid(distance) * distance,
+            min_northing: self.min_northing.div_euclid(distance) * distance,
+            max_easting: (self.max_easting + distance - 1).div_euclid(distance) * distance,
+            max_northing: (self.max_northing + distance - 1).div_euclid(distance) * distance,
+        }
+    }
+
+    // Gets the number of 50m cell columns and rows covering the box
+    pub fn size_in_cells(&self) -> (usize, usize) {
+        let cells = self.snap_to_cells();
+        let cols = (cells.max_easting - cells.min_easting) / os::ELEVATION_DISTANCE;
+        let rows = (cells.max_northing - cells.min_northing) / os::ELEVATION_DISTANCE;
+        (cols as usize, rows as usize)
+    }
+}
+
+// Checks whether an area overlaps the full 91 grid block
+pub fn overlaps_grid(min_easting: i64, min_northing: i64, max_easting: i64, max_northing: i64) -> bool {
+    min_easting < GRID_WIDTH && max_easting > 0 && min_northing < GRID_HEIGHT && max_northing > 0
+}
+
+// A contiguous grid of cells stored row-major from N to S and W to E,
+// i.e. the first value is the north west cell. This is the usual raster layout
+// and the reverse row order of the data blocks in the binary file.
+#[derive(Debug, Clone)]
+pub struct Raster<T> {
+    pub min_easting: i64, // Easting of the west edge of the raster
+    pub min_northing: i64, // Northing of the south edge of the raster
+    pub cell_size: i64, // Cell width & height in metres (50m unless resampled)
+    pub cols: usize,
+    pub rows: usize,
+    pub nodata: T,
+    pub values: Vec<T>,
+}
+
+impl<T: Copy + PartialEq> Raster<T> {
+    pub fn new(bbox: &BoundingBox, nodata: T) -> Raster<T> {
+        let cells = bbox.snap_to_cells();
+        let (cols, rows) = cells.size_in_cells();
+        Raster {
+            min_easting: cells.min_easting,
+            min_northing: cells.min_northing,
+            cell_size: os::ELEVATION_DISTANCE,
+            cols,
+            rows,
+            nodata,
+            values: vec![nodata; cols * rows],
+        }
+    }
+
+    pub fn max_easting(&self) -> i64 {
+        self.min_easting + self.cols as i64 * self.cell_size
+    }
+
+    pub fn max_northing(&self) -> i64 {
+        self.min_northing + self.rows as i64 * self.cell_size
+    }
+
+    pub fn get(&self, col: usize, row: usize) -> T {
+        self.values[row * self.cols + col]
+    }
+
+    pub fn set(&mut self, col: usize, row: usize, value: T) {
+        self.values[row * self.cols + col] = value;
+    }
+
+    pub fn is_nodata(&self, col: usize, row: usize) -> bool {
+        self.get(col, row) == self.nodata
+    }
+
+    // Gets the cell containing a location, if it lies inside the raster
+    pub fn cell_at(&self, easting: f64, northing: f64) -> Option<(usize, usize)> {
+        let col = ((easting - self.min_easting as f64) / self.cell_size as f64).floor();
+        let row = ((self.max_northing() as f64 - northing) / self.cell_size as f64).floor();
+        if col < 0.0 || row < 0.0 || col >= self.cols as f64 || row >= self.rows as f64 {
+            return None;
+        }
+        Some((col as usize, row as usize))
+    }
+
+    // Converts every value, keeping nodata cells as nodata
+    pub fn map<U: Copy + PartialEq>(&self, nodata: U, convert: impl Fn(T) -> U) -> Raster<U> {
+        Raster {
+            min_easting: self.min_easting,
+            min_northing: self.min_northing,
+            cell_size: self.cell_size,
+            cols: self.cols,
+            rows: self.rows,
+            nodata,
+            values: self
+                .values
+                .iter()
+                .map(|&value| if value == self.nodata { nodata } else { convert(value) })
+                .collect(),
+        }
+    }
+}
+
+impl<T: Copy + PartialEq + Into<f64>> Raster<T> {
+    // Interpolates bilinearly between the centres of the four cells around a location.
+    // Falls back to the nearest cell where any of them are nodata, e.g. at the coast.
+    pub fn interpolate(&self, easting: f64, northing: f64) -> Option<f64> {
+        let (nearest_col, nearest_row) = self.cell_at(easting, northing)?;
+
+        // Position relative to the centre of the south west cell, counting rows upwards
+        let x = (easting - self.min_easting as f64) / self.cell_size as f64 - 0.5;
+        let y = (northing - self.min_northing as f64) / self.cell_size as f64 - 0.5;
+        let col = (x.floor().max(0.0) as usize).min(self.cols.saturating_sub(2));
+        let row_up = (y.floor().max(0.0) as usize).min(self.rows.saturating_sub(2));
+        let corners = [(col, row_up), (col + 1, row_up), (col, row_up + 1), (col + 1, row_up + 1)];
+
+        if self.cols < 2 || self.rows < 2 || corners.iter().any(|&(c, r)| self.is_nodata(c, self.rows - 1 - r)) {
+            if self.is_nodata(nearest_col, nearest_row) {
+                return None;
+            }
+            return Some(self.get(nearest_col, nearest_row).into());
+        }
+        let value = |c: usize, r: usize| -> f64 { self.get(c, self.rows - 1 - r).into() };
+        let fx = (x - col as f64).clamp(0.0, 1.0);
+        let fy = (y - row_up as f64).clamp(0.0, 1.0);
+        let south = value(col, row_up) * (1.0 - fx) + value(col + 1, row_up) * fx;
+        let north = value(col, row_up + 1) * (1.0 - fx) + value(col + 1, row_up + 1) * fx;
+        Some(south * (1.0 - fy) + north * fy)
+    }
+}
+
+// Reads the raw x10 elevations for a bounding box, stitched across 10km² data blocks.
+// Cells in blocks without a data address (sea areas) are filled with the nodata value.
+pub fn extract_x10(data: &mut DataFile, bbox: &BoundingBox, nodata: i16) -> Result<Raster<i16>, Box<dyn Error>> {
+    extract_x10_from_blocks(bbox, nodata, |block_easting, block_northing| {
+        match data.data_block_address(block_easting, block_northing)? {
+            Some(data_block_address) => Ok(Some(data.read_data_block(data_block_address)?)),
+            None => Ok(None),
+        }
+    })
+}
+
+// Stitches the raw x10 elevations for a bounding box from the 10km² data blocks given
+// by a function of the block's SW corner easting & northing, which returns None for sea areas
+pub fn extract_x10_from_blocks(
+    bbox: &BoundingBox,
+    nodata: i16,
+    mut read_block: impl FnMut(i64, i64) -> Result<Option<Vec<i16>>, Box<dyn Error>>,
+) -> Result<Raster<i16>, Box<dyn Error>> {
+    let mut raster = Raster::new(bbox, nodata);
+    if raster.values.is_empty() {
+        return Ok(raster);
+    }
+
+    // Work out which 10km² data blocks the raster overlaps
+    let block_size = os::METRES_IN_10_GRID;
+    let first_block_e = raster.min_easting.div_euclid(block_size);
+    let last_block_e = (raster.max_easting() - 1).div_euclid(block_size);
+    let first_block_n = raster.min_northing.div_euclid(block_size);
+    let last_block_n = (raster.max_northing() - 1).div_euclid(block_size);
+
+    // Cell numbers of the raster edges counted from the full grid origin
+    let raster_col_start = raster.min_easting / os::ELEVATION_DISTANCE;
+    let raster_row_start = raster.min_northing / os::ELEVATION_DISTANCE;
+    let cells_per_block = os::ELEVATIONS_PER_ROW as i64;
+
+    for block_n in first_block_n..=last_block_n {
+        for block_e in first_block_e..=last_block_e {
+            let block = match read_block(block_e * block_size, block_n * block_size)? {
+                Some(block) => block,
+                None => continue,
+            };
+
+            // Copy the overlapping part of the block into the raster
+            let block_col_start = block_e * cells_per_block;
+            let block_row_start = block_n * cells_per_block;
+            let col_from = block_col_start.max(raster_col_start);
+            let col_to = (block_col_start + cells_per_block).min(raster_col_start + raster.cols as i64);
+            let row_from = block_row_start.max(raster_row_start);
+            let row_to = (block_row_start + cells_per_block).min(raster_row_start + raster.rows as i64);
+
+            for grid_row in row_from..row_to {
+                // Block rows run S to N whereas raster rows run N to S
+                let raster_row = raster.rows - 1 - (grid_row - raster_row_start) as usize;
+                let block_row = grid_row - block_row_start;
+                let block_index = (block_row * cells_per_block + col_from - block_col_start) as usize;
+                let raster_index = raster_row * raster.cols + (col_from - raster_col_start) as usize;
+                let width = (col_to - col_from) as usize;
+                raster.values[raster_index..raster_index + width]
+                    .copy_from_slice(&block[block_index..block_index + width]);
+            }
+        }
+    }
+    Ok(raster)
+}
+
+// Reads the elevations in metres for a bounding box
+pub fn extract_metres(data: &mut DataFile, bbox: &BoundingBox, nodata: f32) -> Result<Raster<f32>, Box<dyn Error>> {
+    let raster = extract_x10(data, bbox, NODATA_X10)?;
+    Ok(raster.map(nodata, |elev_x10| elev_x10 as f32 / 10f32))
+}
+
+// Reads the raw x10 elevations for a bounding box in bands of rows from N to S,
+// so that large regions can be processed without holding them all in memory
+pub fn extract_bands_x10(
+    data: &mut DataFile,
+    bbox: &BoundingBox,
+    band_rows: usize,
+    mut process_band: impl FnMut(Raster<i16>) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let cells = bbox.snap_to_cells();
+    let band_height = band_rows as i64 * os::ELEVATION_DISTANCE;
+    let mut band_top = cells.max_northing;
+    while band_top > cells.min_northing {
+        let band_bottom = (band_top - band_height).max(cells.min_northing);
+        let band_bbox = BoundingBox::new(cells.min_easting, band_bottom, cells.max_easting, band_top)?;
+        process_band(extract_x10(data, &band_bbox, NODATA_X10)?)?;
+        band_top = band_bottom;
+    }
+    Ok(())
+}
+
+// Reads every step-th 50m cell of a bounding box in each direction, giving a coarser
+// raster of (step x 50m) cells for overviews. Each cell takes the value at its SW corner.
+pub fn extract_resampled_x10(
+    data: &mut DataFile,
+    bbox: &BoundingBox,
+    step: usize,
+    nodata: i16,
+) -> Result<Raster<i16>, Box<dyn Error>> {
+    if step <= 1 {
+        return extract_x10(data, bbox, nodata);
+    }
+    let cells = bbox.snap_to_cells();
+    let (full_cols, full_rows) = cells.size_in_cells();
+    let (cols, rows) = (full_cols.div_ceil(step), full_rows.div_ceil(step));
+    let mut raster = Raster {
+        min_easting: cells.min_easting,
+        min_northing: cells.min_northing,
+        cell_size: os::ELEVATION_DISTANCE * step as i64,
+        cols,
+        rows,
+        nodata,
+        values: vec![nodata; cols * rows],
+    };
+
+    // Cell numbers of the raster edges counted from the full grid origin
+    let raster_col_start = cells.min_easting / os::ELEVATION_DISTANCE;
+    let raster_row_start = cells.min_northing / os::ELEVATION_DISTANCE;
+    let cells_per_block = os::ELEVATIONS_PER_ROW as i64;
+    let step = step as i64;
+
+    // Gets the range of resampled cells whose source cells lie in a range of full grid cells
+    let resampled = |from: i64, to: i64, start: i64, count: usize| -> std::ops::Range<i64> {
+        let first = (from - start).max(0);
+        let first = (first + step - 1) / step;
+        let last = ((to - start + step - 1) / step).min(count as i64);
+        first..last.max(first)
+    };
+
+    let block_size = os::METRES_IN_10_GRID;
+    for block_n in cells.min_northing.div_euclid(block_size)..=(cells.max_northing - 1).div_euclid(block_size) {
+        for block_e in cells.min_easting.div_euclid(block_size)..=(cells.max_easting - 1).div_euclid(block_size) {
+            let block_col_start = block_e * cells_per_block;
+            let block_row_start = block_n * cells_per_block;
+            let target_cols = resampled(block_col_start, block_col_start + cells_per_block, raster_col_start, cols);
+            let target_rows = resampled(block_row_start, block_row_start + cells_per_block, raster_row_start, rows);
+            if target_cols.is_empty() || target_rows.is_empty() {
+                continue;
+            }
+            let data_block_address = match data.data_block_address(block_e * block_size, block_n * block_size)? {
+                Some(address) => address,
+                None => continue,
+            };
+            let block = data.read_data_block(data_block_address)?;
+
+            for target_row in target_rows {
+                let block_row = raster_row_start + target_row * step - block_row_start;
+                let raster_row = rows - 1 - target_row as usize;
+                for target_col in target_cols.clone() {
+                    let block_col = raster_col_start + target_col * step - block_col_start;
+                    let value = block[(block_row * cells_per_block + block_col) as usize];
+                    raster.set(target_col as usize, raster_row, value);
+                }
+            }
+        }
+    }
+    Ok(raster)
+}
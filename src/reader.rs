@@ -0,0 +1,298 @@
+use crate::cache::{BlockCache, CacheStats};
+use crate::coords::{self, OSCoords};
+use crate::os;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path;
+
+/***********************************************************************
+   Code for reading the OS binary file
+************************************************************************/
+
+pub struct DataFile {
+    reader: BufReader<File>,
+    cache: Option<BlockCache>, // See enable_cache()
+}
+
+impl DataFile {
+    pub fn open(data_file: &str) -> Result<DataFile, Box<dyn Error>> {
+        let file_path = path::Path::new(&data_file);
+        if !file_path.is_file() {
+            return Err(format!("The data file path {} is not valid", &data_file).into());
+        }
+        let file = File::open(file_path)?;
+        Ok(DataFile {
+            reader: BufReader::new(file),
+            cache: None,
+        })
+    }
+
+    // Keeps the header section and up to budget_bytes of the most recently used
+    // data blocks in memory, so that repeated lookups in the same blocks don't go to disk
+    pub fn enable_cache(&mut self, budget_bytes: usize) -> Result<(), Box<dyn Error>> {
+        let header_length = os::FILE_SIG.len() as i64 + os::GRID_100.len() as i64 * os::HEADER_BLOCK_LENGTH;
+        let mut header = vec![0; header_length as usize];
+        self.reader.seek(SeekFrom::Start(0))?;
+        self.reader.read_exact(&mut header)?;
+        self.cache = Some(BlockCache::new(header, budget_bytes));
+        Ok(())
+    }
+
+    // Gets the cache hit & miss counts and memory use, if the cache is enabled
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.stats())
+    }
+
+    // Returns the address of the 10km² data block holding the location,
+    // or None if there is no data for it (i.e. it's a sea area)
+    pub fn data_block_address(&mut self, easting: i64, northing: i64) -> Result<Option<u64>, Box<dyn Error>> {
+        if !in_grid(easting, northing) {
+            return Ok(None);
+        }
+
+        let data_block_address = match &self.cache {
+            Some(cache) => cache
+                .address_at(data_block_address_offset(easting, northing))
+                .ok_or("The data file header is incomplete")? as u64,
+            None => {
+                // Jump to the location of the data block address
+                self.reader.seek(SeekFrom::Start(data_block_address_offset(easting, northing)))?;
+
+                // Read the four byte data address value stored there into a buffer
+                let mut address_buffer = [0; os::ADDRESS_LENGTH as usize];
+                self.reader.read_exact(&mut address_buffer)?;
+
+                // If there is a non-zero stored value there, convert it to
+                // a little endian address value
+                u32::from_le_bytes(address_buffer) as u64
+            }
+        };
+        if data_block_address == 0 {
+            return Ok(None);
+        }
+        Ok(Some(data_block_address))
+    }
+
+    // Returns the raw x10 elevation for a location, or None if there is no data for it
+    pub fn read_elevation_x10(&mut self, easting: i64, northing: i64) -> Result<Option<i16>, Box<dyn Error>> {
+        match self.data_block_address(easting, northing)? {
+            Some(data_block_address) if self.cache.as_ref().is_some_and(|cache| cache.holds_blocks()) => {
+                // Take the elevation from the whole data block
+                let index = (elevation_offset(easting, northing) / os::ELEVATION_DATA_LENGTH as u64) as usize;
+                self.with_data_block(data_block_address, |block| Some(block[index]))
+            }
+            Some(data_block_address) => {
+                // Apply the required elevation data offset to the data block address
+                // and jump there
+                let elev_addr = data_block_address + elevation_offset(easting, northing);
+                self.reader.seek(SeekFrom::Start(elev_addr))?;
+
+                // Read the elevation data as two bytes
+                let mut elevation_buffer = [0; os::ELEVATION_DATA_LENGTH as usize];
+                self.reader.read_exact(&mut elevation_buffer)?;
+                Ok(Some(i16::from_le_bytes(elevation_buffer)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // Reads all 40,000 x10 elevations of a data block in one go.
+    // The values are ordered W to E then S to N, as stored.
+    pub fn read_data_block(&mut self, data_block_address: u64) -> Result<Vec<i16>, Box<dyn Error>> {
+        if self.cache.is_some() {
+            return self.with_data_block(data_block_address, |block| block.to_vec());
+        }
+        self.read_data_block_from_disk(data_block_address)
+    }
+
+    // Gets a value from a data block's elevations, taking the block from the cache
+    // or reading and caching it
+    fn with_data_block<T>(&mut self, data_block_address: u64, get: impl Fn(&[i16]) -> T) -> Result<T, Box<dyn Error>> {
+        if let Some(block) = self.cache.as_mut().and_then(|cache| cache.get(data_block_address)) {
+            return Ok(get(block));
+        }
+        let block = self.read_data_block_from_disk(data_block_address)?;
+        let value = get(&block);
+        if let Some(cache) = self.cache.as_mut() {
+            cache.insert(data_block_address, block);
+        }
+        Ok(value)
+    }
+
+    fn read_data_block_from_disk(&mut self, data_block_address: u64) -> Result<Vec<i16>, Box<dyn Error>> {
+        self.reader.seek(SeekFrom::Start(data_block_address))?;
+        let mut block_buffer = vec![0; os::DATA_BLOCK_LENGTH as usize];
+        self.reader.read_exact(&mut block_buffer)?;
+
+        let elevations = block_buffer
+            .chunks_exact(os::ELEVATION_DATA_LENGTH as usize)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        Ok(elevations)
+    }
+
+    // As read_elevations below, but reading from this already open file so that
+    // long running callers only open the file once
+    pub fn read_elevations(&mut self, coords_list: &[OSCoords], infill: bool) -> Result<Vec<OSCoords>, Box<dyn Error>> {
+        elevations_of(coords_list, infill, |easting, northing| self.read_elevation_x10(easting, northing))
+    }
+
+    // As read_elevations without infills, but for large batches of scattered locations.
+    // The locations are grouped by data block so that each block is read only once,
+    // rather than jumping around the file. The results keep the input order.
+    pub fn read_elevations_batch(&mut self, coords_list: &[OSCoords]) -> Result<Vec<OSCoords>, Box<dyn Error>> {
+        // Sort by data block then by position in the block, with locations outside the grid first
+        let block_of = |coord: &OSCoords| {
+            in_grid(coord.easting, coord.northing).then(|| {
+                (
+                    data_block_address_offset(coord.easting, coord.northing),
+                    elevation_offset(coord.easting, coord.northing),
+                )
+            })
+        };
+        let mut order: Vec<usize> = (0..coords_list.len()).collect();
+        order.sort_by_key(|&i| block_of(&coords_list[i]));
+
+        let mut coords = coords_list.to_vec();
+        let mut start = 0;
+        while start < order.len() {
+            // The run of locations in the same data block
+            let block = block_of(&coords_list[order[start]]).map(|(header_offset, _)| header_offset);
+            let end = start
+                + order[start..]
+                    .iter()
+                    .take_while(|&&i| block_of(&coords_list[i]).map(|(header_offset, _)| header_offset) == block)
+                    .count();
+
+            let first = coords_list[order[start]];
+            let elevations = match block {
+                Some(_) => match self.data_block_address(first.easting, first.northing)? {
+                    Some(data_block_address) => Some(self.read_data_block(data_block_address)?),
+                    None => None,
+                },
+                None => None,
+            };
+            for &i in &order[start..end] {
+                let coord = &mut coords[i];
+                // Sea areas are 0, as for read_elevations
+                coord.elevation = Some(match &elevations {
+                    Some(elevations) => {
+                        let index = elevation_offset(coord.easting, coord.northing) / os::ELEVATION_DATA_LENGTH as u64;
+                        elevations[index as usize] as f32 / 10f32
+                    }
+                    None => 0f32,
+                });
+            }
+            start = end;
+        }
+        Ok(coords)
+    }
+}
+
+// Checks whether a location lies within the full 91 grid block
+pub fn in_grid(easting: i64, northing: i64) -> bool {
+    (0..os::GRIDS_PER_ROW_100 * os::METRES_IN_100_GRID).contains(&easting)
+        && (0..os::GRIDS_PER_COL_100 * os::METRES_IN_100_GRID).contains(&northing)
+}
+
+// Calculates the offset from the start of the file to the header
+// placeholder which holds the data block address for a location
+pub fn data_block_address_offset(easting: i64, northing: i64) -> u64 {
+    // Work out how many grid blocks to jump over in the file header section.
+    // NB: uses integer division to deliberately truncate the remainders - use floor(),
+    // trunc() etc. in untyped languages
+
+    // Reduce the coords down to obtain whole grid unit multipliers and apply them to
+    // calculate the mumber of grids to jump over
+    let e_cols = easting / os::METRES_IN_100_GRID;
+    let n_rows = northing / os::METRES_IN_100_GRID;
+    let grid_blocks_to_jump = (os::GRIDS_PER_ROW_100 * n_rows) + e_cols;
+
+    // Calculate the offset from start of file to the start of the required grid block
+    let grid_block_offset = os::FILE_SIG.len() as i64 + (grid_blocks_to_jump * os::HEADER_BLOCK_LENGTH);
+
+    // Now work out how many data address placeholders to jump within the grid block section.
+    // (NB: integer division truncation)
+    let e_addr_cols = (easting % os::METRES_IN_100_GRID) / os::METRES_IN_10_GRID;
+    let n_addr_rows = (northing % os::METRES_IN_100_GRID) / os::METRES_IN_10_GRID;
+    let data_placeholders_to_jump = (n_addr_rows * os::ROWS_IN_10_GRID) + e_addr_cols;
+
+    // Can now determine the individual data block address to jump to within the grid block
+    (grid_block_offset + os::GRID_IDENT_LEN + (data_placeholders_to_jump * os::ADDRESS_LENGTH)) as u64
+}
+
+// Calculates the offset required to get to the desired elevation within a data block
+pub fn elevation_offset(easting: i64, northing: i64) -> u64 {
+    // First reduce the coords to just the parts applicable in a 10 Km² data grid
+    let data_easting = (easting % os::METRES_IN_100_GRID) % os::METRES_IN_10_GRID;
+    let data_northing = (northing % os::METRES_IN_100_GRID) % os::METRES_IN_10_GRID;
+
+    // Then work out how many data rows and columns must be jumped (elevations are every 50m)
+    // (NB: integer division truncation)
+    let data_cols = data_easting / os::ELEVATION_DISTANCE;
+    let data_rows = data_northing / os::ELEVATION_DISTANCE;
+    let data_rows_to_jump = (data_rows * os::ELEVATIONS_PER_ROW as i64) + data_cols;
+
+    (data_rows_to_jump * os::ELEVATION_DATA_LENGTH) as u64
+}
+
+pub fn read_elevations(
+    data_file: &str,
+    coords_list: &[OSCoords],
+    infill: bool,
+) -> Result<Vec<OSCoords>, Box<dyn Error>> {
+    // Returns a vec of the supplied coordinates with the elevation provided for each coordinate.
+    // Optionally creates infill coordinates and elevations at approx. 50m intervals between
+    // each coordinate pair.
+
+    if coords_list.is_empty() {
+        panic!("Need at least one location in the coords list");
+    }
+
+    let mut data = match DataFile::open(data_file) {
+        Ok(data) => data,
+        Err(e) => panic!("{}", e),
+    };
+    data.read_elevations(coords_list, infill)
+}
+
+// Gets the supplied coordinates (plus any infills) with their elevations, reading the
+// raw x10 elevation of each location with a function which returns None for sea areas.
+// This is shared by the readers so that they all treat infills and the sea the same way.
+pub fn elevations_of(
+    coords_list: &[OSCoords],
+    infill: bool,
+    mut read_elevation_x10: impl FnMut(i64, i64) -> Result<Option<i16>, Box<dyn Error>>,
+) -> Result<Vec<OSCoords>, Box<dyn Error>> {
+    let mut coords: Vec<OSCoords> = Vec::new();
+
+    if infill {
+        for i in 0..coords_list.len() {
+            // Need at least two locations to prepare infills
+            if i > 0 {
+                // Avoid double insertions where previous end == current start
+                let include_start = i < 2;
+                let infills = coords::get_infills(coords_list[i - 1], coords_list[i], include_start);
+                // Merge the results
+                coords.extend(infills);
+            }
+        }
+    } else {
+        // No infills required so just process the input locations
+        coords = coords_list.to_vec();
+    }
+
+    for coord in coords.iter_mut() {
+        // Because elevation data never has more than one decimal place, it's stored
+        // as 10x actual value as little endian i16 for space-efficient storage
+        match read_elevation_x10(coord.easting, coord.northing)? {
+            Some(elev_x10) => coord.elevation = Some(elev_x10 as f32 / 10f32),
+
+            // No data address means no data exists for this location, i.e. it's a
+            // sea area or an out-of-scope land mass, e.g. the Isle of Man
+            None => coord.elevation = Some(0 as f32),
+        }
+    }
+    Ok(coords)
+}
@@ -0,0 +1,166 @@
+use crate::derivatives::{self, Method};
+use crate::raster::{self, BoundingBox, Raster};
+use crate::reader::DataFile;
+use std::{error::Error, fs, io, path};
+
+/***********************************************************************
+    Code for rendering a region of the OS binary file as a PNG image
+
+    Each 50m cell (or resampled cell) becomes one pixel. The pixel is
+    shaded by the hillshade of the terrain around it and optionally
+    tinted by a hypsometric colour ramp. Cells in blocks without a data
+    address (sea areas) are drawn in the sea colour.
+************************************************************************/
+
+// Hypsometric colour ramp as elevation (m) and RGB colour stops
+const COLOUR_RAMP: [(f64, [u8; 3]); 7] = [
+    (0.0, [112, 153, 89]),
+    (50.0, [150, 180, 100]),
+    (200.0, [220, 210, 140]),
+    (400.0, [200, 160, 100]),
+    (700.0, [160, 110, 80]),
+    (1000.0, [200, 190, 180]),
+    (1345.0, [255, 255, 255]),
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOptions {
+    pub azimuth: f64, // Direction of the sun in degrees clockwise from north
+    pub altitude: f64, // Angle of the sun in degrees above the horizon
+    pub z_factor: f64, // Vertical exaggeration
+    pub tint: bool, // Colour by elevation rather than just grey shading
+    pub sea_colour: [u8; 3],
+    pub step: usize, // Render every step-th cell for overviews of large regions
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            azimuth: 315.0,
+            altitude: 45.0,
+            z_factor: 1.0,
+            tint: false,
+            sea_colour: [160, 200, 240],
+            step: 1,
+        }
+    }
+}
+
+// Renders a bounding box to a PNG file. Returns the image width & height.
+pub fn write_png(
+    data: &mut DataFile,
+    bbox: &BoundingBox,
+    output_file: &path::Path,
+    options: &RenderOptions,
+) -> Result<(usize, usize), Box<dyn Error>> {
+    let grid = raster::extract_resampled_x10(data, bbox, options.step, raster::NODATA_X10)?;
+    let pixels = render(&grid, options);
+
+    let file = fs::File::create(output_file)?;
+    let mut encoder = png::Encoder::new(io::BufWriter::new(file), grid.cols as u32, grid.rows as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok((grid.cols, grid.rows))
+}
+
+// Renders a raster of x10 elevations to RGB pixels, N to S and W to E
+pub fn render(grid: &Raster<i16>, options: &RenderOptions) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(grid.cols * grid.rows * 3);
+    for row in 0..grid.rows {
+        for col in 0..grid.cols {
+            if grid.is_nodata(col, row) {
+                pixels.extend(options.sea_colour);
+                continue;
+            }
+            let shade = hillshade(grid, col, row, options);
+            let base = if options.tint {
+                ramp_colour(grid.get(col, row) as f64 / 10.0)
+            } else {
+                [255, 255, 255]
+            };
+            pixels.extend(base.map(|channel| (channel as f64 * shade).round() as u8));
+        }
+    }
+    pixels
+}
+
+// Gets the hillshade of a cell from 0 (in full shadow) to 1 (facing the sun)
+// using Horn's method over the 3 x 3 cells around it
+fn hillshade(grid: &Raster<i16>, col: usize, row: usize, options: &RenderOptions) -> f64 {
+    let z = match derivatives::window(grid, col, row) {
+        Some(z) => z,
+        None => return 0.0,
+    };
+    let (dz_de, dz_dn) = derivatives::gradient(&z, grid.cell_size as f64, Method::Horn);
+
+    let slope = (options.z_factor * (dz_de * dz_de + dz_dn * dz_dn).sqrt()).atan();
+    let aspect = (-dz_dn).atan2(-dz_de);
+    let zenith = (90.0 - options.altitude).to_radians();
+    let azimuth = (360.0 - options.azimuth + 90.0).rem_euclid(360.0).to_radians();
+    let shade = zenith.cos() * slope.cos() + zenith.sin() * slope.sin() * (azimuth - aspect).cos();
+    shade.clamp(0.0, 1.0)
+}
+
+// Gets the colour for an elevation by interpolating between the colour ramp stops
+fn ramp_colour(elevation: f64) -> [u8; 3] {
+    let (first, last) = (COLOUR_RAMP[0], COLOUR_RAMP[COLOUR_RAMP.len() - 1]);
+    if elevation <= first.0 {
+        return first.1;
+    }
+    if elevation >= last.0 {
+        return last.1;
+    }
+    let upper = COLOUR_RAMP.iter().position(|stop| stop.0 >= elevation).unwrap_or(COLOUR_RAMP.len() - 1);
+    let (low, high) = (COLOUR_RAMP[upper - 1], COLOUR_RAMP[upper]);
+    let f = (elevation - low.0) / (high.0 - low.0);
+    [0, 1, 2].map(|i| (low.1[i] as f64 + (high.1[i] as f64 - low.1[i] as f64) * f).round() as u8)
+}
+
+// Parses a colour given as "#rrggbb" or "rrggbb"
+pub fn parse_colour(colour: &str) -> Result<[u8; 3], Box<dyn Error>> {
+    let hex = colour.trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(format!("The colour {} is not in the form #rrggbb", colour).into());
+    }
+    let mut rgb = [0; 3];
+    for (i, channel) in rgb.iter_mut().enumerate() {
+        *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| format!("The colour {} is not in the form #rrggbb", colour))?;
+    }
+    Ok(rgb)
+}
+
+#[test]
+fn flat_terrain_is_lit_by_sun_altitude() {
+    let bbox = BoundingBox::new(0, 0, 150, 150).unwrap();
+    let mut grid = Raster::new(&bbox, raster::NODATA_X10);
+    grid.values.fill(1000);
+    let options = RenderOptions::default();
+    let expected = 45f64.to_radians().cos();
+    assert!((hillshade(&grid, 1, 1, &options) - expected).abs() < 1e-9);
+}
+
+#[test]
+fn slope_facing_the_sun_is_brighter() {
+    // Ground rising to the east faces west, away from a sun in the east
+    let bbox = BoundingBox::new(0, 0, 150, 150).unwrap();
+    let mut grid = Raster::new(&bbox, raster::NODATA_X10);
+    for row in 0..3 {
+        for col in 0..3 {
+            grid.set(col, row, col as i16 * 200);
+        }
+    }
+    let west_sun = RenderOptions { azimuth: 270.0, ..RenderOptions::default() };
+    let east_sun = RenderOptions { azimuth: 90.0, ..RenderOptions::default() };
+    assert!(hillshade(&grid, 1, 1, &west_sun) > hillshade(&grid, 1, 1, &east_sun));
+}
+
+#[test]
+fn colour_ramp_and_parsing() {
+    assert_eq!([112, 153, 89], ramp_colour(-2.0));
+    assert_eq!([255, 255, 255], ramp_colour(1345.0));
+    assert_eq!([131, 167, 95], ramp_colour(25.0));
+    assert_eq!([160, 200, 240], parse_colour("#a0c8f0").unwrap());
+    assert!(parse_colour("blue").is_err());
+}
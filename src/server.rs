@@ -0,0 +1,289 @@
+use crate::coords::{self, OSCoords};
+use crate::mapped::MappedDataFile;
+use crate::os;
+use crate::projection;
+use crate::reader;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/***********************************************************************
+    A small local HTTP service answering elevation queries as JSON
+
+    The binary data file is memory-mapped once and shared by all the
+    requests, which are each answered on their own thread:
+
+      GET  /elevation?ref=SU 12345 67890   (or ?e=..&n=.., or ?lat=..&lon=..)
+      POST /elevations   {"locations": [...]}
+      POST /profile      {"locations": [...]}  (infilled every 50m or so)
+
+    Each location in a POST body is a grid reference or "easting,northing"
+    string, an {"easting": .., "northing": ..} object or a {"lat": ..,
+    "lon": ..} WGS84 object. Sea areas have an elevation of 0, as for
+    read_elevations. Locations outside the National Grid, and profiles of
+    more than MAX_PROFILE_POINTS, are refused with a 400 response.
+************************************************************************/
+
+const MAX_BODY_LENGTH: usize = 10_000_000; // Bytes
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_PROFILE_POINTS: f64 = 100_000.0; // Including the infills, i.e. a profile of about 5,000km
+
+pub struct Server {
+    listener: TcpListener,
+    data: Arc<MappedDataFile>,
+}
+
+impl Server {
+    // Opens the data file and listens on an address such as "127.0.0.1:8080"
+    pub fn bind(data_file: &str, address: &str) -> Result<Server, Box<dyn Error>> {
+        Ok(Server {
+            data: Arc::new(MappedDataFile::open(data_file)?),
+            listener: TcpListener::bind(address)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Box<dyn Error>> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    // Answers requests until the process is stopped. A failed request is
+    // reported and doesn't stop the service.
+    pub fn run(self) -> Result<(), Box<dyn Error>> {
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Connection failed: {}", e);
+                    continue;
+                }
+            };
+            let data = Arc::clone(&self.data);
+            thread::spawn(move || {
+                if let Err(e) = handle(&data, stream) {
+                    eprintln!("Request failed: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+// Reads a single request from a connection and writes the response
+fn handle(data: &MappedDataFile, stream: TcpStream) -> Result<(), Box<dyn Error>> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+
+    // The request line, e.g. "GET /elevation?ref=SU1234 HTTP/1.1", then the headers
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().map_err(|_| "Invalid Content-Length")?;
+            }
+        }
+    }
+
+    let (status, body) = if content_length > MAX_BODY_LENGTH {
+        (413, json!({ "error": "The request body is too large" }))
+    } else {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        respond(data, method, target, &String::from_utf8_lossy(&body))
+    };
+
+    let body = body.to_string();
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+// Gets the status code and JSON response for a request
+pub fn respond(data: &MappedDataFile, method: &str, target: &str, body: &str) -> (u16, Value) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let result = match (method, path) {
+        ("GET", "/elevation") => point_elevation(data, &parse_query(query)),
+        ("POST", "/elevations") => batch_elevations(data, body),
+        ("POST", "/profile") => profile(data, body),
+        (_, "/elevation" | "/elevations" | "/profile") => {
+            return (405, json!({ "error": format!("{} is not supported for {}", method, path) }))
+        }
+        _ => return (404, json!({ "error": format!("Unknown path {}", path) })),
+    };
+    match result {
+        Ok(value) => (200, value),
+        Err(e) => (400, json!({ "error": e.to_string() })),
+    }
+}
+
+fn point_elevation(data: &MappedDataFile, query: &HashMap<String, String>) -> Result<Value, Box<dyn Error>> {
+    let location = if let Some(grid_ref) = query.get("ref") {
+        parse_location(&json!(grid_ref))?
+    } else if let (Some(easting), Some(northing)) = (query.get("e"), query.get("n")) {
+        let easting: f64 = easting.parse().map_err(|_| "The easting is not numeric")?;
+        let northing: f64 = northing.parse().map_err(|_| "The northing is not numeric")?;
+        parse_location(&json!({ "easting": easting, "northing": northing }))?
+    } else if let (Some(lat), Some(lon)) = (query.get("lat"), query.get("lon")) {
+        let lat: f64 = lat.parse().map_err(|_| "The latitude is not numeric")?;
+        let lon: f64 = lon.parse().map_err(|_| "The longitude is not numeric")?;
+        parse_location(&json!({ "lat": lat, "lon": lon }))?
+    } else {
+        return Err("Give a location as ref=<grid ref>, e=<easting>&n=<northing> or lat=<latitude>&lon=<longitude>".into());
+    };
+    let coords = data.read_elevations(&[location], false)?;
+    Ok(to_json(&coords[0]))
+}
+
+fn batch_elevations(data: &MappedDataFile, body: &str) -> Result<Value, Box<dyn Error>> {
+    let locations = parse_locations(body)?;
+    let coords = data.read_elevations(&locations, false)?;
+    Ok(json!({ "results": coords.iter().map(to_json).collect::<Vec<_>>() }))
+}
+
+fn profile(data: &MappedDataFile, body: &str) -> Result<Value, Box<dyn Error>> {
+    let locations = parse_locations(body)?;
+    if locations.len() < 2 {
+        return Err("A profile needs at least 2 locations".into());
+    }
+    // Check the number of infills before making them
+    let points: f64 = locations
+        .windows(2)
+        .map(|pair| {
+            let distance = ((pair[1].easting - pair[0].easting) as f64).hypot((pair[1].northing - pair[0].northing) as f64);
+            (distance / os::ELEVATION_DISTANCE as f64).ceil()
+        })
+        .sum();
+    if points > MAX_PROFILE_POINTS {
+        return Err(format!("The profile would have over {} points", MAX_PROFILE_POINTS).into());
+    }
+    let coords = data.read_elevations(&locations, true)?;
+
+    // Add the distance along the profile to each point
+    let mut distance = 0.0;
+    let points: Vec<Value> = coords
+        .iter()
+        .enumerate()
+        .map(|(i, coord)| {
+            if i > 0 {
+                let previous = coords[i - 1];
+                distance += ((coord.easting - previous.easting) as f64).hypot((coord.northing - previous.northing) as f64);
+            }
+            let mut point = to_json(coord);
+            point["distance"] = json!((distance * 10.0).round() / 10.0);
+            point
+        })
+        .collect();
+    Ok(json!({ "distance": (distance * 10.0).round() / 10.0, "points": points }))
+}
+
+fn to_json(coord: &OSCoords) -> Value {
+    json!({ "easting": coord.easting, "northing": coord.northing, "elevation": coord.elevation })
+}
+
+// Gets the locations from a JSON body of the form {"locations": [...]}
+fn parse_locations(body: &str) -> Result<Vec<OSCoords>, Box<dyn Error>> {
+    let body: Value = serde_json::from_str(body).map_err(|e| format!("The request body is not valid JSON: {}", e))?;
+    let locations = body["locations"]
+        .as_array()
+        .ok_or("The request body has no locations array")?;
+    if locations.is_empty() {
+        return Err("The locations array is empty".into());
+    }
+    locations.iter().map(parse_location).collect()
+}
+
+// Converts a JSON location (see the top of the file) to full grid coordinates inside the grid
+fn parse_location(location: &Value) -> Result<OSCoords, Box<dyn Error>> {
+    let coords = parse_any_location(location)?;
+    if !reader::in_grid(coords.easting, coords.northing) {
+        return Err(format!("The location {} is outside the National Grid", location).into());
+    }
+    Ok(coords)
+}
+
+fn parse_any_location(location: &Value) -> Result<OSCoords, Box<dyn Error>> {
+    let coords = |easting: f64, northing: f64| OSCoords {
+        easting: easting.round() as i64,
+        northing: northing.round() as i64,
+        elevation: None,
+    };
+    if let Some(text) = location.as_str() {
+        return coords::parse_coord(text)
+            .map_err(|e| format!("The location {:?} is not a grid reference or easting,northing: {}", text, e).into());
+    }
+    if let (Some(easting), Some(northing)) = (location["easting"].as_f64(), location["northing"].as_f64()) {
+        return Ok(coords(easting, northing));
+    }
+    if let (Some(lat), Some(lon)) = (location["lat"].as_f64(), location["lon"].as_f64()) {
+        if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+            return Err(format!("The latitude & longitude {},{} are out of range", lat, lon).into());
+        }
+        let (easting, northing) = projection::wgs84_to_os(lat, lon);
+        return Ok(coords(easting, northing));
+    }
+    Err(format!("The location {} is not a grid reference, easting & northing or latitude & longitude", location).into())
+}
+
+// Splits a URL query string into decoded names & values
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (decode(name), decode(value)))
+        .collect()
+}
+
+// Decodes "+" and "%XX" escapes in a URL query component
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let hex = |i: usize| bytes.get(i).and_then(|&byte| (byte as char).to_digit(16));
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], hex(i + 1), hex(i + 2)) {
+            (b'+', _, _) => decoded.push(b' '),
+            (b'%', Some(high), Some(low)) => {
+                decoded.push((high * 16 + low) as u8);
+                i += 2;
+            }
+            (byte, _, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[test]
+fn query_strings() {
+    let query = parse_query("ref=SU+12345%2067890&x=1");
+    assert_eq!(Some(&"SU 12345 67890".to_string()), query.get("ref"));
+    assert_eq!("100%", decode("100%"));
+}
@@ -0,0 +1,161 @@
+use crate::raster::Raster;
+use std::cmp::Reverse;
+
+/***********************************************************************
+    Code for finding summits and their topographic prominence
+
+    The land is flooded downwards from the highest cell. Each cell is
+    joined to the islands of higher cells around it, and a cell with no
+    higher neighbours starts a new island whose top is a summit. Where a
+    cell joins two or more islands it is a col: every island except the
+    one with the highest summit is absorbed, and the prominence of each
+    absorbed summit is its height above this col (its key col).
+
+    Summits which are never absorbed are the highest points of islands in
+    the sea, or of the region, and their prominence is their full height.
+    NB: a key col may lie outside the region, so summits near its edges
+    may show more prominence than they really have.
+
+    Cells are 8-connected, so ridges which only touch diagonally still join.
+************************************************************************/
+
+const NOT_FLOODED: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summit {
+    pub easting: f64, // Of the summit cell centre
+    pub northing: f64,
+    pub elevation: f64,
+    pub prominence: f64,
+    pub key_col: Option<(f64, f64, f64)>, // Easting, northing & elevation, or None for island high points
+}
+
+// Finds the summits in a raster of x10 values with at least the given prominence,
+// ranked by prominence then elevation
+pub fn find_summits(grid: &Raster<i16>, min_prominence: f64) -> Vec<Summit> {
+    // Flood the land cells from highest to lowest
+    let mut order: Vec<u32> = (0..grid.values.len() as u32)
+        .filter(|&i| grid.values[i as usize] != grid.nodata)
+        .collect();
+    order.sort_by_key(|&i| Reverse(grid.values[i as usize]));
+
+    // Each flooded cell points towards the root cell of its island,
+    // and the root cell records the island's summit
+    let mut parent = vec![NOT_FLOODED; grid.values.len()];
+    let mut summit_of = vec![NOT_FLOODED; grid.values.len()];
+    let mut summits = Vec::new();
+
+    let centre = |index: u32| -> (f64, f64, f64) {
+        let (col, row) = (index as usize % grid.cols, index as usize / grid.cols);
+        let cell_size = grid.cell_size as f64;
+        (
+            grid.min_easting as f64 + (col as f64 + 0.5) * cell_size,
+            grid.max_northing() as f64 - (row as f64 + 0.5) * cell_size,
+            grid.values[index as usize] as f64 / 10.0,
+        )
+    };
+
+    for &cell in &order {
+        // Find the distinct islands around the cell, highest summit first
+        let (col, row) = (cell as usize % grid.cols, cell as usize / grid.cols);
+        let mut islands: Vec<u32> = Vec::new();
+        for dr in -1..=1isize {
+            for dc in -1..=1isize {
+                let (c, r) = (col as isize + dc, row as isize + dr);
+                if c < 0 || r < 0 || c >= grid.cols as isize || r >= grid.rows as isize {
+                    continue;
+                }
+                let neighbour = r as usize * grid.cols + c as usize;
+                if parent[neighbour] != NOT_FLOODED {
+                    let root = find_root(&mut parent, neighbour as u32);
+                    if !islands.contains(&root) {
+                        islands.push(root);
+                    }
+                }
+            }
+        }
+        // Ties go to the summit found first
+        islands.sort_by_key(|&root| {
+            let summit = summit_of[root as usize];
+            (Reverse(grid.values[summit as usize]), summit)
+        });
+
+        match islands.first() {
+            None => {
+                parent[cell as usize] = cell;
+                summit_of[cell as usize] = cell;
+            }
+            Some(&highest) => {
+                // The cell is the key col of every other island's summit
+                for &island in &islands[1..] {
+                    let (easting, northing, elevation) = centre(summit_of[island as usize]);
+                    let col = centre(cell);
+                    summits.push(Summit {
+                        easting,
+                        northing,
+                        elevation,
+                        prominence: elevation - col.2,
+                        key_col: Some(col),
+                    });
+                    parent[island as usize] = highest;
+                }
+                parent[cell as usize] = highest;
+            }
+        }
+    }
+
+    // The summits still at the top of their islands
+    for &cell in &order {
+        if parent[cell as usize] == cell {
+            let (easting, northing, elevation) = centre(summit_of[cell as usize]);
+            summits.push(Summit {
+                easting,
+                northing,
+                elevation,
+                prominence: elevation,
+                key_col: None,
+            });
+        }
+    }
+
+    summits.retain(|summit| summit.prominence > 0.0 && summit.prominence >= min_prominence);
+    summits.sort_by(|a, b| {
+        b.prominence
+            .total_cmp(&a.prominence)
+            .then(b.elevation.total_cmp(&a.elevation))
+    });
+    summits
+}
+
+// Finds the root cell of an island, shortening the path to it on the way
+fn find_root(parent: &mut [u32], cell: u32) -> u32 {
+    let mut root = cell;
+    while parent[root as usize] != root {
+        root = parent[root as usize];
+    }
+    let mut current = cell;
+    while parent[current as usize] != root {
+        let next = parent[current as usize];
+        parent[current as usize] = root;
+        current = next;
+    }
+    root
+}
+
+#[test]
+fn two_peaks_and_a_col() {
+    use crate::raster::{self, BoundingBox};
+    let bbox = BoundingBox::new(0, 0, 250, 50).unwrap();
+    let mut grid = Raster::new(&bbox, raster::NODATA_X10);
+    grid.values.copy_from_slice(&[100, 500, 200, 300, 50]);
+
+    let summits = find_summits(&grid, 0.0);
+    assert_eq!(2, summits.len());
+    // The higher peak is an island high point
+    assert_eq!((75.0, 50.0, 50.0, None), (summits[0].easting, summits[0].elevation, summits[0].prominence, summits[0].key_col));
+    // The lower peak drops 10m to its key col
+    assert_eq!((175.0, 30.0, 10.0), (summits[1].easting, summits[1].elevation, summits[1].prominence));
+    assert_eq!(Some((125.0, 25.0, 20.0)), summits[1].key_col);
+
+    assert_eq!(1, find_summits(&grid, 15.0).len());
+}
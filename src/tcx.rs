@@ -0,0 +1,108 @@
+use crate::activity::{self, Correction, CorrectionOptions, CorrectionSummary, TrackPoint};
+use crate::reader::DataFile;
+use crate::xml::{self, Element};
+use std::error::Error;
+
+/***********************************************************************
+    Code for reading the track of a Garmin TCX activity file and writing
+    it back with corrected altitudes
+
+    The track points are the Trackpoint elements, each with an optional
+    Position (LatitudeDegrees & LongitudeDegrees) and AltitudeMeters. The
+    file is edited as text (see xml.rs), so only the AltitudeMeters are
+    changed. A Trackpoint with a Position but no AltitudeMeters gets one
+    after its Position, where the TCX schema puts it.
+
+    TCX has no total ascent, so that's only reported.
+************************************************************************/
+
+// A Trackpoint in the TCX text, with the elements to be edited
+struct Point {
+    position: Option<Element>,
+    altitude: Option<Element>,
+    point: TrackPoint,
+}
+
+// Gets the track points of the Trackpoint elements
+pub fn track_points(tcx: &str) -> Result<Vec<TrackPoint>, Box<dyn Error>> {
+    Ok(parse(tcx)?.into_iter().map(|point| point.point).collect())
+}
+
+// Gets a copy of a TCX file with the altitudes corrected with the terrain elevations
+pub fn correct_tcx(data: &mut DataFile, tcx: &str, options: &CorrectionOptions) -> Result<(String, CorrectionSummary), Box<dyn Error>> {
+    let points = parse(tcx)?;
+    let track: Vec<TrackPoint> = points.iter().map(|point| point.point).collect();
+    let Correction { altitudes, summary } = activity::correct_altitudes(data, &track, options)?;
+
+    let mut edits = Vec::new();
+    for (point, altitude) in points.iter().zip(altitudes) {
+        let altitude = match altitude {
+            Some(altitude) => format!("{:.1}", altitude),
+            None => continue,
+        };
+        match (point.altitude, point.position) {
+            (Some(element), _) => edits.push((element.content_start, element.content_end, altitude)),
+            (None, Some(position)) => edits.push((position.end, position.end, format!("<AltitudeMeters>{}</AltitudeMeters>", altitude))),
+            (None, None) => (),
+        }
+    }
+    Ok((xml::apply_edits(tcx, edits), summary))
+}
+
+fn parse(tcx: &str) -> Result<Vec<Point>, Box<dyn Error>> {
+    let mut points = Vec::new();
+    for trackpoint in xml::find_elements(tcx, "Trackpoint", 0, tcx.len()) {
+        let (start, end) = (trackpoint.content_start, trackpoint.content_end);
+        let number = |element: &Element, name: &str| -> Result<f64, Box<dyn Error>> {
+            let text = tcx[element.content_start..element.content_end].trim();
+            text.parse()
+                .map_err(|_| format!("Trackpoint {} has a {} of {:?}, which is not a number", points.len() + 1, name, text).into())
+        };
+
+        let position = xml::find_element(tcx, "Position", start, end);
+        let lat_lon = match position {
+            Some(position) => {
+                let (lat, lon) = match (
+                    xml::find_element(tcx, "LatitudeDegrees", position.content_start, position.content_end),
+                    xml::find_element(tcx, "LongitudeDegrees", position.content_start, position.content_end),
+                ) {
+                    (Some(lat), Some(lon)) => (lat, lon),
+                    _ => return Err(format!("Trackpoint {} has a Position without a latitude & longitude", points.len() + 1).into()),
+                };
+                Some((number(&lat, "LatitudeDegrees")?, number(&lon, "LongitudeDegrees")?))
+            }
+            None => None,
+        };
+        let altitude = xml::find_element(tcx, "AltitudeMeters", start, end);
+        let recorded = match &altitude {
+            Some(altitude) => Some(number(altitude, "AltitudeMeters")?),
+            None => None,
+        };
+        points.push(Point {
+            position,
+            altitude,
+            point: TrackPoint {
+                position: lat_lon,
+                altitude: recorded,
+            },
+        });
+    }
+    Ok(points)
+}
+
+#[test]
+fn reads_trackpoints() {
+    let tcx = "<Track>
+        <Trackpoint><Time>t</Time><Position><LatitudeDegrees>51.5</LatitudeDegrees><LongitudeDegrees>-1.8</LongitudeDegrees></Position><AltitudeMeters>120.4</AltitudeMeters></Trackpoint>
+        <Trackpoint><Time>t</Time><HeartRateBpm><Value>120</Value></HeartRateBpm></Trackpoint>
+        </Track>";
+    let points = track_points(tcx).unwrap();
+    assert_eq!(
+        vec![
+            TrackPoint { position: Some((51.5, -1.8)), altitude: Some(120.4) },
+            TrackPoint { position: None, altitude: None }
+        ],
+        points
+    );
+    assert!(track_points("<Trackpoint><AltitudeMeters>high</AltitudeMeters></Trackpoint>").is_err());
+}
@@ -0,0 +1,305 @@
+use crate::os;
+use crate::projection;
+use crate::raster::{self, BoundingBox};
+use crate::reader::DataFile;
+use std::f64::consts::PI;
+use std::{error::Error, fs, path};
+
+/***********************************************************************
+    Code for writing a Web Mercator tile pyramid of elevations encoded as
+    Mapbox Terrain-RGB or Terrarium PNG tiles, either to a z/x/y directory
+    tree or to an MBTiles (SQLite) file.
+
+    Each tile is reprojected by converting the Web Mercator position of every
+    pixel to an OS easting & northing and interpolating the elevation there.
+    Lower zooms read a resampled raster so that a tile never needs more cells
+    than it has pixels. Tiles which are entirely sea are not written.
+************************************************************************/
+
+const TILE_PIXELS: usize = 256; // Width & height of a tile in pixels
+const CONTROL_SPACING: usize = 16; // Pixels between exactly reprojected control points
+const MIN_CONTROL_ZOOM: u8 = 8; // Lowest zoom at which control points are used
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerrainEncoding {
+    Mapbox,    // height = -10000 + ((R * 256 * 256 + G * 256 + B) * 0.1)
+    Terrarium, // height = (R * 256 + G + B / 256) - 32768
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TileOptions {
+    pub encoding: TerrainEncoding,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+}
+
+// Where the encoded tiles are written
+enum TileSink {
+    Directory(path::PathBuf),
+    MBTiles(rusqlite::Connection),
+}
+
+impl TileSink {
+    fn put(&mut self, zoom: u8, x: u32, y: u32, png: &[u8]) -> Result<(), Box<dyn Error>> {
+        match self {
+            TileSink::Directory(dir) => {
+                let tile_dir = dir.join(zoom.to_string()).join(x.to_string());
+                fs::create_dir_all(&tile_dir)?;
+                fs::write(tile_dir.join(format!("{}.png", y)), png)?;
+            }
+            TileSink::MBTiles(connection) => {
+                // MBTiles rows are numbered from the south (TMS) rather than the north (XYZ)
+                let tile_row = (1u32 << zoom) - 1 - y;
+                connection.execute(
+                    "INSERT OR REPLACE INTO tiles (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, ?4)",
+                    rusqlite::params![zoom, x, tile_row, png],
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Writes the tiles covering a bounding box for every zoom level in the options.
+// An output path ending in ".mbtiles" is written as an MBTiles file, otherwise
+// as a z/x/y.png directory tree. Returns the number of tiles written.
+pub fn write_tiles(
+    data: &mut DataFile,
+    bbox: &BoundingBox,
+    output: &path::Path,
+    options: &TileOptions,
+) -> Result<usize, Box<dyn Error>> {
+    if options.min_zoom > options.max_zoom || options.max_zoom > 20 {
+        return Err("The zoom range must be within 0 to 20 with the minimum not above the maximum".into());
+    }
+    let bounds = wgs84_bounds(bbox);
+
+    let mut sink = if output.extension().is_some_and(|ext| ext == "mbtiles") {
+        let _ = fs::remove_file(output);
+        let connection = rusqlite::Connection::open(output)?;
+        connection.execute_batch(
+            "CREATE TABLE metadata (name TEXT, value TEXT);
+             CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+             CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);
+             BEGIN;",
+        )?;
+        let encoding = match options.encoding {
+            TerrainEncoding::Mapbox => "mapbox",
+            TerrainEncoding::Terrarium => "terrarium",
+        };
+        let metadata = [
+            ("name", "OS Terrain 50".to_string()),
+            ("format", "png".to_string()),
+            ("type", "baselayer".to_string()),
+            ("encoding", encoding.to_string()),
+            ("minzoom", options.min_zoom.to_string()),
+            ("maxzoom", options.max_zoom.to_string()),
+            ("bounds", format!("{},{},{},{}", bounds[0], bounds[1], bounds[2], bounds[3])),
+        ];
+        for (name, value) in metadata {
+            connection.execute("INSERT INTO metadata (name, value) VALUES (?1, ?2)", [name, &value])?;
+        }
+        TileSink::MBTiles(connection)
+    } else {
+        fs::create_dir_all(output)?;
+        TileSink::Directory(output.to_path_buf())
+    };
+
+    let mut tile_count = 0;
+    for zoom in options.min_zoom..=options.max_zoom {
+        let (min_x, max_y) = lon_lat_to_tile(bounds[0], bounds[1], zoom);
+        let (max_x, min_y) = lon_lat_to_tile(bounds[2], bounds[3], zoom);
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                if let Some(elevations) = tile_elevations(data, zoom, x, y, &bounds)? {
+                    sink.put(zoom, x, y, &encode_png(&elevations, options.encoding)?)?;
+                    tile_count += 1;
+                }
+            }
+        }
+    }
+
+    if let TileSink::MBTiles(connection) = &sink {
+        connection.execute_batch("COMMIT;")?;
+    }
+    Ok(tile_count)
+}
+
+// Encodes an elevation in metres as the red, green & blue values of a pixel
+pub fn encode_elevation(elevation: f64, encoding: TerrainEncoding) -> [u8; 3] {
+    match encoding {
+        TerrainEncoding::Mapbox => {
+            let value = ((elevation + 10_000.0) * 10.0).round().max(0.0) as u32;
+            [(value >> 16) as u8, (value >> 8) as u8, value as u8]
+        }
+        TerrainEncoding::Terrarium => {
+            let value = (elevation + 32_768.0).max(0.0);
+            let whole = value.floor() as u32;
+            let fraction = ((value - value.floor()) * 256.0).floor() as u32;
+            [(whole >> 8) as u8, whole as u8, fraction as u8]
+        }
+    }
+}
+
+// Gets the elevations (in metres, N to S and W to E) for every pixel of a tile,
+// or None if the tile has no data, i.e. it lies entirely in the sea or outside GB.
+// Pixels outside the WGS84 bounds of the region are treated as sea.
+fn tile_elevations(
+    data: &mut DataFile,
+    zoom: u8,
+    x: u32,
+    y: u32,
+    bounds: &[f64; 4],
+) -> Result<Option<Vec<f64>>, Box<dyn Error>> {
+    // At higher zooms reproject a lattice of control points exactly and interpolate
+    // the pixels between them. Lower zoom tiles are too large for that to be accurate.
+    let controls = TILE_PIXELS / CONTROL_SPACING + 1;
+    let mut control_points: Vec<(f64, f64)> = Vec::new();
+    if zoom >= MIN_CONTROL_ZOOM {
+        for row in 0..controls {
+            for col in 0..controls {
+                let pixel_x = x as f64 + (col * CONTROL_SPACING) as f64 / TILE_PIXELS as f64;
+                let pixel_y = y as f64 + (row * CONTROL_SPACING) as f64 / TILE_PIXELS as f64;
+                let (lon, lat) = tile_to_lon_lat(pixel_x, pixel_y, zoom);
+                control_points.push(projection::wgs84_to_os(lat, lon));
+            }
+        }
+    }
+
+    // Get the OS position of each pixel centre
+    let mut positions: Vec<Option<(f64, f64)>> = Vec::with_capacity(TILE_PIXELS * TILE_PIXELS);
+    for row in 0..TILE_PIXELS {
+        for col in 0..TILE_PIXELS {
+            let pixel_x = x as f64 + (col as f64 + 0.5) / TILE_PIXELS as f64;
+            let pixel_y = y as f64 + (row as f64 + 0.5) / TILE_PIXELS as f64;
+            let (lon, lat) = tile_to_lon_lat(pixel_x, pixel_y, zoom);
+            if lon < bounds[0] || lat < bounds[1] || lon > bounds[2] || lat > bounds[3] {
+                positions.push(None);
+            } else if control_points.is_empty() {
+                positions.push(Some(projection::wgs84_to_os(lat, lon)));
+            } else {
+                let cx = (col as f64 + 0.5) / CONTROL_SPACING as f64;
+                let cy = (row as f64 + 0.5) / CONTROL_SPACING as f64;
+                let (c, r) = (cx.floor() as usize, cy.floor() as usize);
+                let (fx, fy) = (cx - c as f64, cy - r as f64);
+                let point = |c: usize, r: usize| control_points[r * controls + c];
+                let lerp = |a: (f64, f64), b: (f64, f64), f: f64| (a.0 + (b.0 - a.0) * f, a.1 + (b.1 - a.1) * f);
+                let top = lerp(point(c, r), point(c + 1, r), fx);
+                let bottom = lerp(point(c, r + 1), point(c + 1, r + 1), fx);
+                positions.push(Some(lerp(top, bottom, fy)));
+            }
+        }
+    }
+
+    // Work out the OS area covered by the tile, clipped to the full GB grid
+    let inside: Vec<(f64, f64)> = positions.iter().flatten().copied().collect();
+    if inside.is_empty() {
+        return Ok(None);
+    }
+    let min_e = inside.iter().map(|p| p.0).fold(f64::MAX, f64::min).max(0.0);
+    let max_e = inside.iter().map(|p| p.0).fold(f64::MIN, f64::max);
+    let min_n = inside.iter().map(|p| p.1).fold(f64::MAX, f64::min).max(0.0);
+    let max_n = inside.iter().map(|p| p.1).fold(f64::MIN, f64::max);
+    let max_e = max_e.min((os::GRIDS_PER_ROW_100 * os::METRES_IN_100_GRID) as f64);
+    let max_n = max_n.min((os::GRIDS_PER_COL_100 * os::METRES_IN_100_GRID) as f64);
+    if min_e >= max_e || min_n >= max_n {
+        return Ok(None);
+    }
+
+    // Read at roughly the tile's own resolution, with a margin for interpolation
+    let pixel_metres = (max_e - min_e).max(max_n - min_n) / TILE_PIXELS as f64;
+    let step = ((pixel_metres / os::ELEVATION_DISTANCE as f64).floor() as usize).max(1);
+    let margin = 2 * step as i64 * os::ELEVATION_DISTANCE;
+    let bbox = BoundingBox::new(
+        min_e as i64 - margin,
+        min_n as i64 - margin,
+        max_e.ceil() as i64 + margin,
+        max_n.ceil() as i64 + margin,
+    )?;
+    let grid = raster::extract_resampled_x10(data, &bbox, step, raster::NODATA_X10)?;
+    if grid.values.iter().all(|&value| value == raster::NODATA_X10) {
+        return Ok(None);
+    }
+
+    // Sea areas are given an elevation of 0 as elsewhere in the reading code
+    let elevations = positions
+        .iter()
+        .map(|position| {
+            position
+                .and_then(|(easting, northing)| grid.interpolate(easting, northing))
+                .map_or(0.0, |elev_x10| elev_x10 / 10.0)
+        })
+        .collect();
+    Ok(Some(elevations))
+}
+
+fn encode_png(elevations: &[f64], encoding: TerrainEncoding) -> Result<Vec<u8>, Box<dyn Error>> {
+    let pixels: Vec<u8> = elevations
+        .iter()
+        .flat_map(|&elevation| encode_elevation(elevation, encoding))
+        .collect();
+    let mut png_data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png_data, TILE_PIXELS as u32, TILE_PIXELS as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(png_data)
+}
+
+// Gets the WGS84 bounds of an OS bounding box as [min lon, min lat, max lon, max lat]
+fn wgs84_bounds(bbox: &BoundingBox) -> [f64; 4] {
+    let mut bounds = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+    // Sample along the edges because grid lines are curved in WGS84
+    for i in 0..=10 {
+        let f = i as f64 / 10.0;
+        let e = bbox.min_easting as f64 + (bbox.max_easting - bbox.min_easting) as f64 * f;
+        let n = bbox.min_northing as f64 + (bbox.max_northing - bbox.min_northing) as f64 * f;
+        for (easting, northing) in [
+            (e, bbox.min_northing as f64),
+            (e, bbox.max_northing as f64),
+            (bbox.min_easting as f64, n),
+            (bbox.max_easting as f64, n),
+        ] {
+            let (lat, lon) = projection::os_to_wgs84(easting, northing);
+            bounds = [bounds[0].min(lon), bounds[1].min(lat), bounds[2].max(lon), bounds[3].max(lat)];
+        }
+    }
+    bounds
+}
+
+// Gets the XYZ tile containing a WGS84 longitude & latitude
+pub fn lon_lat_to_tile(lon: f64, lat: f64, zoom: u8) -> (u32, u32) {
+    let tiles = (1u32 << zoom) as f64;
+    let lat = lat.to_radians();
+    let x = (lon + 180.0) / 360.0 * tiles;
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * tiles;
+    let max = tiles - 1.0;
+    (x.floor().clamp(0.0, max) as u32, y.floor().clamp(0.0, max) as u32)
+}
+
+// Gets the WGS84 longitude & latitude of a (fractional) XYZ tile position
+pub fn tile_to_lon_lat(x: f64, y: f64, zoom: u8) -> (f64, f64) {
+    let tiles = (1u32 << zoom) as f64;
+    let lon = x / tiles * 360.0 - 180.0;
+    let lat = (PI * (1.0 - 2.0 * y / tiles)).sinh().atan().to_degrees();
+    (lon, lat)
+}
+
+#[test]
+fn terrain_rgb_encodings() {
+    // Mapbox: 0m is 100,000 x 0.1m steps
+    assert_eq!([1, 134, 160], encode_elevation(0.0, TerrainEncoding::Mapbox));
+    assert_eq!([1, 187, 42], encode_elevation(1345.0, TerrainEncoding::Mapbox));
+    // Terrarium: 0m is 32768
+    assert_eq!([128, 0, 0], encode_elevation(0.0, TerrainEncoding::Terrarium));
+    assert_eq!([127, 254, 128], encode_elevation(-1.5, TerrainEncoding::Terrarium));
+}
+
+#[test]
+fn web_mercator_tiles() {
+    assert_eq!((0, 0), lon_lat_to_tile(-5.0, 56.8, 0));
+    // Ben Nevis
+    assert_eq!((1991, 1259), lon_lat_to_tile(-5.0036, 56.7969, 12));
+    let (lon, lat) = tile_to_lon_lat(1991.5, 1259.5, 12);
+    assert!((lon + 4.9658).abs() < 0.0001 && (lat - 56.7768).abs() < 0.0001);
+}
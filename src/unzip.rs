@@ -59,7 +59,7 @@ fn unzip(source: &path::Path, target_dir: &path::Path) -> Result<path::PathBuf,
             // then unzip it inside the parameter target directory
             if let Some(p) = outpath.parent() {
                 if p != target_dir {
-                    outfile = fs::File::create(&target_dir.join(entry.name()))?;
+                    outfile = fs::File::create(target_dir.join(entry.name()))?;
                 }
             }
             io::copy(&mut entry, &mut outfile)?;
@@ -42,9 +42,25 @@ pub fn format_int(int_to_fix: isize) -> string::String {
     output
 }
 
+// Quotes a CSV field if it holds a comma, quote or line break, doubling any quotes
+pub fn csv_field(text: &str) -> string::String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
 #[test]
 fn format_number_with_commas() {
     assert_eq!("1,000".to_string(), format_int(1_000));
     assert_eq!("1,000,000".to_string(), format_int(1_000_000));
     assert_eq!("1,000,000,000".to_string(), format_int(1_000_000_000));
 }
+
+#[test]
+fn quote_csv_fields() {
+    assert_eq!("Ben Nevis", csv_field("Ben Nevis"));
+    assert_eq!("\"Snowdon, Yr Wyddfa\"", csv_field("Snowdon, Yr Wyddfa"));
+    assert_eq!("\"The \"\"Cobbler\"\"\"", csv_field("The \"Cobbler\""));
+}
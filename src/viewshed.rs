@@ -0,0 +1,228 @@
+use crate::coords::OSCoords;
+use crate::geojson;
+use crate::line_of_sight::{self, SightOptions};
+use crate::polygonize;
+use crate::raster::{self, BoundingBox, Raster};
+use crate::reader::DataFile;
+use serde_json::{json, Value};
+use std::{error::Error, fs, io, path};
+
+/***********************************************************************
+    Code for finding which cells can be seen from an observer
+
+    Rather than check a separate line of sight to every cell, the cells
+    are swept in square rings outwards from the observer (the XDraw method).
+    Each cell on a ring gets the height the line of sight has reached above
+    it by projecting on from the two cells of the previous ring which lie
+    either side of the line back to the observer. A cell is visible if it
+    rises to this height, and it then raises the line of sight for the
+    cells behind it. This visits each cell once, so a 10km radius around
+    the observer takes a fraction of a second.
+
+    The region is read from the binary file's data blocks in one go. Sea
+    areas are treated as the sea surface at 0m and can be visible.
+************************************************************************/
+
+// Mask values
+pub const HIDDEN: u8 = 0;
+pub const VISIBLE: u8 = 1;
+pub const OUTSIDE: u8 = 255; // Beyond the radius (the mask's nodata value)
+
+pub const MAX_RADIUS: f64 = 400_000.0; // In metres, which reaches across most of GB
+
+#[derive(Debug, Clone, Copy)]
+pub struct ViewshedOptions {
+    pub radius: f64, // In metres
+    pub sight: SightOptions, // Heights above ground and earth curvature
+}
+
+impl Default for ViewshedOptions {
+    fn default() -> ViewshedOptions {
+        ViewshedOptions {
+            radius: 5_000.0,
+            sight: SightOptions::default(),
+        }
+    }
+}
+
+pub struct Viewshed {
+    pub mask: Raster<u8>, // HIDDEN, VISIBLE or OUTSIDE for each 50m cell
+    pub visible_area: f64, // In km²
+}
+
+// Finds the cells within a radius of an observer which the observer can see
+pub fn viewshed(data: &mut DataFile, observer: OSCoords, options: &ViewshedOptions) -> Result<Viewshed, Box<dyn Error>> {
+    if options.radius <= 0.0 {
+        return Err("The viewshed radius must be greater than 0".into());
+    }
+    if options.radius > MAX_RADIUS {
+        return Err(format!("The viewshed radius must be at most {}m", MAX_RADIUS).into());
+    }
+    let radius = options.radius.ceil() as i64;
+    let bbox = BoundingBox::new(
+        observer.easting - radius,
+        observer.northing - radius,
+        observer.easting + radius + 1,
+        observer.northing + radius + 1,
+    )?;
+    let grid = raster::extract_x10(data, &bbox, raster::NODATA_X10)?;
+    let (observer_col, observer_row) = grid
+        .cell_at(observer.easting as f64, observer.northing as f64)
+        .ok_or("The observer is outside the viewshed region")?;
+
+    // Distances are measured between cell centres
+    let cell_size = grid.cell_size as f64;
+    let distance = |col: usize, row: usize| -> f64 {
+        let (dc, dr) = (col as f64 - observer_col as f64, row as f64 - observer_row as f64);
+        (dc * dc + dr * dr).sqrt() * cell_size
+    };
+    // Ground heights, lowered by the curve of the earth away from the observer
+    let ground = |col: usize, row: usize| -> f64 {
+        let elevation = if grid.is_nodata(col, row) {
+            0.0
+        } else {
+            grid.get(col, row) as f64 / 10.0
+        };
+        if options.sight.earth_curvature {
+            let d = distance(col, row);
+            elevation - line_of_sight::earth_bulge(d, d, options.sight.refraction)
+        } else {
+            elevation
+        }
+    };
+
+    let eye = ground(observer_col, observer_row) + options.sight.observer_height;
+    let mut mask = Raster::new(&bbox, OUTSIDE);
+    mask.values.fill(HIDDEN);
+    // The height of the line of sight (or the ground, if higher) above each cell
+    let mut horizon = vec![f64::NAN; grid.cols * grid.rows];
+
+    let (oc, or) = (observer_col as isize, observer_row as isize);
+    let rings = [oc, or, grid.cols as isize - 1 - oc, grid.rows as isize - 1 - or]
+        .into_iter()
+        .max()
+        .unwrap_or(0);
+    for ring in 0..=rings {
+        for (dc, dr) in ring_cells(ring) {
+            let (c, r) = (oc + dc, or + dr);
+            if c < 0 || r < 0 || c >= grid.cols as isize || r >= grid.rows as isize {
+                continue;
+            }
+            let (col, row) = (c as usize, r as usize);
+            let height = ground(col, row);
+            let index = row * grid.cols + col;
+
+            // The observer's cell and its neighbours are always visible
+            if ring <= 1 {
+                horizon[index] = height;
+                mask.set(col, row, VISIBLE);
+                continue;
+            }
+
+            // Project the line of sight on from the two cells on the previous ring
+            let d = distance(col, row);
+            let project = |dc: isize, dr: isize| -> f64 {
+                let (pc, pr) = ((oc + dc) as usize, (or + dr) as usize);
+                eye + (horizon[pr * grid.cols + pc] - eye) * d / distance(pc, pr)
+            };
+            let (low, high, t) = if dc.abs() >= dr.abs() {
+                let (step, along) = (dc - dc.signum(), dr as f64 * (ring - 1) as f64 / ring as f64);
+                let low = along.floor() as isize;
+                ((step, low), (step, low + 1), along - along.floor())
+            } else {
+                let (step, along) = (dr - dr.signum(), dc as f64 * (ring - 1) as f64 / ring as f64);
+                let low = along.floor() as isize;
+                ((low, step), (low + 1, step), along - along.floor())
+            };
+            // NB: the high cell may not have been reached yet when the line passes exactly through the low one
+            let sight_line = if t == 0.0 {
+                project(low.0, low.1)
+            } else {
+                project(low.0, low.1) * (1.0 - t) + project(high.0, high.1) * t
+            };
+
+            horizon[index] = height.max(sight_line);
+            if height + options.sight.target_height >= sight_line {
+                mask.set(col, row, VISIBLE);
+            }
+        }
+    }
+
+    // Mask out the corners of the square beyond the radius
+    let mut visible_cells = 0;
+    for row in 0..mask.rows {
+        for col in 0..mask.cols {
+            if distance(col, row) > options.radius {
+                mask.set(col, row, OUTSIDE);
+            } else if mask.get(col, row) == VISIBLE {
+                visible_cells += 1;
+            }
+        }
+    }
+    let cell_area = (grid.cell_size * grid.cell_size) as f64 / 1_000_000.0;
+    Ok(Viewshed {
+        visible_area: visible_cells as f64 * cell_area,
+        mask,
+    })
+}
+
+// Gets the column & row offsets of the cells on a square ring around the centre
+fn ring_cells(ring: isize) -> Vec<(isize, isize)> {
+    if ring == 0 {
+        return vec![(0, 0)];
+    }
+    let mut cells = Vec::with_capacity(ring as usize * 8);
+    for i in -ring..ring {
+        cells.push((i, -ring));
+        cells.push((ring, i));
+        cells.push((-i, ring));
+        cells.push((-ring, -i));
+    }
+    cells
+}
+
+// Writes the mask as a greyscale PNG with visible cells in white, hidden cells
+// in black and cells beyond the radius transparent
+pub fn write_png(viewshed: &Viewshed, output_file: &path::Path) -> Result<(), Box<dyn Error>> {
+    let mask = &viewshed.mask;
+    let pixels: Vec<u8> = mask
+        .values
+        .iter()
+        .flat_map(|&value| match value {
+            VISIBLE => [255, 255],
+            HIDDEN => [0, 255],
+            _ => [0, 0],
+        })
+        .collect();
+
+    let file = fs::File::create(output_file)?;
+    let mut encoder = png::Encoder::new(io::BufWriter::new(file), mask.cols as u32, mask.rows as u32);
+    encoder.set_color(png::ColorType::GrayscaleAlpha);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(())
+}
+
+// Converts the visible areas to a GeoJSON feature collection of polygons with their areas
+pub fn to_geojson(viewshed: &Viewshed) -> Value {
+    let features = polygonize::polygonize(&viewshed.mask, |value| value == VISIBLE)
+        .iter()
+        .map(|rings| {
+            // Holes are taken away from the outer ring
+            let area: f64 = rings
+                .iter()
+                .enumerate()
+                .map(|(i, ring)| if i == 0 { ring_area(ring) } else { -ring_area(ring) })
+                .sum();
+            let area_km2 = (area / 10_000.0).round() / 100.0;
+            geojson::feature(geojson::polygon(rings), json!({ "area_km2": area_km2 }))
+        })
+        .collect();
+    geojson::feature_collection(features)
+}
+
+// Gets the unsigned area of a closed ring of eastings & northings in m²
+fn ring_area(ring: &[(f64, f64)]) -> f64 {
+    let twice_area: f64 = ring.windows(2).map(|pair| pair[0].0 * pair[1].1 - pair[1].0 * pair[0].1).sum();
+    twice_area.abs() / 2.0
+}
@@ -0,0 +1,107 @@
+/***********************************************************************
+    Helpers for editing XML documents (e.g. KML & TCX) as text
+
+    Elements are found by their tag names and their positions in the text
+    are used to replace or insert content, so everything else in a
+    document is written back exactly as it was read. This suits the simple
+    and regular files written by mapping & fitness software, but isn't a
+    general XML parser: comments and nested elements of the same name
+    aren't recognised.
+************************************************************************/
+
+// The byte positions of an element in the text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Element {
+    pub start: usize,
+    pub content_start: usize,
+    pub content_end: usize,
+    pub end: usize,
+}
+
+// Finds the first element with a tag name between two positions in the text
+pub fn find_element(text: &str, name: &str, from: usize, to: usize) -> Option<Element> {
+    let open = format!("<{}", name);
+    let mut search = from;
+    loop {
+        let start = search + text[search..to].find(&open)?;
+        let after = start + open.len();
+        // Skip longer tag names which start with the name, e.g. <PointStyle>
+        if !text[after..to].starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace()) {
+            search = after;
+            continue;
+        }
+        let content_start = after + text[after..to].find('>')? + 1;
+        if text[..content_start].ends_with("/>") {
+            return Some(Element {
+                start,
+                content_start,
+                content_end: content_start,
+                end: content_start,
+            });
+        }
+        let close = format!("</{}>", name);
+        let content_end = content_start + text[content_start..to].find(&close)?;
+        return Some(Element {
+            start,
+            content_start,
+            content_end,
+            end: content_end + close.len(),
+        });
+    }
+}
+
+// Finds the elements with a tag name between two positions, not looking inside each one found
+pub fn find_elements(text: &str, name: &str, from: usize, to: usize) -> Vec<Element> {
+    let mut elements = Vec::new();
+    let mut search = from;
+    while let Some(element) = find_element(text, name, search, to) {
+        search = element.end;
+        elements.push(element);
+    }
+    elements
+}
+
+// Escapes text for an element's content
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// Gets the text of an element's content, which may be a CDATA section
+pub fn unescape(text: &str) -> String {
+    match text.strip_prefix("<![CDATA[").and_then(|text| text.strip_suffix("]]>")) {
+        Some(text) => text.to_string(),
+        None => text
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    }
+}
+
+// Replaces the start..end ranges of the text with new text, e.g. inserting where start == end
+pub fn apply_edits(text: &str, mut edits: Vec<(usize, usize, String)>) -> String {
+    edits.sort_by_key(|&(start, end, _)| (start, end));
+    let mut output = String::with_capacity(text.len() + edits.iter().map(|edit| edit.2.len()).sum::<usize>());
+    let mut position = 0;
+    for (start, end, new_text) in edits {
+        output.push_str(&text[position..start]);
+        output.push_str(&new_text);
+        position = end;
+    }
+    output.push_str(&text[position..]);
+    output
+}
+
+#[test]
+fn finds_and_edits_elements() {
+    let text = "<a><b>1</b><bc/><b x=\"y\">2</b><c/></a>";
+    let elements = find_elements(text, "b", 0, text.len());
+    assert_eq!(vec!["1", "2"], elements.iter().map(|b| &text[b.content_start..b.content_end]).collect::<Vec<_>>());
+    let c = find_element(text, "c", 0, text.len()).unwrap();
+    assert_eq!((c.content_start, c.end), (c.content_end, text.len() - 4));
+    let edits = vec![(elements[1].content_start, elements[1].content_end, "3".to_string()), (c.start, c.start, "<d/>".to_string())];
+    assert_eq!("<a><b>1</b><bc/><b x=\"y\">3</b><d/><c/></a>", apply_edits(text, edits));
+    assert_eq!("a < b", unescape("a &lt; b"));
+    assert_eq!("a &lt; b", unescape("<![CDATA[a &lt; b]]>"));
+}
@@ -0,0 +1,152 @@
+use crate::polygonize::Polygon;
+use crate::raster::{self, BoundingBox, Raster};
+use crate::reader::DataFile;
+use std::error::Error;
+
+/***********************************************************************
+    Code for summarising the elevations within polygons
+
+    Polygons are rasterised onto the 50m grid by taking every cell whose
+    centre lies inside them. Each row of cell centres is crossed with the
+    polygon edges (a scanline fill), so a large area such as a council
+    costs little more than reading its elevations. Holes, and the parts of
+    a multipolygon, follow the even-odd rule.
+
+    Sea cells (those without data) are left out of the statistics.
+************************************************************************/
+
+pub const DEFAULT_BIN_WIDTH: f64 = 10.0; // Histogram bin width in metres
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZonalStats {
+    pub count: usize, // Land cells inside the polygons
+    pub min: f64, // In metres
+    pub max: f64,
+    pub mean: f64,
+    pub std_dev: f64, // Population standard deviation
+    pub histogram: Vec<(f64, usize)>, // Lower elevation of each bin and its cell count
+}
+
+// Marks the cells of a raster whose centres lie inside the polygons with 1, and other cells with 0
+pub fn rasterise<T: Copy + PartialEq>(grid: &Raster<T>, polygons: &[Polygon]) -> Raster<u8> {
+    let mut mask = grid.map(0, |_| 0);
+    let cell_size = grid.cell_size as f64;
+    let mut crossings = Vec::new();
+    for row in 0..grid.rows {
+        let northing = grid.max_northing() as f64 - (row as f64 + 0.5) * cell_size;
+
+        // Find where the row of cell centres crosses the edges, counting
+        // each end of an edge on one side only so that vertices aren't counted twice
+        crossings.clear();
+        for ring in polygons.iter().flatten() {
+            for edge in ring.windows(2) {
+                let ((e1, n1), (e2, n2)) = (edge[0], edge[1]);
+                if (n1 <= northing) != (n2 <= northing) {
+                    crossings.push(e1 + (northing - n1) / (n2 - n1) * (e2 - e1));
+                }
+            }
+        }
+        crossings.sort_by(|a, b| a.total_cmp(b));
+
+        // Fill the cells with centres between each pair of crossings
+        for pair in crossings.chunks_exact(2) {
+            let first_col = ((pair[0] - grid.min_easting as f64) / cell_size - 0.5).ceil().max(0.0) as usize;
+            let end_col = (((pair[1] - grid.min_easting as f64) / cell_size - 0.5).ceil().max(0.0) as usize).min(grid.cols);
+            for col in first_col..end_col {
+                mask.set(col, row, 1);
+            }
+        }
+    }
+    mask
+}
+
+// Gets the statistics of the elevations in a raster of x10 values inside the polygons,
+// or None if there are no land cells inside them
+pub fn zonal_stats(grid: &Raster<i16>, polygons: &[Polygon], bin_width: f64) -> Option<ZonalStats> {
+    let mask = rasterise(grid, polygons);
+    let elevations: Vec<f64> = grid
+        .values
+        .iter()
+        .zip(&mask.values)
+        .filter(|&(&value, &inside)| inside == 1 && value != grid.nodata)
+        .map(|(&value, _)| value as f64 / 10.0)
+        .collect();
+    if elevations.is_empty() {
+        return None;
+    }
+
+    let count = elevations.len();
+    let min = elevations.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = elevations.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let mean = elevations.iter().sum::<f64>() / count as f64;
+    let variance = elevations.iter().map(|elevation| (elevation - mean).powi(2)).sum::<f64>() / count as f64;
+
+    // Bins start at a multiple of the bin width
+    let first_bin = (min / bin_width).floor();
+    let bins = ((max / bin_width).floor() - first_bin) as usize + 1;
+    let mut counts = vec![0; bins];
+    for elevation in &elevations {
+        let bin = ((elevation / bin_width).floor() - first_bin) as usize;
+        counts[bin.min(bins - 1)] += 1;
+    }
+    let histogram = counts
+        .into_iter()
+        .enumerate()
+        .map(|(bin, count)| ((first_bin + bin as f64) * bin_width, count))
+        .collect();
+
+    Some(ZonalStats {
+        count,
+        min,
+        max,
+        mean,
+        std_dev: variance.sqrt(),
+        histogram,
+    })
+}
+
+// Reads the elevations around the polygons from the binary file and gets their statistics
+pub fn polygon_stats(data: &mut DataFile, polygons: &[Polygon], bin_width: f64) -> Result<Option<ZonalStats>, Box<dyn Error>> {
+    if bin_width <= 0.0 {
+        return Err("The histogram bin width must be greater than 0".into());
+    }
+    let points = || polygons.iter().flatten().flatten();
+    if points().next().is_none() {
+        return Ok(None);
+    }
+    let (min_easting, min_northing, max_easting, max_northing) = (
+        points().map(|point| point.0).fold(f64::INFINITY, f64::min).floor() as i64,
+        points().map(|point| point.1).fold(f64::INFINITY, f64::min).floor() as i64,
+        points().map(|point| point.0).fold(f64::NEG_INFINITY, f64::max).ceil() as i64 + 1,
+        points().map(|point| point.1).fold(f64::NEG_INFINITY, f64::max).ceil() as i64 + 1,
+    );
+    // Polygons outside the grid have no land cells
+    if !raster::overlaps_grid(min_easting, min_northing, max_easting, max_northing) {
+        return Ok(None);
+    }
+    let bbox = BoundingBox::new(min_easting, min_northing, max_easting, max_northing)?;
+    let grid = raster::extract_x10(data, &bbox, raster::NODATA_X10)?;
+    Ok(zonal_stats(&grid, polygons, bin_width))
+}
+
+#[test]
+fn square_with_a_hole() {
+    // A 4 x 4 cell grid rising 1m per cell eastwards, with a 2 x 2 cell hole in the middle
+    let bbox = BoundingBox::new(0, 0, 200, 200).unwrap();
+    let mut grid = Raster::new(&bbox, raster::NODATA_X10);
+    for (i, value) in grid.values.iter_mut().enumerate() {
+        *value = (i % 4) as i16 * 10;
+    }
+    let outer = vec![(0.0, 0.0), (200.0, 0.0), (200.0, 200.0), (0.0, 200.0), (0.0, 0.0)];
+    let hole = vec![(50.0, 50.0), (50.0, 150.0), (150.0, 150.0), (150.0, 50.0), (50.0, 50.0)];
+    let polygons = vec![vec![outer, hole]];
+
+    let mask = rasterise(&grid, &polygons);
+    assert_eq!(12, mask.values.iter().filter(|&&value| value == 1).count());
+    assert_eq!(0, mask.get(1, 1));
+
+    let stats = zonal_stats(&grid, &polygons, 2.0).unwrap();
+    assert_eq!((12, 0.0, 3.0, 1.5), (stats.count, stats.min, stats.max, stats.mean));
+    assert!((stats.std_dev - (19.0f64 / 12.0).sqrt()).abs() < 1e-9);
+    assert_eq!(vec![(0.0, 6), (2.0, 6)], stats.histogram);
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use osterrain50::activity::{CorrectionOptions, TrackPoint};
+use osterrain50::fit;
+use osterrain50::projection;
+use osterrain50::reader::DataFile;
+use osterrain50::tcx;
+
+/************************************
+   Test correcting the altitudes
+   of FIT & TCX activity files
+************************************/
+
+// A track eastwards through the middles of 50m cells at 100m, 102m & 104m,
+// with recorded altitudes of 50m, 60m & 55m
+fn track() -> Vec<(f64, f64, f64)> {
+    [(85_025.0, 50.0), (85_125.0, 60.0), (85_225.0, 55.0)]
+        .iter()
+        .map(|&(easting, altitude)| {
+            let (lat, lon) = projection::os_to_wgs84(easting, 5_025.0);
+            (lat, lon, altitude)
+        })
+        .collect()
+}
+
+// Builds a FIT file of the track's records (the last with a compressed timestamp header)
+// and a big-endian session message
+fn build_fit() -> Vec<u8> {
+    let semicircles = |degrees: f64| ((degrees * 2f64.powi(31) / 180.0).round() as i32).to_le_bytes();
+    let mut messages = vec![0x40, 0, 0, 20, 0, 3, 0, 4, 0x85, 1, 4, 0x85, 2, 2, 0x84];
+    let track = track();
+    for (i, (lat, lon, altitude)) in track.iter().enumerate() {
+        messages.push(if i == track.len() - 1 { 0x80 | 5 } else { 0 });
+        messages.extend(semicircles(*lat));
+        messages.extend(semicircles(*lon));
+        messages.extend((((altitude + 500.0) * 5.0) as u16).to_le_bytes());
+    }
+    messages.extend([0x41, 0, 1, 0, 18, 2, 22, 2, 0x84, 23, 2, 0x84]);
+    messages.extend([1, 0, 10, 0, 5]);
+    frame_fit(messages)
+}
+
+// Adds the header & CRC of a FIT file to its messages
+fn frame_fit(messages: Vec<u8>) -> Vec<u8> {
+    let mut fit = vec![14, 0x10, 0x54, 0x08];
+    fit.extend((messages.len() as u32).to_le_bytes());
+    fit.extend(b".FIT");
+    let header_crc = fit::crc(&fit);
+    fit.extend(header_crc.to_le_bytes());
+    fit.extend(messages);
+    let crc = fit::crc(&fit);
+    fit.extend(crc.to_le_bytes());
+    fit
+}
+
+#[test]
+fn corrects_fit_altitudes() {
+    let data_file = common::build_test_file("activity_fit", &["SV80"], common::rising_eastwards);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    let input = build_fit();
+    let points = fit::track_points(&input).unwrap();
+    assert_eq!(vec![Some(50.0), Some(60.0), Some(55.0)], points.iter().map(|point| point.altitude).collect::<Vec<_>>());
+
+    let (output, summary) = fit::correct_fit(&mut data, &input, &CorrectionOptions::default()).unwrap();
+    assert_eq!((3, 3), (summary.points, summary.corrected));
+    assert_eq!((10.0, 4.0, 0.0), (summary.recorded_ascent, summary.ascent, summary.descent));
+
+    let corrected = fit::track_points(&output).unwrap();
+    assert_eq!(vec![Some(100.0), Some(102.0), Some(104.0)], corrected.iter().map(|point| point.altitude).collect::<Vec<_>>());
+    assert_eq!(points.iter().map(|point| point.position).collect::<Vec<_>>(), corrected.iter().map(|point| point.position).collect::<Vec<_>>());
+
+    // The session's big-endian total ascent & descent, then the file's CRC
+    assert_eq!([0, 4, 0, 0], output[output.len() - 6..output.len() - 2]);
+    assert_eq!(0, fit::crc(&output));
+    assert_eq!(input.len(), output.len());
+
+    // Half & half (75m, 81m & 79.5m), then smoothed over 3 points and stored to the nearest 0.2m
+    let options = CorrectionOptions { blend: 0.5, smooth: 3 };
+    let (output, _) = fit::correct_fit(&mut data, &input, &options).unwrap();
+    let blended: Vec<f64> = fit::track_points(&output)
+        .unwrap()
+        .iter()
+        .map(|point| (point.altitude.unwrap() * 10.0).round() / 10.0)
+        .collect();
+    assert_eq!(vec![78.0, 78.6, 80.2], blended);
+}
+
+#[test]
+fn corrects_each_fit_session() {
+    let data_file = common::build_test_file("activity_sessions", &["SV80"], common::rising_eastwards);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    // Up from 100m to 102m in the first session, then down from 104m to 102m in the second,
+    // with the last record's time in a compressed timestamp header
+    let semicircles = |degrees: f64| ((degrees * 2f64.powi(31) / 180.0).round() as i32).to_le_bytes();
+    let mut messages = vec![0x40, 0, 0, 20, 0, 4, 253, 4, 0x86, 0, 4, 0x85, 1, 4, 0x85, 2, 2, 0x84];
+    messages.extend([0x41, 0, 0, 20, 0, 3, 0, 4, 0x85, 1, 4, 0x85, 2, 2, 0x84]);
+    for (i, easting) in [85_025.0, 85_125.0, 85_225.0, 85_125.0].into_iter().enumerate() {
+        let (lat, lon) = projection::os_to_wgs84(easting, 5_025.0);
+        match i {
+            3 => messages.push(0x80 | 1 << 5 | (1003u32 & 0x1F) as u8),
+            _ => {
+                messages.push(0);
+                messages.extend((1000 + i as u32).to_le_bytes());
+            }
+        }
+        messages.extend(semicircles(lat));
+        messages.extend(semicircles(lon));
+        messages.extend(((500 * 5) as u16).to_le_bytes());
+    }
+    messages.extend([0x42, 0, 0, 18, 0, 4, 253, 4, 0x86, 2, 4, 0x86, 22, 2, 0x84, 23, 2, 0x84]);
+    for (start, end) in [(1000u32, 1001u32), (1002, 1003)] {
+        messages.push(2);
+        messages.extend(end.to_le_bytes());
+        messages.extend(start.to_le_bytes());
+        messages.extend([0, 0, 0, 0]);
+    }
+    let input = frame_fit(messages);
+
+    let (output, summary) = fit::correct_fit(&mut data, &input, &CorrectionOptions::default()).unwrap();
+    assert_eq!((4.0, 2.0), (summary.ascent, summary.descent));
+    // Each session's total ascent & descent, before the file's CRC
+    let totals = |session: usize| &output[output.len() - 6 - 13 * (1 - session)..output.len() - 2 - 13 * (1 - session)];
+    assert_eq!([2, 0, 0, 0], totals(0));
+    assert_eq!([0, 0, 2, 0], totals(1));
+    assert_eq!(0, fit::crc(&output));
+}
+
+#[test]
+fn corrects_tcx_altitudes() {
+    let data_file = common::build_test_file("activity_tcx", &["SV80"], common::rising_eastwards);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    let trackpoints: Vec<String> = track()
+        .iter()
+        .enumerate()
+        .map(|(i, (lat, lon, altitude))| {
+            // The second point has no recorded altitude
+            let altitude = if i == 1 { String::new() } else { format!("<AltitudeMeters>{}</AltitudeMeters>", altitude) };
+            format!(
+                "<Trackpoint><Time>t{}</Time><Position><LatitudeDegrees>{}</LatitudeDegrees><LongitudeDegrees>{}</LongitudeDegrees></Position>{}<DistanceMeters>{}</DistanceMeters></Trackpoint>",
+                i,
+                lat,
+                lon,
+                altitude,
+                i * 100
+            )
+        })
+        .collect();
+    // Then a point in SV90, which has no data so keeps its altitude, and one without a position
+    let (lat, lon) = projection::os_to_wgs84(95_025.0, 5_025.0);
+    let input = format!(
+        "<TrainingCenterDatabase><Activities><Activity><Lap><Track>{}<Trackpoint><Time>t3</Time><Position><LatitudeDegrees>{}</LatitudeDegrees><LongitudeDegrees>{}</LongitudeDegrees></Position><AltitudeMeters>42</AltitudeMeters></Trackpoint><Trackpoint><Time>t4</Time></Trackpoint></Track></Lap></Activity></Activities></TrainingCenterDatabase>",
+        trackpoints.concat(),
+        lat,
+        lon
+    );
+
+    let (output, summary) = tcx::correct_tcx(&mut data, &input, &CorrectionOptions::default()).unwrap();
+    assert_eq!((5, 3), (summary.points, summary.corrected));
+    assert_eq!((5.0, 4.0), (summary.recorded_ascent, summary.ascent));
+    assert_eq!(4, output.matches("<AltitudeMeters>").count());
+    assert!(output.contains("</Position><AltitudeMeters>102.0</AltitudeMeters><DistanceMeters>100</DistanceMeters>"));
+    assert_eq!(
+        vec![Some(100.0), Some(102.0), Some(104.0), Some(42.0), None],
+        tcx::track_points(&output).unwrap().iter().map(|point: &TrackPoint| point.altitude).collect::<Vec<_>>()
+    );
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use osterrain50::ascii_grid;
+use osterrain50::raster::BoundingBox;
+use osterrain50::reader::DataFile;
+use std::{env, fs, path, process};
+
+/************************************
+   Test ESRI ASCII grid export from a
+   small synthetic binary data file
+************************************/
+
+// Whole, decimal and negative elevations as found in the OS data files
+fn synthetic_elevation(easting: i64, northing: i64) -> f32 {
+    ((easting % 10_000) / 50 + (northing % 10_000) / 50 * 7 - 15) as f32 / 10.0
+}
+
+#[test]
+fn ascii_grid_round_trips_source_data_file() {
+    let data_file = common::build_test_file("ascii_round_trip", &["SV80"], synthetic_elevation);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let output_file = env::temp_dir().join(format!("osterrain50_ascii_round_trip_{}.asc", process::id()));
+
+    let bbox = BoundingBox::new(80_000, 0, 90_000, 10_000).unwrap();
+    ascii_grid::write_ascii_grid(&mut data, &bbox, &output_file, ascii_grid::NODATA_ASCII).unwrap();
+
+    // The data rows must be identical to those in the source data file
+    let source_file = path::Path::new(&data_file).parent().unwrap().join("data/sv/SV80.asc");
+    let source = fs::read_to_string(source_file).unwrap();
+    let exported = fs::read_to_string(&output_file).unwrap();
+    let source_rows: Vec<&str> = source.split("\r\n").skip(5).collect();
+    let exported_rows: Vec<&str> = exported.split("\r\n").skip(6).collect();
+    assert_eq!(source_rows, exported_rows);
+}
+
+#[test]
+fn ascii_grid_header_and_nodata() {
+    let data_file = common::build_test_file("ascii_header", &["SV80"], synthetic_elevation);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let output_file = env::temp_dir().join(format!("osterrain50_ascii_header_{}.asc", process::id()));
+
+    // One row in SV80 and one in the sea area of SV81
+    let bbox = BoundingBox::new(80_000, 9_950, 80_100, 10_050).unwrap();
+    ascii_grid::write_ascii_grid(&mut data, &bbox, &output_file, -9999).unwrap();
+
+    let expected = "ncols 2\r\nnrows 2\r\nxllcorner 80000\r\nyllcorner 9950\r\ncellsize 50\r\nNODATA_value -9999\r\n\
+                    -9999 -9999\r\n137.8 137.9\r\n";
+    assert_eq!(expected, fs::read_to_string(&output_file).unwrap());
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::OSCoords;
+use osterrain50::cache::BLOCK_BYTES;
+use osterrain50::reader::DataFile;
+
+/************************************
+   Test batch lookups of scattered
+   locations give the same results
+   as one by one lookups
+************************************/
+
+#[test]
+fn batch_matches_single_lookups() {
+    let data_file = common::build_test_file("batch", &["SV80", "SV81"], common::rising_east_and_north);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    // Scattered between the two blocks, the sea and outside the grid, with repeats
+    let coords: Vec<OSCoords> = (0..1_000)
+        .map(|i| OSCoords {
+            easting: 75_000 + (i * 7_919) % 15_000,
+            northing: (i * 104_729) % 22_000 - if i % 97 == 0 { 30_000 } else { 0 },
+            elevation: None,
+        })
+        .collect();
+
+    let expected = data.read_elevations(&coords, false).unwrap();
+    let found = data.read_elevations_batch(&coords).unwrap();
+    assert_eq!(coords.len(), found.len());
+    for (a, b) in expected.iter().zip(&found) {
+        assert_eq!((a.easting, a.northing, a.elevation), (b.easting, b.northing, b.elevation));
+    }
+
+    // Each block with data is read once
+    data.enable_cache(10 * BLOCK_BYTES).unwrap();
+    data.read_elevations_batch(&coords).unwrap();
+    assert_eq!(2, data.cache_stats().unwrap().misses);
+    assert_eq!(0, data.cache_stats().unwrap().hits);
+}
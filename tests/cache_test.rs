@@ -0,0 +1,75 @@
+mod common;
+
+use common::OSCoords;
+use osterrain50::cache::{CacheStats, BLOCK_BYTES};
+use osterrain50::raster::{self, BoundingBox};
+use osterrain50::reader::DataFile;
+
+/************************************
+   Test the reader's block cache
+   gives the same elevations and
+   counts its hits & misses
+************************************/
+
+fn coords(easting: i64, northing: i64) -> OSCoords {
+    OSCoords {
+        easting,
+        northing,
+        elevation: None,
+    }
+}
+
+#[test]
+fn cached_reads_match_disk_reads() {
+    let data_file = common::build_test_file("cache", &["SV80", "SV81"], common::rising_east_and_north);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let mut cached = DataFile::open(&data_file).unwrap();
+    assert_eq!(None, cached.cache_stats());
+    cached.enable_cache(10 * BLOCK_BYTES).unwrap();
+
+    // A profile from the sea into SV80 then SV81 and back
+    let route = [coords(75_000, 5_000), coords(85_000, 15_000), coords(85_000, 5_000)];
+    let expected = data.read_elevations(&route, true).unwrap();
+    let found = cached.read_elevations(&route, true).unwrap();
+    assert!(expected.iter().zip(&found).all(|(a, b)| a.elevation == b.elevation));
+
+    // Each block is read once, and the sea points need no block
+    let stats = cached.cache_stats().unwrap();
+    assert_eq!((2, 2, 2 * BLOCK_BYTES), (stats.misses, stats.blocks, stats.bytes));
+    let land_points = found.iter().filter(|coord| coord.easting >= 80_000).count() as u64;
+    assert_eq!(land_points - 2, stats.hits);
+
+    // Whole blocks come from the cache too
+    let bbox = BoundingBox::new(82_000, 8_000, 84_000, 12_000).unwrap();
+    assert_eq!(
+        raster::extract_x10(&mut data, &bbox, raster::NODATA_X10).unwrap().values,
+        raster::extract_x10(&mut cached, &bbox, raster::NODATA_X10).unwrap().values
+    );
+    assert_eq!(stats.hits + 2, cached.cache_stats().unwrap().hits);
+}
+
+#[test]
+fn budget_limits_the_blocks_held() {
+    let data_file = common::build_test_file("cache_budget", &["SV80", "SV81"], common::rising_east_and_north);
+    let mut data = DataFile::open(&data_file).unwrap();
+    data.enable_cache(BLOCK_BYTES).unwrap();
+
+    // Alternating between two blocks with room for one misses every time
+    for northing in [5_000, 15_000, 5_000, 15_000] {
+        data.read_elevation_x10(85_000, northing).unwrap();
+    }
+    assert_eq!(
+        CacheStats {
+            hits: 0,
+            misses: 4,
+            blocks: 1,
+            bytes: BLOCK_BYTES
+        },
+        data.cache_stats().unwrap()
+    );
+
+    // Without room for a block only the header is kept
+    data.enable_cache(0).unwrap();
+    assert_eq!(Some(Some(1000)), data.read_elevation_x10(85_000, 10_000).ok());
+    assert_eq!(0, data.cache_stats().unwrap().misses);
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use osterrain50::catchment;
+use osterrain50::coords::OSCoords;
+use osterrain50::reader::DataFile;
+
+/************************************
+   Test catchment delineation up a
+   synthetic valley which runs on
+   beyond the first window of blocks
+************************************/
+
+#[test]
+fn catchment_grows_up_the_valley() {
+    let data_file = common::build_test_file("catchment", &["SV80", "SV81", "SV82", "SV83"], common::valley);
+    let mut data = DataFile::open(&data_file).unwrap();
+    // Just east of the valley floor
+    let pour_point = OSCoords {
+        easting: 85_080,
+        northing: 5_010,
+        elevation: None,
+    };
+
+    let result = catchment::delineate(&mut data, pour_point, 3).unwrap();
+    // Snapped to the valley floor 3 cells downstream
+    assert_eq!((85_025.0, 4_875.0), result.pour_point);
+    // The window grew north by 1 block then 2, reaching the sea beyond SV83
+    assert_eq!(18, result.blocks_read);
+    assert_eq!(60_000, result.mask.max_northing());
+
+    // The whole valley floor upstream of the pour point is in the catchment, and nothing downstream
+    let in_catchment = |easting: f64, northing: f64| {
+        let (col, row) = result.mask.cell_at(easting, northing).unwrap();
+        result.mask.get(col, row) == 1
+    };
+    assert!(in_catchment(85_025.0, 39_975.0));
+    assert!(in_catchment(80_025.0, 39_975.0));
+    assert!(!in_catchment(85_025.0, 4_825.0));
+    assert!(!in_catchment(80_025.0, 5_025.0));
+
+    // 10km wide up to 40km north, less the wedges either side of the pour point
+    assert!(result.area > 320.0 && result.area < 330.0, "area {}", result.area);
+    let geojson = catchment::to_geojson(&result);
+    assert_eq!(1, geojson["features"].as_array().unwrap().len());
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use osterrain50::contours;
+use osterrain50::raster::{self, BoundingBox};
+use osterrain50::reader::DataFile;
+
+/************************************
+   Test contour generation from a
+   small synthetic binary data file
+************************************/
+
+// A cone centred on the boundary between SV80 and SV90
+fn synthetic_cone(easting: i64, northing: i64) -> f32 {
+    let distance = (((easting - 90_000).pow(2) + (northing - 5_000).pow(2)) as f64).sqrt();
+    (480.0 - distance / 10.0).max(0.0).round() as f32
+}
+
+#[test]
+fn contours_stitched_across_data_blocks() {
+    let data_file = common::build_test_file("contours_stitched", &["SV80", "SV90"], synthetic_cone);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    let bbox = BoundingBox::new(85_000, 0, 95_000, 10_000).unwrap();
+    let grid = raster::extract_x10(&mut data, &bbox, raster::NODATA_X10).unwrap();
+    let lines = contours::contours(&grid, 100.0, 0.0).unwrap();
+
+    // One closed ring at each of 100m to 400m, crossing the block boundary
+    let rings: Vec<&contours::Contour> = lines.iter().filter(|c| c.elevation > 0.0).collect();
+    assert_eq!(vec![100.0, 200.0, 300.0, 400.0], rings.iter().map(|c| c.elevation).collect::<Vec<_>>());
+    for ring in rings {
+        assert_eq!(ring.points.first(), ring.points.last());
+        assert!(ring.points.iter().any(|p| p.0 < 90_000.0) && ring.points.iter().any(|p| p.0 > 90_000.0));
+    }
+
+    let geojson = contours::to_geojson(&lines);
+    assert_eq!("LineString", geojson["features"][0]["geometry"]["type"]);
+    assert_eq!("urn:ogc:def:crs:EPSG::27700", geojson["crs"]["properties"]["name"]);
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use osterrain50::derivatives::{self, Derivative, Method};
+use osterrain50::raster::{self, BoundingBox};
+use osterrain50::reader::DataFile;
+
+/************************************
+   Test slope & aspect across grid
+   boundaries from a small synthetic
+   binary data file
+************************************/
+
+// A plane rising 1m per 50m to the east
+fn synthetic_elevation(easting: i64, _northing: i64) -> f32 {
+    (easting / 50) as f32
+}
+
+#[test]
+fn slope_across_100km_grid_boundary() {
+    // SV90 is the easternmost 10km² of the SV grid and SW00 is next to it
+    let data_file = common::build_test_file("derivatives", &["SV90", "SW00"], synthetic_elevation);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    // The point is in the last column of SV90, so its east neighbour is in SW00
+    let point = derivatives::derivatives_at(&mut data, 99_975.0, 5_000.0, Method::ZevenbergenThorne)
+        .unwrap()
+        .unwrap();
+    assert!((point.slope_percent - 2.0).abs() < 1e-6, "Slope {}", point.slope_percent);
+    assert!((point.aspect.unwrap() - 270.0).abs() < 1e-6);
+
+    // A box straddling the boundary has the same slope everywhere
+    let bbox = BoundingBox::new(99_900, 5_000, 100_100, 5_100).unwrap();
+    let grid = derivatives::extract_derivative(&mut data, &bbox, Derivative::SlopePercent, Method::Horn, raster::NODATA_METRES)
+        .unwrap();
+    assert_eq!((4, 2), (grid.cols, grid.rows));
+    assert!(grid.values.iter().all(|slope| (slope - 2.0).abs() < 1e-4), "{:?}", grid.values);
+
+    // The sea to the north has no aspect
+    let sea = BoundingBox::new(90_000, 10_000, 90_050, 10_050).unwrap();
+    let grid = derivatives::extract_derivative(&mut data, &sea, Derivative::Aspect, Method::Horn, raster::NODATA_METRES)
+        .unwrap();
+    assert_eq!(vec![raster::NODATA_METRES], grid.values);
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use osterrain50::drape::{self, Crs, DrapeOptions};
+use osterrain50::projection;
+use osterrain50::reader::DataFile;
+use serde_json::json;
+
+/************************************
+   Test adding Z values to GeoJSON
+   in either coordinate system,
+   with & without densifying
+************************************/
+
+#[test]
+fn drapes_grid_coordinates() {
+    let data_file = common::build_test_file("drape", &["SV80"], common::rising_eastwards);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    let input = json!({
+        "type": "FeatureCollection",
+        "features": [
+            { "type": "Feature", "properties": { "name": "path" }, "geometry": { "type": "LineString", "coordinates": [[85000, 5000], [85150, 5000, 999]] } },
+            { "type": "Feature", "properties": {}, "geometry": { "type": "Point", "coordinates": [82000, 5000] } },
+            { "type": "Feature", "properties": {}, "geometry": null },
+        ],
+    });
+
+    let (output, summary) = drape::drape(&mut data, &input, &DrapeOptions::default()).unwrap();
+    assert_eq!((Crs::BritishNationalGrid, 3, 0), (summary.crs, summary.positions, summary.added));
+    assert_eq!(json!([[85000, 5000, 100.0], [85150, 5000, 103.0]]), output["features"][0]["geometry"]["coordinates"]);
+    assert_eq!(json!([82000, 5000, 40.0]), output["features"][1]["geometry"]["coordinates"]);
+    assert_eq!("path", output["features"][0]["properties"]["name"]);
+
+    let options = DrapeOptions { crs: None, densify: true };
+    let (output, summary) = drape::drape(&mut data, &input, &options).unwrap();
+    assert_eq!((5, 2), (summary.positions, summary.added));
+    assert_eq!(
+        json!([[85000, 5000, 100.0], [85050.0, 5000.0, 101.0], [85100.0, 5000.0, 102.0], [85150, 5000, 103.0]]),
+        output["features"][0]["geometry"]["coordinates"]
+    );
+
+    // Positions outside the grid are left as they are, unless none are inside
+    let input = json!({ "type": "MultiPoint", "coordinates": [[85000, 5000], [800000, 5000, 7]] });
+    let (output, summary) = drape::drape(&mut data, &input, &DrapeOptions::default()).unwrap();
+    assert_eq!((1, 1), (summary.positions, summary.outside));
+    assert_eq!(json!([[85000, 5000, 100.0], [800000, 5000, 7]]), output["coordinates"]);
+    let input = json!({ "type": "Point", "coordinates": [85000, 5000] });
+    let options = DrapeOptions { crs: Some(Crs::Wgs84), densify: false };
+    assert!(drape::drape(&mut data, &input, &options).is_err());
+    let input = json!({ "type": "Point", "coordinates": [1.5, 5.0] });
+    let error = drape::drape(&mut data, &input, &DrapeOptions::default()).unwrap_err();
+    assert!(error.to_string().contains("--crs bng"));
+}
+
+#[test]
+fn drapes_wgs84_polygons() {
+    let data_file = common::build_test_file("drape_wgs84", &["SV80"], common::rising_eastwards);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    let position = |easting: f64, northing: f64| {
+        let (lat, lon) = projection::os_to_wgs84(easting, northing);
+        json!([lon, lat])
+    };
+    let ring = json!([
+        position(85_000.0, 5_000.0),
+        position(85_500.0, 5_000.0),
+        position(85_500.0, 5_500.0),
+        position(85_000.0, 5_000.0),
+    ]);
+    let input = json!({ "type": "Polygon", "coordinates": [ring] });
+
+    let options = DrapeOptions { crs: None, densify: true };
+    let (output, summary) = drape::drape(&mut data, &input, &options).unwrap();
+    assert_eq!(Crs::Wgs84, summary.crs);
+    let positions = output["coordinates"][0].as_array().unwrap();
+    assert_eq!(summary.positions, positions.len());
+    assert!(summary.added > 20);
+    assert_eq!(100.0, positions[0][2]);
+    // The second corner follows 9 positions added along the first side
+    assert_eq!(json!(110.0), positions[10][2]);
+
+    // Densified positions lie along the ring in longitude & latitude
+    let (lat, lon) = (positions[1][1].as_f64().unwrap(), positions[1][0].as_f64().unwrap());
+    let (easting, northing) = projection::wgs84_to_os(lat, lon);
+    assert!((easting - 85_050.0).abs() < 1.0 && (northing - 5_000.0).abs() < 1.0);
+    assert_eq!(101.0, positions[1][2]);
+}
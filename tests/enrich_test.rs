@@ -0,0 +1,73 @@
+mod common;
+
+use osterrain50::enrich::{self, Columns, EnrichOptions};
+use osterrain50::projection;
+use osterrain50::reader::DataFile;
+use std::io::Cursor;
+
+/************************************
+   Test adding elevations & slopes
+   to CSV rows, with bad rows
+   reported rather than panicking
+************************************/
+
+#[test]
+fn enriches_rows_and_reports_bad_ones() {
+    let data_file = common::build_test_file("enrich", &["SV80"], common::rising_eastwards);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    let input = "name,Grid Ref,notes\r\n\
+                 a,SV 85000 05000,\"quoted, with comma\"\r\n\
+                 b,SI 1234,\r\n\
+                 c,,\r\n\
+                 d,SV8200005000,\"two\nlines\"\r\n";
+    let mut output = Vec::new();
+    let mut reported = Vec::new();
+    let options = EnrichOptions {
+        columns: Some(Columns::GridRef("grid ref".to_string())),
+        slope: true,
+    };
+    let summary = enrich::enrich_csv(&mut data, Cursor::new(input), &mut output, &options, |row, reason| {
+        reported.push((row, reason.to_string()))
+    })
+    .unwrap();
+
+    assert_eq!((4, 2), (summary.rows, summary.failed));
+    assert_eq!(vec![2, 3], reported.iter().map(|(row, _)| *row).collect::<Vec<_>>());
+    assert!(reported[0].1.contains("2nd letter"));
+    // The slope of 1 in 50 is 1.1°
+    assert_eq!(
+        "name,Grid Ref,notes,elevation,slope\n\
+         a,SV 85000 05000,\"quoted, with comma\",100.0,1.1\n\
+         b,SI 1234,,,\n\
+         c,,,,\n\
+         d,SV8200005000,\"two\nlines\",40.0,1.1\n",
+        String::from_utf8(output).unwrap()
+    );
+}
+
+#[test]
+fn finds_lat_lon_columns() {
+    let data_file = common::build_test_file("enrich_lat_lon", &["SV80"], common::rising_eastwards);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    // SV 85000 05000, then out at sea in SV70, then in Paris, outside the grid
+    let (lat, lon) = projection::os_to_wgs84(85_000.0, 5_000.0);
+    let (sea_lat, sea_lon) = projection::os_to_wgs84(75_000.0, 5_000.0);
+    let input = format!("latitude,longitude\n{},{}\n{},{}\n48.85,2.35\n", lat, lon, sea_lat, sea_lon);
+    let mut output = Vec::new();
+    let mut reported = Vec::new();
+    let summary = enrich::enrich_csv(&mut data, Cursor::new(input), &mut output, &EnrichOptions::default(), |row, reason| {
+        reported.push((row, reason.to_string()))
+    })
+    .unwrap();
+    assert_eq!((3, 1), (summary.rows, summary.failed));
+    assert_eq!(3, reported[0].0);
+    assert!(reported[0].1.contains("outside the National Grid"));
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!("latitude,longitude,elevation", lines[0]);
+    assert!(lines[1].ends_with(",100.0"));
+    assert_eq!(format!("{},{},0.0", sea_lat, sea_lon), lines[2]);
+    assert_eq!("48.85,2.35,", lines[3]);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use osterrain50::geotiff::{self, GeoTiffOptions, SampleType};
+use osterrain50::raster::BoundingBox;
+use osterrain50::reader::DataFile;
+use std::{env, fs, process};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+
+/************************************
+   Test GeoTIFF export from a small
+   synthetic binary data file
+************************************/
+
+fn output_path(name: &str) -> std::path::PathBuf {
+    env::temp_dir().join(format!("osterrain50_{}_{}.tif", name, process::id()))
+}
+
+#[test]
+fn geotiff_strips_with_georeferencing() {
+    let data_file = common::build_test_file("geotiff_strips", &["SV80", "SV90"], common::distinct_cells);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let output_file = output_path("geotiff_strips");
+
+    let bbox = BoundingBox::new(89_000, 9_000, 91_000, 11_000).unwrap();
+    geotiff::write_geotiff(&mut data, &bbox, &output_file, &GeoTiffOptions::default()).unwrap();
+
+    let mut decoder = Decoder::new(fs::File::open(&output_file).unwrap()).unwrap();
+    assert_eq!((40, 40), decoder.dimensions().unwrap());
+    assert_eq!(
+        vec![0.0, 0.0, 0.0, 89_000.0, 11_000.0, 0.0],
+        decoder.get_tag_f64_vec(Tag::ModelTiepointTag).unwrap()
+    );
+    assert!(decoder.get_tag_u16_vec(Tag::GeoKeyDirectoryTag).unwrap().ends_with(&[3072, 0, 1, 27700]));
+    assert_eq!("-9999", decoder.get_tag_ascii_string(Tag::GdalNodata).unwrap());
+
+    if let DecodingResult::F32(values) = decoder.read_image().unwrap() {
+        // North west cell is in the sea area of SV81
+        assert_eq!(-9999.0, values[0]);
+        // South west cell is in SV80 and south east cell in SV90
+        assert_eq!(common::distinct_cells(89_000, 9_000), values[39 * 40]);
+        assert_eq!(common::distinct_cells(90_950, 9_000), values[39 * 40 + 39]);
+    } else {
+        panic!("Expected f32 samples");
+    }
+}
+
+#[test]
+fn geotiff_tiled_and_deflated() {
+    let data_file = common::build_test_file("geotiff_tiled", &["SV80"], common::distinct_cells);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let output_file = output_path("geotiff_tiled");
+
+    let options = GeoTiffOptions {
+        sample_type: SampleType::I16,
+        nodata: -32768.0,
+        tiled: true,
+        deflate: true,
+    };
+    let bbox = BoundingBox::for_region("SV").unwrap();
+    geotiff::write_geotiff(&mut data, &bbox, &output_file, &options).unwrap();
+
+    let mut decoder = Decoder::new(fs::File::open(&output_file).unwrap()).unwrap();
+    assert_eq!((2000, 2000), decoder.dimensions().unwrap());
+    assert_eq!(256, decoder.get_tag_u32(Tag::TileWidth).unwrap());
+
+    if let DecodingResult::I16(values) = decoder.read_image().unwrap() {
+        assert_eq!(2000 * 2000, values.len());
+        assert_eq!(-32768, values[0]);
+        // South west cell of SV80
+        let index = 1999 * 2000 + 80_000 / 50;
+        assert_eq!((common::distinct_cells(80_000, 0) * 10.0) as i16, values[index]);
+    } else {
+        panic!("Expected i16 samples");
+    }
+}
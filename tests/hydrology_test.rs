@@ -0,0 +1,35 @@
+mod common;
+
+use osterrain50::hydrology;
+use osterrain50::raster::{self, BoundingBox};
+use osterrain50::reader::DataFile;
+
+/************************************
+   Test stream extraction from a
+   synthetic valley crossing a
+   10km² data block boundary
+************************************/
+
+#[test]
+fn valley_stream_crosses_block_boundary() {
+    let data_file = common::build_test_file("hydrology", &["SV80", "SV81"], common::valley);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let bbox = BoundingBox::new(84_000, 8_000, 86_000, 12_000).unwrap();
+    let grid = raster::extract_x10(&mut data, &bbox, raster::NODATA_X10).unwrap();
+
+    let directions = hydrology::flow_directions(&hydrology::fill_depressions(&grid));
+    let accumulation = hydrology::flow_accumulation(&directions);
+    // Every cell drains to an outlet
+    let drained: u32 = (0..grid.values.len())
+        .filter(|&i| directions.values[i] == hydrology::OUTLET)
+        .map(|i| accumulation.values[i])
+        .sum();
+    assert_eq!(grid.values.len() as u32, drained);
+
+    // The valley floor is the main stream, running south off the region
+    let streams = hydrology::extract_streams(&directions, &accumulation, 400);
+    let main = streams.iter().max_by_key(|stream| stream.points.len()).unwrap();
+    assert!(main.points.iter().all(|&(easting, _)| easting == 85_025.0));
+    assert_eq!(Some(&(85_025.0, 8_025.0)), main.points.last());
+    assert!(main.points.first().unwrap().1 > 10_000.0);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use osterrain50::igc::{self, AltitudeSource};
+use osterrain50::projection;
+use osterrain50::reader::DataFile;
+
+/************************************
+   Test the heights above the
+   terrain of an IGC flight log
+************************************/
+
+// A B record over the middle of a 50m cell, with a pressure altitude 20m above the GPS altitude
+fn b_record(time: &str, easting: f64, gps_altitude: i32) -> String {
+    let (lat, lon) = projection::os_to_wgs84(easting, 5_025.0);
+    let angle = |degrees: f64, digits: usize| {
+        let thousandths = (degrees.abs() * 60_000.0).round() as i64;
+        format!("{:0digits$}{:05}", thousandths / 60_000, thousandths % 60_000, digits = digits)
+    };
+    format!(
+        "B{}{}{}{}{}A{:05}{:05}",
+        time,
+        angle(lat, 2),
+        if lat < 0.0 { 'S' } else { 'N' },
+        angle(lon, 3),
+        if lon < 0.0 { 'W' } else { 'E' },
+        gps_altitude + 20,
+        gps_altitude
+    )
+}
+
+#[test]
+fn finds_least_clearance_in_flight() {
+    let data_file = common::build_test_file("igc", &["SV80"], common::rising_eastwards);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    // Take off 2m above the 100m terrain, fly over 102m, 104m & 106m terrain and land by 108m terrain
+    let igc = [
+        "AXXX001".to_string(),
+        "HFDTE150725".to_string(),
+        b_record("120000", 85_025.0, 102),
+        b_record("120010", 85_125.0, 200),
+        b_record("120020", 85_225.0, 140),
+        b_record("120030", 85_325.0, 180),
+        b_record("120040", 85_425.0, 110),
+    ]
+    .join("\r\n");
+    let fixes = igc::parse_fixes(&igc).unwrap();
+    assert_eq!(5, fixes.len());
+
+    let analysis = igc::analyse(&mut data, &fixes, AltitudeSource::Gps, igc::DEFAULT_THRESHOLD).unwrap();
+    let agls: Vec<Option<f64>> = analysis.clearances.iter().map(|clearance| clearance.agl).collect();
+    assert_eq!(vec![Some(2.0), Some(98.0), Some(36.0), Some(74.0), Some(2.0)], agls);
+    assert_eq!(
+        vec![false, true, true, true, false],
+        analysis.clearances.iter().map(|clearance| clearance.in_flight).collect::<Vec<_>>()
+    );
+    assert_eq!((Some(2), vec![2]), (analysis.minimum, analysis.below));
+
+    // The pressure altitudes are 20m higher, so nothing is below the threshold
+    let analysis = igc::analyse(&mut data, &fixes, AltitudeSource::Pressure, igc::DEFAULT_THRESHOLD).unwrap();
+    assert_eq!(Some(56.0), analysis.clearances[2].agl);
+    assert!(analysis.below.is_empty());
+
+    // A flight which never gets above the threshold still has its minimum
+    let analysis = igc::analyse(&mut data, &fixes, AltitudeSource::Gps, 500.0).unwrap();
+    assert_eq!((Some(2), vec![1, 2, 3]), (analysis.minimum, analysis.below));
+}
+
+#[test]
+fn takes_the_whole_track_without_a_take_off() {
+    let data_file = common::build_test_file("igc_slow", &["SV80"], common::rising_eastwards);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    // Hanging in the wind over 100m terrain, then drifting slowly over SV90, which has no data
+    let igc = [b_record("120000", 85_025.0, 130), b_record("120100", 85_025.0, 120), b_record("140100", 95_025.0, 110)].join("\n");
+    let fixes = igc::parse_fixes(&igc).unwrap();
+    let analysis = igc::analyse(&mut data, &fixes, AltitudeSource::Gps, igc::DEFAULT_THRESHOLD).unwrap();
+    assert!(analysis.clearances.iter().all(|clearance| clearance.in_flight));
+    assert_eq!((None, None), (analysis.clearances[2].terrain, analysis.clearances[2].agl));
+    assert_eq!((Some(1), vec![0, 1]), (analysis.minimum, analysis.below));
+}
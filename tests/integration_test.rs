@@ -24,7 +24,7 @@ fn ben_nevis() {
     let published_elev = 1345.0;
     let coords = "NN 1669 7127";
     let diff = common::get_elev_diff(published_elev, coords, DATA_FILE);
-    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {}m", format!("{0:.1}", diff));
+    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {:.1}m", diff);
 }
 
 #[test]
@@ -33,7 +33,7 @@ fn cairn_gorm() {
     let published_elev = 1244.0;
     let coords = "300510, 804054";
     let diff = common::get_elev_diff(published_elev, coords, DATA_FILE);
-    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {}m", format!("{0:.1}", diff));
+    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {:.1}m", diff);
 }
 
 /************************************
@@ -47,7 +47,7 @@ fn mynydd_bodafon() {
     let published_elev = 173.8;
     let coords = "247244, 385418";
     let diff = common::get_elev_diff(published_elev, coords, DATA_FILE);
-    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {}m", format!("{0:.1}", diff));
+    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {:.1}m", diff);
 }
 
 #[test]
@@ -57,7 +57,7 @@ fn mount_snowdon() {
     let published_elev = 1056.8;
     let coords = "SH 6094 5434";
     let diff = common::get_elev_diff(published_elev, coords, DATA_FILE);
-    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {}m", format!("{0:.1}", diff));
+    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {:.1}m", diff);
 }
 
 #[test]
@@ -67,7 +67,7 @@ fn foel_cwmcerwyn() {
     let published_elev = 532.6;
     let coords = "209395, 231152";
     let diff = common::get_elev_diff(published_elev, coords, DATA_FILE);
-    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {}m", format!("{0:.1}", diff));
+    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {:.1}m", diff);
 }
 
 /************************************
@@ -81,7 +81,7 @@ fn beacon_hill() {
     let published_elev = 52.0;
     let coords = "573218, 341864";
     let diff = common::get_elev_diff(published_elev, coords, DATA_FILE);
-    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {}m", format!("{0:.1}", diff));
+    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {:.1}m", diff);
 }
 
 #[test]
@@ -91,7 +91,7 @@ fn parliament_hill() {
     let published_elev = 101.6;
     let coords = "528054, 186978";
     let diff = common::get_elev_diff(published_elev, coords, DATA_FILE);
-    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {}m", format!("{0:.1}", diff));
+    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {:.1}m", diff);
 }
 
 #[test]
@@ -101,7 +101,7 @@ fn ebrington_hill() {
     let published_elev = 259.9;
     let coords = "SP 1872 4258";
     let diff = common::get_elev_diff(published_elev, coords, DATA_FILE);
-    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {}m", format!("{0:.1}", diff));
+    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {:.1}m", diff);
 }
 
 #[test]
@@ -111,7 +111,7 @@ fn butser_hill() {
     let published_elev = 270.4;
     let coords = "SU 7166 2031";
     let diff = common::get_elev_diff(published_elev, coords, DATA_FILE);
-    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {}m", format!("{0:.1}", diff));
+    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {:.1}m", diff);
 }
 
 #[test]
@@ -121,7 +121,7 @@ fn skiddaw() {
     let published_elev = 927.9;
     let coords = "326041, 529086";
     let diff = common::get_elev_diff(published_elev, coords, DATA_FILE);
-    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {}m", format!("{0:.1}", diff));
+    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {:.1}m", diff);
 }
 
 #[test]
@@ -131,7 +131,7 @@ fn black_rock() {
     let published_elev = -1.6;
     let coords = "183381, 31669";
     let diff = common::get_elev_diff(published_elev, coords, DATA_FILE);
-    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {}m", format!("{0:.1}", diff));
+    assert!(diff <= MIN_ELEV_DIFF, "Elevation difference is {:.1}m", diff);
 }
 
 /************************************
@@ -0,0 +1,43 @@
+mod common;
+
+use osterrain50::inundation;
+use osterrain50::raster::{self, BoundingBox};
+use osterrain50::reader::DataFile;
+
+/************************************
+   Test sea level flooding of a
+   synthetic coast with a hollow
+   behind higher ground
+************************************/
+
+// Land rising 1m per 500m eastwards from the sea west of SV80,
+// with a hollow at 1m from 86km to 87km east
+fn synthetic_elevation(easting: i64, _northing: i64) -> f32 {
+    if (86_000..87_000).contains(&easting) {
+        return 1.0;
+    }
+    (easting - 80_000) as f32 / 500.0
+}
+
+#[test]
+fn flooding_spreads_from_the_sea() {
+    let data_file = common::build_test_file("inundation", &["SV80"], synthetic_elevation);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let bbox = BoundingBox::new(75_000, 0, 90_000, 10_000).unwrap();
+    let grid = raster::extract_x10(&mut data, &bbox, raster::NODATA_X10).unwrap();
+
+    let mask = inundation::inundate(&grid, 3.0);
+    let at = |easting: f64| {
+        let (col, row) = mask.cell_at(easting, 5_025.0).unwrap();
+        mask.get(col, row)
+    };
+    assert_eq!(inundation::SEA, at(75_025.0));
+    assert_eq!(inundation::SEA, at(80_025.0));
+    assert_eq!(inundation::FLOODED, at(81_525.0));
+    assert_eq!(inundation::DRY, at(81_575.0));
+    // The hollow is below the water level but cut off from the sea
+    assert_eq!(inundation::DRY, at(86_525.0));
+
+    // 30 columns of 200 cells flood, each 0.0025km²
+    assert_eq!(vec![("SV", 15.0)], inundation::flooded_area_by_grid(&mask));
+}
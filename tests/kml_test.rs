@@ -0,0 +1,110 @@
+mod common;
+
+use osterrain50::kml;
+use osterrain50::projection;
+use osterrain50::reader::DataFile;
+use std::io::{Read, Write};
+use std::{env, fs};
+
+/************************************
+   Test adding heights to KML & KMZ
+   Placemarks with profile summaries
+************************************/
+
+// A "lon,lat" KML position for the middle of a 50m cell
+fn position(easting: f64, northing: f64) -> String {
+    let (lat, lon) = projection::os_to_wgs84(easting, northing);
+    format!("{},{}", lon, lat)
+}
+
+fn route_kml() -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2"><Document>
+<Placemark><name>Walk</name><description>Morning walk</description><styleUrl>#red</styleUrl>
+<LineString><tessellate>1</tessellate><altitudeMode>clampToGround</altitudeMode><coordinates>{} {},0</coordinates></LineString></Placemark>
+<Placemark><name>Cairn</name><Point><coordinates>{}</coordinates></Point></Placemark>
+</Document></kml>"#,
+        position(85_025.0, 5_025.0),
+        position(85_225.0, 5_025.0),
+        position(82_025.0, 5_025.0)
+    )
+}
+
+#[test]
+fn adds_heights_and_profiles() {
+    let data_file = common::build_test_file("kml", &["SV80"], common::rising_eastwards);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    let input = route_kml();
+    let (output, placemarks) = kml::add_heights(&mut data, &input).unwrap();
+    assert_eq!(2, placemarks.len());
+    assert_eq!(("Walk", 2), (placemarks[0].name.as_str(), placemarks[0].positions));
+    let profile = placemarks[0].profile.unwrap();
+    assert_eq!((200.0, 4.0, 0.0, 100.0, 104.0), (profile.distance, profile.ascent, profile.descent, profile.min, profile.max));
+    assert_eq!(None, placemarks[1].profile);
+
+    assert!(output.contains(&format!("<coordinates>{},100.0 {},104.0</coordinates>", position(85_025.0, 5_025.0), position(85_225.0, 5_025.0))));
+    assert_eq!(2, output.matches("<altitudeMode>absolute</altitudeMode>").count());
+    assert!(!output.contains("clampToGround"));
+    assert!(output.contains(
+        "<description>Morning walk\nOS Terrain 50 profile: 0.20km, ascent 4m, descent 0m, lowest 100.0m, highest 104.0m</description><styleUrl>#red</styleUrl>"
+    ));
+    assert!(output.contains("<name>Cairn</name><description>OS Terrain 50 elevation: 40.0m</description><Point>"));
+
+    // Running again replaces rather than repeats the summaries
+    let (again, _) = kml::add_heights(&mut data, &output).unwrap();
+    assert_eq!(output, again);
+    assert_eq!(2, kml::placemarks(&again).unwrap().iter().map(|placemark| placemark.geometries.len()).sum::<usize>());
+
+    // Geometries reaching outside the grid are left as they are
+    let input = format!(
+        "<kml><Placemark><name>Trip</name><MultiGeometry><Point><coordinates>{}</coordinates></Point><LineString><coordinates>{} 2.35,48.85,35</coordinates></LineString></MultiGeometry></Placemark></kml>",
+        position(82_025.0, 5_025.0),
+        position(85_025.0, 5_025.0)
+    );
+    let (output, placemarks) = kml::add_heights(&mut data, &input).unwrap();
+    assert_eq!((1, 1), (placemarks[0].positions, placemarks[0].outside));
+    assert_eq!(None, placemarks[0].profile);
+    assert_eq!(1, output.matches("absolute").count());
+    assert!(output.contains(&format!("<LineString><coordinates>{} 2.35,48.85,35</coordinates>", position(85_025.0, 5_025.0))));
+}
+
+#[test]
+fn kmz_round_trip() {
+    let data_file = common::build_test_file("kmz", &["SV80"], common::rising_eastwards);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    // A KMZ with an image beside the KML
+    let input_file = env::temp_dir().join("osterrain50_kml_test_input.kmz");
+    let mut writer = zip::ZipWriter::new(fs::File::create(&input_file).unwrap());
+    writer.start_file("doc.kml", Default::default()).unwrap();
+    writer.write_all(route_kml().as_bytes()).unwrap();
+    writer.start_file("files/photo.jpg", Default::default()).unwrap();
+    writer.write_all(b"not really a photo").unwrap();
+    writer.finish().unwrap();
+
+    let (output, _) = kml::add_heights(&mut data, &kml::read_file(&input_file).unwrap()).unwrap();
+    let output_file = env::temp_dir().join("osterrain50_kml_test_output.kmz");
+    kml::write_file(&output_file, &output, &input_file).unwrap();
+
+    assert_eq!(output, kml::read_file(&output_file).unwrap());
+    let mut archive = zip::ZipArchive::new(fs::File::open(&output_file).unwrap()).unwrap();
+    assert_eq!("doc.kml", archive.by_index(0).unwrap().name());
+    assert_eq!(2, archive.len());
+    let mut photo = String::new();
+    archive.by_name("files/photo.jpg").unwrap().read_to_string(&mut photo).unwrap();
+    assert_eq!("not really a photo", photo);
+}
+
+#[test]
+fn kmz_without_doc_kml_uses_the_first_kml() {
+    let input_file = env::temp_dir().join("osterrain50_kml_test_first.kmz");
+    let mut writer = zip::ZipWriter::new(fs::File::create(&input_file).unwrap());
+    for (name, content) in [("files/photo.jpg", "not really a photo"), ("z.kml", "<kml>z</kml>"), ("a.kml", "<kml>a</kml>")] {
+        writer.start_file(name, Default::default()).unwrap();
+        writer.write_all(content.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap();
+    assert_eq!("<kml>z</kml>", kml::read_file(&input_file).unwrap());
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use osterrain50::coords::OSCoords;
+use osterrain50::line_of_sight::{self, SightOptions};
+use osterrain50::reader::DataFile;
+
+/************************************
+   Test the line of sight over a
+   synthetic ridge
+************************************/
+
+fn location(easting: i64, northing: i64) -> OSCoords {
+    OSCoords { easting, northing, elevation: None }
+}
+
+#[test]
+fn ridge_hides_target() {
+    let data_file = common::build_test_file("line_of_sight", &["SV80"], common::ridge);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let (observer, target) = (location(82_000, 5_000), location(88_000, 5_000));
+
+    let result = line_of_sight::line_of_sight(&mut data, observer, target, &SightOptions::default()).unwrap();
+    assert!(!result.visible);
+    assert_eq!(6_000.0, result.distance);
+    let obstruction = result.first_obstruction.unwrap();
+    assert_eq!((84_950, 5_000), (obstruction.point.easting, obstruction.point.northing));
+    assert!((obstruction.height_above_sight_line - 49.14).abs() < 0.01);
+
+    // Both ends raised well above the ridge can see each other
+    let towers = SightOptions {
+        observer_height: 60.0,
+        target_height: 60.0,
+        ..SightOptions::default()
+    };
+    assert!(line_of_sight::line_of_sight(&mut data, observer, target, &towers).unwrap().visible);
+
+    // Until the curve of the earth gets in the way
+    let curved = SightOptions {
+        observer_height: 50.2,
+        target_height: 50.2,
+        earth_curvature: true,
+        refraction: 0.0,
+    };
+    let result = line_of_sight::line_of_sight(&mut data, observer, target, &curved).unwrap();
+    assert!(!result.visible);
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::OSCoords;
+use osterrain50::mapped::MappedDataFile;
+use osterrain50::reader::DataFile;
+
+/************************************
+   Test the memory-mapped reader
+   gives the same elevations as the
+   seek & read reader
+************************************/
+
+#[test]
+fn mapped_reader_matches_data_file() {
+    let data_file = common::build_test_file("mapped", &["SV80", "SV81"], common::rising_east_and_north);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let mapped = MappedDataFile::open(&data_file).unwrap();
+
+    // Land in both blocks, the sea beside them and outside the grid
+    for (easting, northing) in [(80_000, 0), (89_999, 9_999), (85_123, 14_567), (79_999, 5_000), (85_000, 25_000), (-1, 5_000)] {
+        assert_eq!(
+            data.read_elevation_x10(easting, northing).unwrap(),
+            mapped.read_elevation_x10(easting, northing).unwrap(),
+            "at {},{}",
+            easting,
+            northing
+        );
+    }
+    let address = mapped.data_block_address(85_000, 15_000).unwrap().unwrap();
+    assert_eq!(data.read_data_block(address).unwrap(), mapped.read_data_block(address).unwrap());
+
+    // An infilled profile from the sea across both blocks
+    let coords = |easting, northing| OSCoords {
+        easting,
+        northing,
+        elevation: None,
+    };
+    let route = [coords(75_000, 2_000), coords(88_000, 18_000), coords(81_000, 19_000)];
+    let expected = data.read_elevations(&route, true).unwrap();
+    let found = mapped.read_elevations(&route, true).unwrap();
+    assert_eq!(expected.len(), found.len());
+    assert!(expected.iter().zip(&found).all(|(a, b)| (a.easting, a.northing, a.elevation) == (b.easting, b.northing, b.elevation)));
+}
+
+#[test]
+fn rejects_other_files() {
+    let path = std::env::temp_dir().join(format!("osterrain50_mapped_{}.txt", std::process::id()));
+    std::fs::write(&path, "not a data file").unwrap();
+    assert!(MappedDataFile::open(path.to_str().unwrap()).is_err());
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn shared_between_threads() {
+    let data_file = common::build_test_file("mapped_threads", &["SV80"], common::rising_east_and_north);
+    let mapped = std::sync::Arc::new(MappedDataFile::open(&data_file).unwrap());
+
+    // Each thread reads its own column of cells from the one shared reader
+    let handles: Vec<_> = (0..8)
+        .map(|thread| {
+            let mapped = std::sync::Arc::clone(&mapped);
+            std::thread::spawn(move || {
+                let easting = 80_000 + thread * 1_000;
+                (0..10_000).step_by(50).all(|northing| {
+                    let expected = (common::rising_east_and_north(easting, northing) * 10.0).round() as i16;
+                    mapped.read_elevation_x10(easting, northing).unwrap() == Some(expected)
+                })
+            })
+        })
+        .collect();
+    assert!(handles.into_iter().all(|handle| handle.join().unwrap()));
+}
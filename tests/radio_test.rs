@@ -0,0 +1,59 @@
+mod common;
+
+use osterrain50::coords::OSCoords;
+use osterrain50::radio::{self, RadioOptions};
+use osterrain50::reader::DataFile;
+
+/************************************
+   Test a radio path over a
+   synthetic ridge
+************************************/
+
+// Flat ground at 10m with a 30m ridge running N to S at easting 85000
+fn synthetic_elevation(easting: i64, _northing: i64) -> f32 {
+    if (84_950..85_050).contains(&easting) {
+        30.0
+    } else {
+        10.0
+    }
+}
+
+fn location(easting: i64, northing: i64) -> OSCoords {
+    OSCoords { easting, northing, elevation: None }
+}
+
+#[test]
+fn ridge_obstructs_fresnel_zone() {
+    let data_file = common::build_test_file("radio", &["SV80"], synthetic_elevation);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let (tx, rx) = (location(82_000, 5_000), location(88_000, 5_000));
+
+    // Antennas 25m up clear the 20m high ridge, but not by 60% of the Fresnel zone
+    let options = RadioOptions {
+        frequency_mhz: 868.0,
+        tx_height: 25.0,
+        rx_height: 25.0,
+        k_factor: 4.0 / 3.0,
+    };
+    let path = radio::radio_path(&mut data, tx, rx, &options).unwrap();
+    assert_eq!(121, path.points.len());
+    assert!(path.line_of_sight);
+    assert!(!path.fresnel_clear);
+    let worst = path.worst.unwrap();
+    // The ridge cell nearer the middle of the path has the most earth bulge
+    assert_eq!(85_000, worst.ground.easting);
+    assert!(worst.clearance > 0.0 && worst.clearance < 5.0, "Clearance {}", worst.clearance);
+    assert!(path.diffraction_loss > 0.0 && path.diffraction_loss < 6.0);
+
+    // Lower antennas are blocked and lose more to diffraction
+    let low = RadioOptions { tx_height: 5.0, rx_height: 5.0, ..options };
+    let blocked = radio::radio_path(&mut data, tx, rx, &low).unwrap();
+    assert!(!blocked.line_of_sight);
+    assert!(blocked.diffraction_loss > 6.0);
+
+    // Raising them well above the ridge clears the zone
+    let high = RadioOptions { tx_height: 60.0, rx_height: 60.0, ..options };
+    let clear = radio::radio_path(&mut data, tx, rx, &high).unwrap();
+    assert!(clear.fresnel_clear);
+    assert!(clear.diffraction_loss == 0.0);
+}
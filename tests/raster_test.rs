@@ -0,0 +1,67 @@
+mod common;
+
+use osterrain50::raster::{self, BoundingBox};
+use osterrain50::reader::DataFile;
+
+/************************************
+   Test dense raster extraction from
+   a small synthetic binary data file
+************************************/
+
+#[test]
+fn raster_stitched_across_data_blocks() {
+    let data_file = common::build_test_file("raster_stitched", &["SV80", "SV90"], common::distinct_cells);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    // Straddles the boundary between SV80 and SV90 at easting 90,000
+    let bbox = BoundingBox::new(89_900, 5_000, 90_150, 5_100).unwrap();
+    let grid = raster::extract_x10(&mut data, &bbox, raster::NODATA_X10).unwrap();
+    assert_eq!((grid.cols, grid.rows), (5, 2));
+
+    for row in 0..grid.rows {
+        for col in 0..grid.cols {
+            // Rows run N to S
+            let easting = grid.min_easting + col as i64 * 50;
+            let northing = grid.max_northing() - (row as i64 + 1) * 50;
+            let expected = (common::distinct_cells(easting, northing) * 10.0) as i16;
+            assert_eq!(expected, grid.get(col, row), "Cell {}, {}", easting, northing);
+        }
+    }
+}
+
+#[test]
+fn raster_snaps_to_50m_cells() {
+    let data_file = common::build_test_file("raster_snaps", &["SV80"], common::distinct_cells);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    let bbox = BoundingBox::new(80_010, 1_020, 80_110, 1_051).unwrap();
+    let grid = raster::extract_metres(&mut data, &bbox, raster::NODATA_METRES).unwrap();
+    assert_eq!((grid.min_easting, grid.min_northing), (80_000, 1_000));
+    assert_eq!((grid.cols, grid.rows), (3, 2));
+    assert_eq!(common::distinct_cells(80_000, 1_050), grid.get(0, 0));
+}
+
+#[test]
+fn raster_fills_missing_blocks_with_nodata() {
+    let data_file = common::build_test_file("raster_nodata", &["SV80"], common::distinct_cells);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    // Half in SV80, half in the sea area of SV81
+    let bbox = BoundingBox::new(85_000, 9_900, 85_050, 10_100).unwrap();
+    let grid = raster::extract_metres(&mut data, &bbox, -1.0).unwrap();
+    assert_eq!(vec![-1.0, -1.0, common::distinct_cells(85_000, 9_950), common::distinct_cells(85_000, 9_900)], grid.values);
+}
+
+#[test]
+fn boxes_must_fit_the_grid() {
+    // Partly outside the grid is fine, as for regions around points near its edges
+    let bbox = BoundingBox::parse("-100,-100,100,100").unwrap();
+    assert_eq!((4, 4), bbox.size_in_cells());
+    assert!(BoundingBox::for_region("GB").is_ok());
+
+    let error = BoundingBox::parse("-5000,-5000,-1000,-1000").unwrap_err();
+    assert!(error.to_string().contains("outside the National Grid"));
+    let error = BoundingBox::parse("0,0,100000000,100000000").unwrap_err();
+    assert!(error.to_string().contains("more cells than the National Grid"));
+    assert!(BoundingBox::new(i64::MIN, i64::MIN, i64::MAX, i64::MAX).is_err());
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use osterrain50::raster::BoundingBox;
+use osterrain50::reader::DataFile;
+use osterrain50::render::{self, RenderOptions};
+use std::{env, fs, process};
+
+/************************************
+   Test PNG rendering from a small
+   synthetic binary data file
+************************************/
+
+fn synthetic_elevation(easting: i64, northing: i64) -> f32 {
+    ((easting % 10_000) / 50 + (northing % 10_000) / 50) as f32
+}
+
+#[test]
+fn render_tinted_png_with_sea() {
+    let data_file = common::build_test_file("render_png", &["SV80"], synthetic_elevation);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let output_file = env::temp_dir().join(format!("osterrain50_render_{}.png", process::id()));
+
+    // The northern half is in the sea area of SV81
+    let options = RenderOptions {
+        tint: true,
+        sea_colour: [0, 0, 255],
+        step: 2,
+        ..RenderOptions::default()
+    };
+    let bbox = BoundingBox::new(80_000, 8_000, 84_000, 12_000).unwrap();
+    assert_eq!((40, 40), render::write_png(&mut data, &bbox, &output_file, &options).unwrap());
+
+    let decoder = png::Decoder::new(fs::File::open(&output_file).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+    assert_eq!((40, 40), (reader.info().width, reader.info().height));
+
+    // North west pixel is sea, south west pixel is land
+    assert_eq!([0, 0, 255], pixels[0..3]);
+    assert_ne!([0, 0, 255], pixels[39 * 40 * 3..39 * 40 * 3 + 3]);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use osterrain50::server::Server;
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+
+/************************************
+   Test the HTTP elevation service
+   over a real socket
+************************************/
+
+// Sends a request and gets the status code and JSON response
+fn request(address: SocketAddr, method: &str, target: &str, body: &str) -> (u16, Value) {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        target,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response[9..12].parse().unwrap();
+    let (_, json) = response.split_once("\r\n\r\n").unwrap();
+    (status, serde_json::from_str(json).unwrap())
+}
+
+#[test]
+fn answers_elevation_queries() {
+    let data_file = common::build_test_file("server", &["SV80"], common::rising_eastwards);
+    let server = Server::bind(&data_file, "127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    thread::spawn(move || {
+        let _ = server.run();
+    });
+
+    let (status, point) = request(address, "GET", "/elevation?ref=SV+85000+05000", "");
+    assert_eq!(200, status);
+    assert_eq!((85_000, 100.0), (point["easting"].as_i64().unwrap(), point["elevation"].as_f64().unwrap()));
+    let (_, point) = request(address, "GET", "/elevation?e=81000&n=5000", "");
+    assert_eq!(20.0, point["elevation"].as_f64().unwrap());
+    // Near SV 85000 05000 (49.96°N 6.30°W), and the sea beyond SV80
+    let (_, point) = request(address, "GET", "/elevation?lat=49.96&lon=-6.3", "");
+    assert!(point["easting"].as_i64().unwrap() > 80_000);
+    let (_, point) = request(address, "GET", "/elevation?e=75000&n=5000", "");
+    assert_eq!(0.0, point["elevation"].as_f64().unwrap());
+    // Outside the grid
+    assert_eq!(400, request(address, "GET", "/elevation?e=-5000&n=5000", "").0);
+    assert_eq!(400, request(address, "GET", "/elevation?e=1e300&n=5000", "").0);
+
+    let body = r#"{"locations": ["SV 82000 05000", {"easting": 84000, "northing": 5000}]}"#;
+    let (status, batch) = request(address, "POST", "/elevations", body);
+    assert_eq!(200, status);
+    let elevations: Vec<f64> = batch["results"].as_array().unwrap().iter().map(|r| r["elevation"].as_f64().unwrap()).collect();
+    assert_eq!(vec![40.0, 80.0], elevations);
+
+    let (status, profile) = request(address, "POST", "/profile", body);
+    assert_eq!(200, status);
+    assert_eq!(2_000.0, profile["distance"].as_f64().unwrap());
+    assert_eq!(41, profile["points"].as_array().unwrap().len());
+
+    // Profiles are limited in length, here to about 5,000km
+    let (status, error) = request(address, "POST", "/profile", r#"{"locations": ["0,0", "0,1200000", "0,0", "0,1200000", "0,0", "0,1200000"]}"#);
+    assert_eq!(400, status);
+    assert!(error["error"].as_str().unwrap().contains("points"));
+    let (status, _) = request(address, "POST", "/profile", r#"{"locations": ["0,0", "0,99999999999"]}"#);
+    assert_eq!(400, status);
+
+    let (status, error) = request(address, "GET", "/elevation?ref=SI1234", "");
+    assert_eq!(400, status);
+    assert!(error["error"].as_str().unwrap().contains("SI1234"));
+    assert_eq!(404, request(address, "GET", "/nowhere", "").0);
+    assert_eq!(405, request(address, "GET", "/profile", "").0);
+}
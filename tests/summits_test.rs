@@ -0,0 +1,48 @@
+mod common;
+
+use osterrain50::raster::{self, BoundingBox};
+use osterrain50::reader::DataFile;
+use osterrain50::summits;
+
+/************************************
+   Test summit detection across a
+   grid boundary using two synthetic
+   hills joined by a saddle
+************************************/
+
+// Cones 400m high in SV90 and 300m high in SW00 on a 100m plateau,
+// joined by a ridge which is 150m high along the line between them
+fn synthetic_elevation(easting: i64, northing: i64) -> f32 {
+    // Distances are from the cell centres
+    let dn = (northing + 25 - 5_025) as f32;
+    let cone = |peak_e: i64, height: f32| {
+        let de = (easting + 25 - peak_e) as f32;
+        height - (de * de + dn * dn).sqrt() / 10.0
+    };
+    let ridge = 150.0 - dn.abs() / 10.0;
+    let elevation = cone(95_025, 400.0).max(cone(105_025, 300.0)).max(ridge).max(100.0);
+    (elevation * 10.0).round() / 10.0
+}
+
+#[test]
+fn saddle_is_key_col_of_lower_hill() {
+    let data_file = common::build_test_file("summits", &["SV90", "SW00"], synthetic_elevation);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let bbox = BoundingBox::new(90_000, 0, 110_000, 10_000).unwrap();
+    let grid = raster::extract_x10(&mut data, &bbox, raster::NODATA_X10).unwrap();
+
+    let summits = summits::find_summits(&grid, 100.0);
+    assert_eq!(2, summits.len(), "{:?}", summits);
+
+    // The higher hill is the highest point of the region
+    assert_eq!((95_025.0, 5_025.0), (summits[0].easting, summits[0].northing));
+    assert_eq!(None, summits[0].key_col);
+
+    // The lower hill's key col is on the saddle between the two
+    assert_eq!((105_025.0, 5_025.0), (summits[1].easting, summits[1].northing));
+    let (col_easting, col_northing, col_elevation) = summits[1].key_col.unwrap();
+    assert!(col_easting > 95_000.0 && col_easting < 105_000.0);
+    assert_eq!(5_025.0, col_northing);
+    assert!((summits[1].prominence - (summits[1].elevation - col_elevation)).abs() < 1e-9);
+    assert_eq!(150.0, summits[1].prominence);
+}
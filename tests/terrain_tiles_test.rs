@@ -0,0 +1,82 @@
+mod common;
+
+use osterrain50::projection;
+use osterrain50::raster::BoundingBox;
+use osterrain50::reader::DataFile;
+use osterrain50::terrain_tiles::{self, TerrainEncoding, TileOptions};
+use std::{env, fs, process};
+
+/************************************
+   Test Terrain-RGB tile export from
+   a small synthetic binary data file
+************************************/
+
+// A smooth slope so that interpolated values are predictable
+fn synthetic_elevation(easting: i64, northing: i64) -> f32 {
+    ((easting - 80_000) / 50 + (northing / 50) * 2) as f32 / 10.0
+}
+
+#[test]
+fn terrain_rgb_directory_tiles_decode_to_elevations() {
+    let data_file = common::build_test_file("tiles_directory", &["SV80"], synthetic_elevation);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let output_dir = env::temp_dir().join(format!("osterrain50_tiles_directory_{}", process::id()));
+    let _ = fs::remove_dir_all(&output_dir);
+
+    let options = TileOptions {
+        encoding: TerrainEncoding::Mapbox,
+        min_zoom: 11,
+        max_zoom: 12,
+    };
+    let bbox = BoundingBox::new(80_000, 0, 90_000, 10_000).unwrap();
+    let tile_count = terrain_tiles::write_tiles(&mut data, &bbox, &output_dir, &options).unwrap();
+    assert!(tile_count > 0);
+
+    // Find the pixel for a location in the middle of SV80 and decode its elevation
+    let (easting, northing) = (85_000.0, 5_000.0);
+    let (lat, lon) = projection::os_to_wgs84(easting, northing);
+    let (x, y) = terrain_tiles::lon_lat_to_tile(lon, lat, 12);
+    let tile_file = output_dir.join(format!("12/{}/{}.png", x, y));
+    let decoder = png::Decoder::new(fs::File::open(tile_file).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+
+    let tiles = 4096.0;
+    let lat_rad = lat.to_radians();
+    let pixel_x = (((lon + 180.0) / 360.0 * tiles - x as f64) * 256.0) as usize;
+    let pixel_y = (((1.0 - (lat_rad.tan() + 1.0 / lat_rad.cos()).ln() / std::f64::consts::PI) / 2.0 * tiles
+        - y as f64)
+        * 256.0) as usize;
+    let rgb = &pixels[(pixel_y * 256 + pixel_x) * 3..][..3];
+    let elevation = -10_000.0 + (rgb[0] as f64 * 65_536.0 + rgb[1] as f64 * 256.0 + rgb[2] as f64) * 0.1;
+    let expected = synthetic_elevation(easting as i64, northing as i64) as f64;
+    assert!((elevation - expected).abs() < 1.0, "Decoded {} expected {}", elevation, expected);
+}
+
+#[test]
+fn terrarium_mbtiles() {
+    let data_file = common::build_test_file("tiles_mbtiles", &["SV80"], synthetic_elevation);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let output_file = env::temp_dir().join(format!("osterrain50_tiles_{}.mbtiles", process::id()));
+
+    let options = TileOptions {
+        encoding: TerrainEncoding::Terrarium,
+        min_zoom: 6,
+        max_zoom: 10,
+    };
+    let bbox = BoundingBox::for_region("SV").unwrap();
+    let tile_count = terrain_tiles::write_tiles(&mut data, &bbox, &output_file, &options).unwrap();
+
+    let connection = rusqlite::Connection::open(&output_file).unwrap();
+    let stored: usize = connection.query_row("SELECT COUNT(*) FROM tiles", [], |row| row.get(0)).unwrap();
+    assert_eq!(tile_count, stored);
+    let zooms: usize = connection
+        .query_row("SELECT COUNT(DISTINCT zoom_level) FROM tiles", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(5, zooms);
+    let encoding: String = connection
+        .query_row("SELECT value FROM metadata WHERE name = 'encoding'", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!("terrarium", encoding);
+}
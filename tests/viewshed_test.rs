@@ -0,0 +1,66 @@
+mod common;
+
+use osterrain50::coords::OSCoords;
+use osterrain50::geotiff;
+use osterrain50::line_of_sight::{self, SightOptions};
+use osterrain50::reader::DataFile;
+use osterrain50::viewshed::{self, ViewshedOptions, HIDDEN, OUTSIDE, VISIBLE};
+use std::{env, fs, process};
+use tiff::decoder::{Decoder, DecodingResult};
+
+/************************************
+   Test the viewshed behind a
+   synthetic ridge
+************************************/
+
+fn location(easting: i64, northing: i64) -> OSCoords {
+    OSCoords { easting, northing, elevation: None }
+}
+
+#[test]
+fn ridge_hides_ground_behind_it() {
+    let data_file = common::build_test_file("viewshed", &["SV80"], common::ridge);
+    let mut data = DataFile::open(&data_file).unwrap();
+    let observer = location(82_000, 5_000);
+    let result = viewshed::viewshed(&mut data, observer, &ViewshedOptions::default()).unwrap();
+    let huge = ViewshedOptions { radius: 1e9, ..ViewshedOptions::default() };
+    assert!(viewshed::viewshed(&mut data, observer, &huge).is_err());
+
+    let mask = &result.mask;
+    assert_eq!((201, 201), (mask.cols, mask.rows));
+    let at = |easting: i64, northing: i64| {
+        let (col, row) = mask.cell_at(easting as f64, northing as f64).unwrap();
+        mask.get(col, row)
+    };
+    assert_eq!(VISIBLE, at(84_000, 5_000));
+    assert_eq!(VISIBLE, at(84_950, 7_000));
+    assert_eq!(HIDDEN, at(86_000, 5_000));
+    assert_eq!(OUTSIDE, at(86_900, 9_900));
+
+    // The sweep agrees with separate lines of sight
+    let sight = SightOptions::default();
+    for target in [location(84_500, 8_000), location(86_000, 3_000), location(81_000, 2_000)] {
+        let expected = line_of_sight::line_of_sight(&mut data, observer, target, &sight).unwrap().visible;
+        assert_eq!(expected, at(target.easting, target.northing) == VISIBLE);
+    }
+
+    // The visible area is the part of the circle between the ridge and the sea
+    // west of SV80, which lies below the flat ground so is hidden from view
+    assert!((result.visible_area - 47.56).abs() < 0.01, "Area {}", result.visible_area);
+
+    // The polygons cover the same area as the visible cells
+    let geojson = viewshed::to_geojson(&result);
+    let features = geojson["features"].as_array().unwrap();
+    let polygon_area: f64 = features.iter().map(|f| f["properties"]["area_km2"].as_f64().unwrap()).sum();
+    assert!((polygon_area - result.visible_area).abs() < 0.01 * features.len() as f64);
+
+    // The GeoTIFF mask has the same values
+    let output_file = env::temp_dir().join(format!("osterrain50_viewshed_{}.tif", process::id()));
+    geotiff::write_geotiff_u8(mask, &output_file, true).unwrap();
+    let mut decoder = Decoder::new(fs::File::open(&output_file).unwrap()).unwrap();
+    assert_eq!((201, 201), decoder.dimensions().unwrap());
+    match decoder.read_image().unwrap() {
+        DecodingResult::U8(values) => assert_eq!(mask.values, values),
+        _ => panic!("Expected u8 samples"),
+    }
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use osterrain50::geojson;
+use osterrain50::reader::DataFile;
+use osterrain50::zonal;
+use serde_json::json;
+
+/************************************
+   Test zonal statistics for GeoJSON
+   polygons crossing a 10km² data
+   block boundary and the coast
+************************************/
+
+// Land rising 1m per 50m northwards through SV80 and SV81
+fn synthetic_elevation(_easting: i64, northing: i64) -> f32 {
+    (northing / 50) as f32
+}
+
+#[test]
+fn polygon_across_blocks_and_coast() {
+    let data_file = common::build_test_file("zonal", &["SV80", "SV81"], synthetic_elevation);
+    let mut data = DataFile::open(&data_file).unwrap();
+
+    // 1km square straddling the blocks, and a 1km square half out to sea west of SV80
+    let input = json!({
+        "type": "FeatureCollection",
+        "features": [
+            { "type": "Feature", "properties": { "name": "straddle" }, "geometry": { "type": "Polygon", "coordinates":
+                [[[85_000, 9_500], [86_000, 9_500], [86_000, 10_500], [85_000, 10_500], [85_000, 9_500]]] } },
+            { "type": "Feature", "properties": { "name": "coast" }, "geometry": { "type": "MultiPolygon", "coordinates":
+                [[[[79_500, 5_000], [80_500, 5_000], [80_500, 6_000], [79_500, 6_000], [79_500, 5_000]]]] } },
+        ]
+    });
+    let features = geojson::features(&input).unwrap();
+
+    let polygons = geojson::polygons(&features[0]["geometry"]).unwrap();
+    let stats = zonal::polygon_stats(&mut data, &polygons, 10.0).unwrap().unwrap();
+    assert_eq!((400, 190.0, 209.0, 199.5), (stats.count, stats.min, stats.max, stats.mean));
+    assert_eq!(vec![(190.0, 200), (200.0, 200)], stats.histogram);
+
+    // Only the land half of the coastal square counts
+    let polygons = geojson::polygons(&features[1]["geometry"]).unwrap();
+    let stats = zonal::polygon_stats(&mut data, &polygons, 10.0).unwrap().unwrap();
+    assert_eq!((200, 100.0, 119.0), (stats.count, stats.min, stats.max));
+}